@@ -0,0 +1,213 @@
+use crate::ast::{binding_power, AssertStatement, Expression, POSTFIX};
+use crate::environment::Env;
+use crate::evaluator::{
+    apply_infix, apply_prefix, call_function, eval_expression, eval_field_expression,
+    eval_index_expression, eval_method_lookup, is_truthy,
+};
+use crate::object::{Object, ObjectType};
+use crate::token::Span;
+
+// evaluates an `assert`, a failed one shows the condition with the value of each of its parts under it:
+//
+//   assert len(xs) == n + 1
+//          |   |   |  | |
+//          3   |   |  4 5
+//              |   false
+//              [1, 2, 3]
+pub(crate) fn eval_assert(assert: &AssertStatement, env: &Env) -> ObjectType {
+    let line = "assert ";
+    let mut trace = Trace { env, marks: vec![] };
+    let (text, value) = match trace.eval(&assert.condition, line.len()) {
+        Ok(traced) => traced,
+        Err(unwinding) => return unwinding,
+    };
+    debug_assert_eq!(text, assert.condition.to_string());
+    if is_truthy(&value) {
+        return ObjectType::Null;
+    }
+
+    let mut message = "Assertion failed".to_string();
+    if let Some(extra) = &assert.message {
+        match eval_expression(extra, env) {
+            value if value.is_unwinding() => return value,
+            value => message = format!("{}: {}", message, value.inspect()),
+        }
+    }
+    let diagram = render(&format!("{}{}", line, text), trace.marks);
+    ObjectType::error("AssertionError", format!("{}\n\n{}", message, diagram))
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+// evaluates the condition while printing it back, remembering the column each value belongs under
+struct Trace<'a> {
+    env: &'a Env,
+    marks: Vec<(usize, String)>,
+}
+
+type Traced = Result<(String, ObjectType), ObjectType>;
+
+impl Trace<'_> {
+    // `expr` printed at column `at`, an unwinding value is handed back as the error
+    fn eval(&mut self, expr: &Expression, at: usize) -> Traced {
+        let (text, value, column) = match expr {
+            // the fallback of `??` may not run, so the whole of it is one value
+            Expression::INFEX {
+                left,
+                operator,
+                right,
+                ..
+            } if operator != "??" => {
+                let power = binding_power(expr);
+                let (left_text, left) = self.operand(left, power, at)?;
+                let column = at + width(&left_text) + 1;
+                let (right_text, right) = self.operand(right, power + 1, column + width(operator) + 1)?;
+                let value = apply_infix(operator, left, right, self.env);
+                (format!("{} {} {}", left_text, operator, right_text), value, Some(column))
+            }
+            Expression::PREFIX { operator, right } => {
+                let (right_text, right) = self.operand(right, binding_power(expr), at + width(operator))?;
+                (format!("{}{}", operator, right_text), apply_prefix(operator, right), Some(at))
+            }
+            Expression::CALL {
+                function,
+                arguments,
+                named,
+                span,
+            } if named.is_empty() && traceable(function, arguments) => self.call(function, arguments, *span, at)?,
+            Expression::INDEX { left, index } if !matches!(**left, Expression::NULLSAFE(_)) => {
+                let (left_text, left) = self.operand(left, POSTFIX, at)?;
+                let column = at + width(&left_text);
+                let (index_text, index) = self.eval(index, column + 1)?;
+                (format!("{}[{}]", left_text, index_text), eval_index_expression(left, index), Some(column))
+            }
+            Expression::FIELD { object, field } if !matches!(**object, Expression::NULLSAFE(_)) => {
+                let (object_text, object) = self.operand(object, POSTFIX, at)?;
+                let column = at + width(&object_text) + 1;
+                (format!("{}.{}", object_text, field), eval_field_expression(object, field), Some(column))
+            }
+            // a literal already shows its value
+            Expression::INT(_)
+            | Expression::STRING(_)
+            | Expression::ATOM(_)
+            | Expression::BOOLEAN(_)
+            | Expression::NULL => {
+                (expr.to_string(), eval_expression(expr, self.env), None)
+            }
+            expr => (expr.to_string(), eval_expression(expr, self.env), Some(at)),
+        };
+        if value.is_unwinding() {
+            return Err(value);
+        }
+        if let Some(column) = column {
+            self.marks.push((column, value.inspect()));
+        }
+        Ok((text, value))
+    }
+
+    // like `eval`, with parentheses when `expr` binds looser than `power`
+    fn operand(&mut self, expr: &Expression, power: u8, at: usize) -> Traced {
+        if binding_power(expr) >= power {
+            return self.eval(expr, at);
+        }
+        let (text, value) = self.eval(expr, at + 1)?;
+        Ok((format!("({})", text), value))
+    }
+
+    // the call's value goes under the function's name, the function itself is not shown
+    fn call(
+        &mut self,
+        function: &Expression,
+        arguments: &[Expression],
+        span: Span,
+        at: usize,
+    ) -> Result<(String, ObjectType, Option<usize>), ObjectType> {
+        let (callee, function, receiver, column) = match function {
+            Expression::FIELD { object, field } => {
+                let (object_text, object) = self.operand(object, POSTFIX, at)?;
+                let (function, receiver) = eval_method_lookup(object, field)?;
+                let column = at + width(&object_text) + 1;
+                (format!("{}.{}", object_text, field), function, receiver, column)
+            }
+            function => {
+                let text = if binding_power(function) < POSTFIX {
+                    format!("({})", function)
+                } else {
+                    function.to_string()
+                };
+                match eval_expression(function, self.env) {
+                    value if value.is_unwinding() => return Err(value),
+                    value => (text, value, None, at),
+                }
+            }
+        };
+
+        let mut next = at + width(&callee) + 1;
+        let mut texts = vec![];
+        let mut values: Vec<ObjectType> = receiver.into_iter().collect();
+        for argument in arguments {
+            let (text, value) = self.eval(argument, next)?;
+            next += width(&text) + 2;
+            texts.push(text);
+            values.push(value);
+        }
+        let value = call_function(function, values, vec![], Some(span));
+        Ok((format!("{}({})", callee, texts.join(", ")), value, Some(column)))
+    }
+}
+
+// calls whose parts can be evaluated one by one, the rest are shown as a single value
+fn traceable(function: &Expression, arguments: &[Expression]) -> bool {
+    let callee = match function {
+        Expression::NULLSAFE(_) => false,
+        Expression::FIELD { object, .. } => !matches!(**object, Expression::NULLSAFE(_)),
+        Expression::IDENT(name) => name != "quote" && name != "unquote",
+        _ => true,
+    };
+    callee
+        && !arguments
+            .iter()
+            .any(|arg| matches!(arg, Expression::SPREAD(_) | Expression::PLACEHOLDER))
+}
+
+// hangs each value from a `|` under its column, the rightmost first so that the
+// values further left can share a row when they fit before it
+fn render(line: &str, mut marks: Vec<(usize, String)>) -> String {
+    fn put(row: &mut Vec<char>, column: usize, text: &str) {
+        if row.len() < column + width(text) {
+            row.resize(column + width(text), ' ');
+        }
+        for (i, ch) in text.chars().enumerate() {
+            row[column + i] = ch;
+        }
+    }
+
+    marks.sort_by_key(|(column, _)| std::cmp::Reverse(*column));
+    let mut rows: Vec<Vec<char>> = vec![vec![]];
+    for (column, _) in &marks {
+        put(&mut rows[0], *column, "|");
+    }
+    // where the leftmost thing on each row below the first starts
+    let mut leftmost: Vec<usize> = vec![];
+    for (column, value) in &marks {
+        let row = (0..)
+            .find(|&row| leftmost.get(row).is_none_or(|&left| column + width(value) < left))
+            .unwrap();
+        if row == leftmost.len() {
+            leftmost.push(*column);
+            rows.push(vec![]);
+        }
+        for above in 0..row {
+            put(&mut rows[above + 1], *column, "|");
+            leftmost[above] = *column;
+        }
+        put(&mut rows[row + 1], *column, value);
+        leftmost[row] = *column;
+    }
+
+    let mut lines = vec![line.to_string()];
+    lines.extend(rows.iter().map(|row| row.iter().collect::<String>().trim_end().to_string()));
+    lines.join("\n")
+}
@@ -1,41 +1,150 @@
-use std::vec;
+use std::fmt;
+
+use crate::token::Span;
 
 #[derive(Debug)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
-    Let(LetStatement),
+    Let(Box<LetStatement>),
     Return(ReturnStatement),
     Expression(ExpressionStatement),
+    Break,
+    Continue,
+    Struct(StructStatement),
+    Enum(EnumStatement),
+    Trait(TraitStatement),
+    Impl(ImplStatement),
+    // `pub` in front of a top-level declaration exports it from the module
+    Pub(Box<Statement>),
+    Import(ImportStatement),
+    Macro(MacroStatement),
+    Infix(InfixStatement),
+    // `defer expr;`, runs when the enclosing block is left
+    Defer(Expression),
+    Assert(AssertStatement),
+}
+
+// `assert len(xs) == n, "message"`, a failure shows the value of each part of the condition
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssertStatement {
+    pub condition: Expression,
+    pub message: Option<Expression>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct LetStatement {
-    pub name: String,
+    pub pattern: Pattern,
     pub value: Expression,
+    pub span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ReturnStatement {
     pub return_value: Expression,
+    pub span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ExpressionStatement {
     pub expression: Expression,
 }
 
+// `struct Point { x, y }`
+#[derive(Debug, PartialEq, Clone)]
+pub struct StructStatement {
+    pub name: String,
+    pub fields: Vec<String>,
+    // `invariant self.x >= 0` clauses after the fields, checked after every method call
+    pub invariants: Vec<Expression>,
+}
+
+// the `requires` and `ensures` clauses of a function, `result` is bound in the latter
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Contract {
+    pub requires: Vec<Expression>,
+    pub ensures: Vec<Expression>,
+}
+
+impl Contract {
+    fn rewrite(self, r: &mut dyn Rewriter) -> Contract {
+        Contract {
+            requires: self.requires.into_iter().map(|e| e.rewrite(r)).collect(),
+            ensures: self.ensures.into_iter().map(|e| e.rewrite(r)).collect(),
+        }
+    }
+}
+
+// `enum Shape { Circle(r), Rect(w, h), Empty }`
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnumStatement {
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+}
+
+// `fields` names the payload, it is empty for unit variants like `Empty`
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// `trait Show { fun show(self); fun print(self) { ... } }`,
+// `provided` holds the default methods as named FUNCTIONs
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraitStatement {
+    pub name: String,
+    pub required: Vec<String>,
+    pub provided: Vec<Expression>,
+}
+
+// `impl Point { fun len(self) { ... } }` or `impl Show for Point { ... }`,
+// every method is a named FUNCTION
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImplStatement {
+    pub trait_name: Option<String>,
+    pub name: String,
+    pub methods: Vec<Expression>,
+}
+
+// `import "utils.em" as utils;` binds the module, `from "math.em" import sqrt, pi;`
+// binds the listed names and leaves `alias` empty
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportStatement {
+    pub path: String,
+    pub alias: Option<String>,
+    pub names: Vec<String>,
+}
+
+// `macro unless(cond, body) { quote(...) }`, the parameters are bound to the
+// quoted argument expressions when a call to it is expanded
+#[derive(Debug, PartialEq, Clone)]
+pub struct MacroStatement {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Vec<Statement>,
+}
+
+// `infix 60 <+> (a, b) { ... }`, the function is named after the operator
+#[derive(Debug, PartialEq, Clone)]
+pub struct InfixStatement {
+    pub operator: String,
+    pub precedence: u8,
+    pub right: bool,
+    pub function: Box<Expression>,
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     INT(i64),
     INFEX {
         left: Box<Expression>,
         operator: String,
         right: Box<Expression>,
+        span: Span,
     },
     IDENT(String),
     BOOLEAN(bool),
@@ -48,14 +157,1017 @@ pub enum Expression {
         consequence: Vec<Statement>,
         alternative: Option<Vec<Statement>>
     },
+    // `if let Shape.Circle(r) = s { ... }`
+    IFLET {
+        pattern: Box<Pattern>,
+        value: Box<Expression>,
+        consequence: Vec<Statement>,
+        alternative: Option<Vec<Statement>>
+    },
+    // `name` is None for anonymous functions and `|x| ...` lambdas,
+    // `rest` collects extra arguments for a trailing `...name` parameter,
+    // `generator` is set when the body uses `yield`
     FUNCTION {
-        name: String,
-        parameters: Vec<String>,
+        name: Option<String>,
+        parameters: Vec<Pattern>,
+        rest: Option<String>,
         body: Vec<Statement>,
+        generator: bool,
+        // the `-> type` annotation
+        returns: Option<Type>,
+        contract: Contract,
+        // where the `fun`, `|` or `infix` starts
+        span: Span,
     },
+    // `named` holds the `name: value` arguments that follow the positional ones
     CALL {
         function: Box<Expression>,
         arguments: Vec<Expression>,
+        named: Vec<(String, Expression)>,
+        span: Span,
+    },
+    // `...xs` inside call arguments and array literals
+    SPREAD(Box<Expression>),
+    // `_` in call arguments, the call becomes a partially applied function
+    PLACEHOLDER,
+    // `(+)`, an infix operator used as a function value
+    OPERATOR(String),
+    // `value |> function`
+    PIPE {
+        value: Box<Expression>,
+        function: Box<Expression>,
+        span: Span,
+    },
+    STRING(String),
+    // `:ok`
+    ATOM(String),
+    // `"Hello, ${name}!"`, the text pieces are STRINGs
+    INTERPOLATION(Vec<Expression>),
+    ARRAY(Vec<Expression>),
+    // `(a, b)`, `(x,)` has a single element
+    TUPLE(Vec<Expression>),
+    MAP(Vec<(Expression, Expression)>),
+    // `set{1, 2, 3}`
+    SET(Vec<Expression>),
+    // `[x * x for x in xs if x > 1]`
+    LISTCOMP {
+        element: Box<Expression>,
+        clauses: Vec<Clause>,
+    },
+    // `{k: v for [k, v] in pairs}`
+    MAPCOMP {
+        key: Box<Expression>,
+        value: Box<Expression>,
+        clauses: Vec<Clause>,
+    },
+    INDEX {
+        left: Box<Expression>,
+        index: Box<Expression>,
     },
+    MATCH {
+        value: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    // `start..end step n`, either bound may be left out as in `arr[..2]`
+    RANGE {
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        inclusive: bool,
+        step: Option<Box<Expression>>,
+    },
+    FOR {
+        pattern: Box<Pattern>,
+        iterable: Box<Expression>,
+        body: Vec<Statement>,
+    },
+    // `Point { x: 1, y: 2 }`
+    STRUCT {
+        name: String,
+        fields: Vec<(String, Expression)>,
+    },
+    // `p.x`
+    FIELD {
+        object: Box<Expression>,
+        field: String,
+    },
+    // `x = value` or `p.x = value`
+    ASSIGN {
+        target: Box<Expression>,
+        value: Box<Expression>,
+    },
+    TRY(Box<TryExpression>),
+    // `value?`
+    PROPAGATE(Box<Expression>),
+    NULL,
+    // the object of a `?.` link, the chain stops here when it is null
+    NULLSAFE(Box<Expression>),
+    // a postfix chain containing `?.`, null when one of its links stopped it
+    OPTCHAIN(Box<Expression>),
+    THROW(Box<Expression>),
+    // `yield value` as a statement of a generator function
+    YIELD(Box<Expression>),
+}
+
+// the clauses of a comprehension run left to right, each `for` nested in the ones before it
+#[derive(Debug, PartialEq, Clone)]
+pub enum Clause {
+    FOR {
+        pattern: Box<Pattern>,
+        iterable: Expression,
+    },
+    IF(Expression),
+}
+
+// `try { } catch e { } finally { }`, at least one of the two handlers is present
+#[derive(Debug, PartialEq, Clone)]
+pub struct TryExpression {
+    pub body: Vec<Statement>,
+    pub catch_name: Option<String>,
+    pub catch_body: Option<Vec<Statement>>,
+    pub finally: Option<Vec<Statement>>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    // `_`
+    WILDCARD,
+    // ints, strings and booleans compared by value
+    LITERAL(Expression),
+    BINDING(String),
+    // `[first, ..rest, last]`, `rest` is `_` for a bare `..`
+    ARRAY {
+        before: Vec<Pattern>,
+        rest: Option<String>,
+        after: Vec<Pattern>,
+    },
+    // `{name, age: 18..=99}` matches string keys
+    MAP(Vec<(String, Pattern)>),
+    // `(q, r)` matches a tuple of exactly that many elements
+    TUPLE(Vec<Pattern>),
+    RANGE {
+        start: Expression,
+        end: Expression,
+        inclusive: bool,
+    },
+    // `1 | 2 | 3`
+    OR(Vec<Pattern>),
+    // `Shape.Circle(r)` or `Shape.Empty`
+    VARIANT {
+        enum_name: String,
+        variant: String,
+        fields: Vec<Pattern>,
+    },
+    // `y = 0` inside an array or map pattern, used when the element is missing
+    DEFAULT {
+        pattern: Box<Pattern>,
+        default: Expression,
+    },
+    // `x: int` in a `let` or parameter list, only `ember check` looks at the type
+    TYPED {
+        pattern: Box<Pattern>,
+        annotation: Type,
+    },
+}
+
+// a type annotation, `any` is what unannotated code gets
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Int,
+    String,
+    Bool,
+    Atom,
+    Null,
+    Any,
+    // any function, `fun`
+    Function,
+    Array(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    // `int?` also allows null
+    Optional(Box<Type>),
+    // a struct or enum
+    Named(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Atom => write!(f, "atom"),
+            Type::Null => write!(f, "null"),
+            Type::Any => write!(f, "any"),
+            Type::Function => write!(f, "fun"),
+            Type::Array(element) => write!(f, "array<{}>", element),
+            Type::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            Type::Optional(inner) => write!(f, "{}?", inner),
+            Type::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// prints a pattern back in source form, e.g. for function signatures
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::WILDCARD => write!(f, "_"),
+            Pattern::LITERAL(literal) => write!(f, "{}", literal_source(literal)),
+            Pattern::BINDING(name) => write!(f, "{}", name),
+            Pattern::ARRAY {
+                before,
+                rest,
+                after,
+            } => {
+                let mut parts: Vec<String> = before.iter().map(|p| p.to_string()).collect();
+                if let Some(rest) = rest {
+                    parts.push(if rest == "_" { "..".to_string() } else { format!("..{}", rest) });
+                }
+                parts.extend(after.iter().map(|p| p.to_string()));
+                write!(f, "[{}]", parts.join(", "))
+            }
+            Pattern::TUPLE(elements) if elements.len() == 1 => write!(f, "({},)", elements[0]),
+            Pattern::TUPLE(elements) => {
+                let parts: Vec<String> = elements.iter().map(|p| p.to_string()).collect();
+                write!(f, "({})", parts.join(", "))
+            }
+            Pattern::MAP(entries) => {
+                let parts: Vec<String> = entries
+                    .iter()
+                    .map(|(key, pattern)| match pattern {
+                        Pattern::BINDING(name) if name == key => key.clone(),
+                        _ => format!("{}: {}", key, pattern),
+                    })
+                    .collect();
+                write!(f, "{{{}}}", parts.join(", "))
+            }
+            Pattern::RANGE {
+                start,
+                end,
+                inclusive,
+            } => write!(
+                f,
+                "{}{}{}",
+                literal_source(start),
+                if *inclusive { "..=" } else { ".." },
+                literal_source(end)
+            ),
+            Pattern::OR(alternatives) => {
+                let parts: Vec<String> = alternatives.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", parts.join(" | "))
+            }
+            Pattern::VARIANT {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                if prelude_variant(variant) == Some(enum_name.as_str()) {
+                    write!(f, "{}", variant)?;
+                } else {
+                    write!(f, "{}.{}", enum_name, variant)?;
+                }
+                if !fields.is_empty() {
+                    let parts: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                    write!(f, "({})", parts.join(", "))?;
+                }
+                Ok(())
+            }
+            Pattern::DEFAULT { pattern, default } => {
+                write!(f, "{} = {}", pattern, literal_source(default))
+            }
+            Pattern::TYPED {
+                pattern,
+                annotation,
+            } => write!(f, "{}: {}", pattern, annotation),
+        }
+    }
+}
+
+fn literal_source(expr: &Expression) -> String {
+    match expr {
+        Expression::INT(val) => val.to_string(),
+        Expression::BOOLEAN(val) => val.to_string(),
+        Expression::STRING(val) => format!("{:?}", val),
+        Expression::NULL => "null".to_string(),
+        Expression::ATOM(name) => format!(":{}", name),
+        Expression::IDENT(name) => name.clone(),
+        _ => "...".to_string(),
+    }
+}
+
+// the built-in enum that `Ok`, `Err`, `Some` and `None` belong to
+pub fn prelude_variant(name: &str) -> Option<&'static str> {
+    match name {
+        "Ok" | "Err" => Some("Result"),
+        "Some" | "None" => Some("Option"),
+        _ => None,
+    }
+}
+
+// the binding power of calls, indexing, fields and anything else that needs no parentheses
+pub(crate) const POSTFIX: u8 = 13;
+
+// how tightly an expression binds when printed as an operand, mirroring the parser's precedences
+pub(crate) fn binding_power(expr: &Expression) -> u8 {
+    match expr {
+        Expression::ASSIGN { .. } => 1,
+        Expression::PIPE { .. } => 2,
+        Expression::INFEX { operator, .. } => match operator.as_str() {
+            "??" => 3,
+            "==" | "!=" => 4,
+            "<" | ">" | "<=" | ">=" | "in" => 5,
+            "|" => 6,
+            "^" => 7,
+            "&" => 8,
+            "+" | "-" => 10,
+            "*" | "/" | "%" => 11,
+            _ => 0,
+        },
+        Expression::RANGE { .. } => 9,
+        Expression::PREFIX { .. } => 12,
+        // blocks and keyword expressions read ambiguously as operands
+        Expression::IF { .. }
+        | Expression::IFLET { .. }
+        | Expression::FUNCTION { .. }
+        | Expression::MATCH { .. }
+        | Expression::FOR { .. }
+        | Expression::TRY(_)
+        | Expression::THROW(_)
+        | Expression::YIELD(_) => 0,
+        _ => POSTFIX,
+    }
+}
+
+// `expr` as an operand that must bind at least as tightly as `power`
+fn operand(expr: &Expression, power: u8) -> String {
+    if binding_power(expr) < power {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+fn block(statements: &[Statement]) -> String {
+    if statements.is_empty() {
+        return "{}".to_string();
+    }
+    let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+    format!("{{ {} }}", statements.join("; "))
+}
+
+fn list(expressions: &[Expression]) -> String {
+    expressions.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn clauses(clauses: &[Clause]) -> String {
+    clauses
+        .iter()
+        .map(|clause| match clause {
+            Clause::FOR { pattern, iterable } => format!(" for {} in {}", pattern, iterable),
+            Clause::IF(condition) => format!(" if {}", condition),
+        })
+        .collect()
+}
+
+fn function(
+    name: &Option<String>,
+    parameters: &[Pattern],
+    rest: &Option<String>,
+    returns: &Option<Type>,
+    contract: &Contract,
+    body: &[Statement],
+) -> String {
+    let mut params: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+    if let Some(rest) = rest {
+        params.push(format!("...{}", rest));
+    }
+    let returns = match returns {
+        Some(returns) => format!(" -> {}", returns),
+        None => String::new(),
+    };
+    let mut clauses = String::new();
+    for clause in &contract.requires {
+        clauses.push_str(&format!(" requires {}", clause));
+    }
+    for clause in &contract.ensures {
+        clauses.push_str(&format!(" ensures {}", clause));
+    }
+    format!(
+        "fun {}({}){}{} {}",
+        name.as_deref().unwrap_or(""),
+        params.join(", "),
+        returns,
+        clauses,
+        block(body)
+    )
+}
+
+// prints an expression back in source form, e.g. for quotes and failed assertions
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let power = binding_power(self);
+        match self {
+            Expression::INT(val) => write!(f, "{}", val),
+            Expression::BOOLEAN(val) => write!(f, "{}", val),
+            Expression::STRING(val) => write!(f, "{:?}", val),
+            Expression::NULL => write!(f, "null"),
+            Expression::ATOM(name) => write!(f, ":{}", name),
+            Expression::IDENT(name) => write!(f, "{}", name),
+            Expression::PLACEHOLDER => write!(f, "_"),
+            Expression::OPERATOR(operator) => write!(f, "({})", operator),
+            // the right operand needs parentheses at the same power, operators group to the left
+            Expression::INFEX {
+                left,
+                operator,
+                right,
+                ..
+            } => write!(f, "{} {} {}", operand(left, power), operator, operand(right, power + 1)),
+            Expression::PREFIX { operator, right } => write!(f, "{}{}", operator, operand(right, power)),
+            Expression::ASSIGN { target, value } => write!(f, "{} = {}", target, value),
+            Expression::PIPE {
+                value, function, ..
+            } => write!(f, "{} |> {}", operand(value, power), operand(function, power + 1)),
+            Expression::IF {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                write!(f, "if {} {}", condition, block(consequence))?;
+                match alternative {
+                    Some(alternative) => write!(f, " else {}", block(alternative)),
+                    None => Ok(()),
+                }
+            }
+            Expression::IFLET {
+                pattern,
+                value,
+                consequence,
+                alternative,
+            } => {
+                write!(f, "if let {} = {} {}", pattern, value, block(consequence))?;
+                match alternative {
+                    Some(alternative) => write!(f, " else {}", block(alternative)),
+                    None => Ok(()),
+                }
+            }
+            Expression::FUNCTION {
+                name,
+                parameters,
+                rest,
+                body,
+                returns,
+                contract,
+                ..
+            } => write!(f, "{}", function(name, parameters, rest, returns, contract, body)),
+            Expression::CALL {
+                function,
+                arguments,
+                named,
+                ..
+            } => {
+                let mut args = vec![list(arguments)];
+                args.extend(named.iter().map(|(name, value)| format!("{}: {}", name, value)));
+                args.retain(|arg| !arg.is_empty());
+                match &**function {
+                    Expression::NULLSAFE(function) => write!(f, "{}?.({})", operand(function, POSTFIX), args.join(", ")),
+                    function => write!(f, "{}({})", operand(function, POSTFIX), args.join(", ")),
+                }
+            }
+            Expression::SPREAD(value) => write!(f, "...{}", value),
+            Expression::INTERPOLATION(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        Expression::STRING(text) => {
+                            let escaped = format!("{:?}", text);
+                            write!(f, "{}", escaped[1..escaped.len() - 1].replace("${", "\\${"))?
+                        }
+                        part => write!(f, "${{{}}}", part)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expression::ARRAY(elements) => write!(f, "[{}]", list(elements)),
+            Expression::TUPLE(elements) if elements.len() == 1 => write!(f, "({},)", elements[0]),
+            Expression::TUPLE(elements) => write!(f, "({})", list(elements)),
+            Expression::SET(elements) => write!(f, "set{{{}}}", list(elements)),
+            Expression::MAP(pairs) => {
+                let pairs: Vec<String> = pairs.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Expression::LISTCOMP { element, clauses: c } => write!(f, "[{}{}]", element, clauses(c)),
+            Expression::MAPCOMP {
+                key,
+                value,
+                clauses: c,
+            } => write!(f, "{{{}: {}{}}}", key, value, clauses(c)),
+            Expression::INDEX { left, index } => match &**left {
+                Expression::NULLSAFE(left) => write!(f, "{}?.[{}]", operand(left, POSTFIX), index),
+                left => write!(f, "{}[{}]", operand(left, POSTFIX), index),
+            },
+            Expression::FIELD { object, field } => match &**object {
+                Expression::NULLSAFE(object) => write!(f, "{}?.{}", operand(object, POSTFIX), field),
+                object => write!(f, "{}.{}", operand(object, POSTFIX), field),
+            },
+            Expression::MATCH { value, arms } => {
+                let arms: Vec<String> = arms
+                    .iter()
+                    .map(|arm| {
+                        let guard = match &arm.guard {
+                            Some(guard) => format!(" if {}", guard),
+                            None => String::new(),
+                        };
+                        format!("{}{} => {}", arm.pattern, guard, block(&arm.body))
+                    })
+                    .collect();
+                write!(f, "match {} {{ {} }}", value, arms.join(", "))
+            }
+            Expression::RANGE {
+                start,
+                end,
+                inclusive,
+                step,
+            } => {
+                if let Some(start) = start {
+                    write!(f, "{}", operand(start, power + 1))?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", operand(end, power + 1))?;
+                }
+                match step {
+                    Some(step) => write!(f, " step {}", operand(step, power + 1)),
+                    None => Ok(()),
+                }
+            }
+            Expression::FOR {
+                pattern,
+                iterable,
+                body,
+            } => write!(f, "for {} in {} {}", pattern, iterable, block(body)),
+            Expression::STRUCT { name, fields } => {
+                let fields: Vec<String> = fields.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            Expression::TRY(try_expr) => {
+                write!(f, "try {}", block(&try_expr.body))?;
+                if let Some(catch_body) = &try_expr.catch_body {
+                    match &try_expr.catch_name {
+                        Some(name) => write!(f, " catch {} {}", name, block(catch_body))?,
+                        None => write!(f, " catch {}", block(catch_body))?,
+                    }
+                }
+                match &try_expr.finally {
+                    Some(finally) => write!(f, " finally {}", block(finally)),
+                    None => Ok(()),
+                }
+            }
+            Expression::PROPAGATE(value) => write!(f, "{}?", operand(value, POSTFIX)),
+            Expression::NULLSAFE(value) => write!(f, "{}", value),
+            Expression::OPTCHAIN(chain) => write!(f, "{}", chain),
+            Expression::THROW(value) => write!(f, "throw {}", value),
+            Expression::YIELD(value) => write!(f, "yield {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Let(let_stmt) => write!(f, "let {} = {}", let_stmt.pattern, let_stmt.value),
+            Statement::Return(ret) => write!(f, "ret {}", ret.return_value),
+            Statement::Expression(expr) => write!(f, "{}", expr.expression),
+            Statement::Break => write!(f, "break"),
+            Statement::Continue => write!(f, "continue"),
+            Statement::Struct(struct_stmt) => {
+                write!(f, "struct {} {{ {} }}", struct_stmt.name, struct_stmt.fields.join(", "))?;
+                for invariant in &struct_stmt.invariants {
+                    write!(f, " invariant {}", invariant)?;
+                }
+                Ok(())
+            }
+            Statement::Enum(enum_stmt) => {
+                let variants: Vec<String> = enum_stmt
+                    .variants
+                    .iter()
+                    .map(|v| {
+                        if v.fields.is_empty() {
+                            v.name.clone()
+                        } else {
+                            format!("{}({})", v.name, v.fields.join(", "))
+                        }
+                    })
+                    .collect();
+                write!(f, "enum {} {{ {} }}", enum_stmt.name, variants.join(", "))
+            }
+            Statement::Trait(trait_stmt) => {
+                let mut methods: Vec<String> =
+                    trait_stmt.required.iter().map(|name| format!("fun {}(...);", name)).collect();
+                methods.extend(trait_stmt.provided.iter().map(|method| method.to_string()));
+                write!(f, "trait {} {{ {} }}", trait_stmt.name, methods.join(" "))
+            }
+            Statement::Impl(impl_stmt) => {
+                let methods: Vec<String> = impl_stmt.methods.iter().map(|m| m.to_string()).collect();
+                match &impl_stmt.trait_name {
+                    Some(trait_name) => write!(f, "impl {} for {} {{ {} }}", trait_name, impl_stmt.name, methods.join(" ")),
+                    None => write!(f, "impl {} {{ {} }}", impl_stmt.name, methods.join(" ")),
+                }
+            }
+            Statement::Pub(inner) => write!(f, "pub {}", inner),
+            Statement::Import(import) => {
+                if !import.names.is_empty() {
+                    write!(f, "from {:?} import {}", import.path, import.names.join(", "))
+                } else if let Some(alias) = &import.alias {
+                    write!(f, "import {:?} as {}", import.path, alias)
+                } else {
+                    write!(f, "import {:?}", import.path)
+                }
+            }
+            Statement::Macro(definition) => write!(
+                f,
+                "macro {}({}) {}",
+                definition.name,
+                definition.parameters.join(", "),
+                block(&definition.body)
+            ),
+            Statement::Defer(expr) => write!(f, "defer {}", expr),
+            Statement::Assert(assert) => match &assert.message {
+                Some(message) => write!(f, "assert {}, {}", assert.condition, message),
+                None => write!(f, "assert {}", assert.condition),
+            },
+            Statement::Infix(infix) => {
+                write!(f, "infix ")?;
+                if infix.right {
+                    write!(f, "right ")?;
+                }
+                match &*infix.function {
+                    Expression::FUNCTION {
+                        parameters, body, ..
+                    } => {
+                        let params: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+                        write!(f, "{} {} ({}) {}", infix.precedence, infix.operator, params.join(", "), block(body))
+                    }
+                    function => write!(f, "{} {} {}", infix.precedence, infix.operator, function),
+                }
+            }
+        }
+    }
+}
+
+// rebuilds a syntax tree top-down, a hook that replaces a node also decides its children
+pub trait Rewriter {
+    fn expression(&mut self, _expr: &Expression) -> Option<Expression> {
+        None
+    }
+
+    // every name a pattern, parameter list, `catch` or named function binds
+    fn binding(&mut self, name: String) -> String {
+        name
+    }
+}
+
+impl Statement {
+    pub fn rewrite(self, r: &mut dyn Rewriter) -> Statement {
+        match self {
+            Statement::Let(let_stmt) => {
+                let LetStatement {
+                    pattern,
+                    value,
+                    span,
+                } = *let_stmt;
+                Statement::Let(Box::new(LetStatement {
+                    pattern: pattern.rewrite(r),
+                    value: value.rewrite(r),
+                    span,
+                }))
+            }
+            Statement::Return(ret) => Statement::Return(ReturnStatement {
+                return_value: ret.return_value.rewrite(r),
+                span: ret.span,
+            }),
+            Statement::Expression(expr) => Statement::Expression(ExpressionStatement {
+                expression: expr.expression.rewrite(r),
+            }),
+            // method names are looked up on the type, so only their insides are rewritten
+            Statement::Trait(trait_stmt) => Statement::Trait(TraitStatement {
+                provided: trait_stmt.provided.into_iter().map(|m| rewrite_method(m, r)).collect(),
+                ..trait_stmt
+            }),
+            Statement::Impl(impl_stmt) => Statement::Impl(ImplStatement {
+                methods: impl_stmt.methods.into_iter().map(|m| rewrite_method(m, r)).collect(),
+                ..impl_stmt
+            }),
+            Statement::Struct(struct_stmt) => Statement::Struct(StructStatement {
+                invariants: struct_stmt.invariants.into_iter().map(|e| e.rewrite(r)).collect(),
+                ..struct_stmt
+            }),
+            Statement::Pub(inner) => Statement::Pub(Box::new(inner.rewrite(r))),
+            Statement::Defer(expr) => Statement::Defer(expr.rewrite(r)),
+            Statement::Assert(assert) => Statement::Assert(AssertStatement {
+                condition: assert.condition.rewrite(r),
+                message: assert.message.map(|m| m.rewrite(r)),
+            }),
+            // the operator keeps its symbol, like a method keeps its name
+            Statement::Infix(infix) => Statement::Infix(InfixStatement {
+                function: Box::new(rewrite_method(*infix.function, r)),
+                ..infix
+            }),
+            statement => statement,
+        }
+    }
+}
+
+fn rewrite_block(statements: Vec<Statement>, r: &mut dyn Rewriter) -> Vec<Statement> {
+    statements.into_iter().map(|s| s.rewrite(r)).collect()
+}
+
+fn rewrite_method(method: Expression, r: &mut dyn Rewriter) -> Expression {
+    match method {
+        Expression::FUNCTION {
+            name,
+            parameters,
+            rest,
+            body,
+            generator,
+            returns,
+            contract,
+            span,
+        } => Expression::FUNCTION {
+            name,
+            parameters: parameters.into_iter().map(|p| p.rewrite(r)).collect(),
+            rest: rest.map(|rest| r.binding(rest)),
+            body: rewrite_block(body, r),
+            generator,
+            returns,
+            contract: contract.rewrite(r),
+            span,
+        },
+        method => method.rewrite(r),
+    }
+}
+
+impl Expression {
+    pub fn rewrite(self, r: &mut dyn Rewriter) -> Expression {
+        if let Some(replaced) = r.expression(&self) {
+            return replaced;
+        }
+
+        let mut boxed = |expr: Box<Expression>| Box::new(expr.rewrite(r));
+        match self {
+            Expression::INFEX {
+                left,
+                operator,
+                right,
+                span,
+            } => Expression::INFEX {
+                left: boxed(left),
+                operator,
+                right: boxed(right),
+                span,
+            },
+            Expression::PREFIX { operator, right } => Expression::PREFIX {
+                operator,
+                right: boxed(right),
+            },
+            Expression::IF {
+                condition,
+                consequence,
+                alternative,
+            } => Expression::IF {
+                condition: boxed(condition),
+                consequence: rewrite_block(consequence, r),
+                alternative: alternative.map(|a| rewrite_block(a, r)),
+            },
+            Expression::IFLET {
+                pattern,
+                value,
+                consequence,
+                alternative,
+            } => Expression::IFLET {
+                pattern: Box::new(pattern.rewrite(r)),
+                value: Box::new(value.rewrite(r)),
+                consequence: rewrite_block(consequence, r),
+                alternative: alternative.map(|a| rewrite_block(a, r)),
+            },
+            Expression::FUNCTION {
+                name,
+                parameters,
+                rest,
+                body,
+                generator,
+                returns,
+                contract,
+                span,
+            } => Expression::FUNCTION {
+                name: name.map(|name| r.binding(name)),
+                parameters: parameters.into_iter().map(|p| p.rewrite(r)).collect(),
+                rest: rest.map(|rest| r.binding(rest)),
+                body: rewrite_block(body, r),
+                generator,
+                returns,
+                contract: contract.rewrite(r),
+                span,
+            },
+            Expression::CALL {
+                function,
+                arguments,
+                named,
+                span,
+            } => Expression::CALL {
+                function: boxed(function),
+                arguments: arguments.into_iter().map(|a| a.rewrite(r)).collect(),
+                named: named.into_iter().map(|(n, v)| (n, v.rewrite(r))).collect(),
+                span,
+            },
+            Expression::PIPE {
+                value,
+                function,
+                span,
+            } => Expression::PIPE {
+                value: boxed(value),
+                function: boxed(function),
+                span,
+            },
+            Expression::SPREAD(value) => Expression::SPREAD(boxed(value)),
+            Expression::INTERPOLATION(parts) => {
+                Expression::INTERPOLATION(parts.into_iter().map(|p| p.rewrite(r)).collect())
+            }
+            Expression::TUPLE(elements) => {
+                Expression::TUPLE(elements.into_iter().map(|e| e.rewrite(r)).collect())
+            }
+            Expression::ARRAY(elements) => {
+                Expression::ARRAY(elements.into_iter().map(|e| e.rewrite(r)).collect())
+            }
+            Expression::SET(elements) => {
+                Expression::SET(elements.into_iter().map(|e| e.rewrite(r)).collect())
+            }
+            Expression::MAP(pairs) => Expression::MAP(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.rewrite(r), v.rewrite(r)))
+                    .collect(),
+            ),
+            Expression::LISTCOMP { element, clauses } => {
+                // the clauses bind what the element refers to
+                let clauses = rewrite_clauses(clauses, r);
+                Expression::LISTCOMP {
+                    element: Box::new(element.rewrite(r)),
+                    clauses,
+                }
+            }
+            Expression::MAPCOMP {
+                key,
+                value,
+                clauses,
+            } => {
+                let clauses = rewrite_clauses(clauses, r);
+                Expression::MAPCOMP {
+                    key: Box::new(key.rewrite(r)),
+                    value: Box::new(value.rewrite(r)),
+                    clauses,
+                }
+            }
+            Expression::INDEX { left, index } => Expression::INDEX {
+                left: boxed(left),
+                index: boxed(index),
+            },
+            Expression::MATCH { value, arms } => Expression::MATCH {
+                value: Box::new(value.rewrite(r)),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern.rewrite(r),
+                        guard: arm.guard.map(|g| g.rewrite(r)),
+                        body: rewrite_block(arm.body, r),
+                    })
+                    .collect(),
+            },
+            Expression::RANGE {
+                start,
+                end,
+                inclusive,
+                step,
+            } => Expression::RANGE {
+                start: start.map(&mut boxed),
+                end: end.map(&mut boxed),
+                inclusive,
+                step: step.map(&mut boxed),
+            },
+            Expression::FOR {
+                pattern,
+                iterable,
+                body,
+            } => Expression::FOR {
+                pattern: Box::new(pattern.rewrite(r)),
+                iterable: Box::new(iterable.rewrite(r)),
+                body: rewrite_block(body, r),
+            },
+            Expression::STRUCT { name, fields } => Expression::STRUCT {
+                name,
+                fields: fields.into_iter().map(|(n, v)| (n, v.rewrite(r))).collect(),
+            },
+            Expression::FIELD { object, field } => Expression::FIELD {
+                object: boxed(object),
+                field,
+            },
+            Expression::ASSIGN { target, value } => Expression::ASSIGN {
+                target: boxed(target),
+                value: boxed(value),
+            },
+            Expression::TRY(try_expr) => {
+                let TryExpression {
+                    body,
+                    catch_name,
+                    catch_body,
+                    finally,
+                } = *try_expr;
+                Expression::TRY(Box::new(TryExpression {
+                    body: rewrite_block(body, r),
+                    catch_name: catch_name.map(|name| r.binding(name)),
+                    catch_body: catch_body.map(|b| rewrite_block(b, r)),
+                    finally: finally.map(|b| rewrite_block(b, r)),
+                }))
+            }
+            Expression::PROPAGATE(value) => Expression::PROPAGATE(boxed(value)),
+            Expression::NULLSAFE(value) => Expression::NULLSAFE(boxed(value)),
+            Expression::OPTCHAIN(chain) => Expression::OPTCHAIN(boxed(chain)),
+            Expression::THROW(value) => Expression::THROW(boxed(value)),
+            Expression::YIELD(value) => Expression::YIELD(boxed(value)),
+            leaf => leaf,
+        }
+    }
+}
+
+fn rewrite_clauses(clauses: Vec<Clause>, r: &mut dyn Rewriter) -> Vec<Clause> {
+    clauses
+        .into_iter()
+        .map(|clause| match clause {
+            Clause::FOR { pattern, iterable } => Clause::FOR {
+                pattern: Box::new(pattern.rewrite(r)),
+                iterable: iterable.rewrite(r),
+            },
+            Clause::IF(condition) => Clause::IF(condition.rewrite(r)),
+        })
+        .collect()
+}
+
+impl Pattern {
+    pub fn rewrite(self, r: &mut dyn Rewriter) -> Pattern {
+        match self {
+            Pattern::BINDING(name) => Pattern::BINDING(r.binding(name)),
+            Pattern::LITERAL(literal) => Pattern::LITERAL(literal.rewrite(r)),
+            Pattern::ARRAY {
+                before,
+                rest,
+                after,
+            } => Pattern::ARRAY {
+                before: before.into_iter().map(|p| p.rewrite(r)).collect(),
+                rest: rest.map(|rest| if rest == "_" { rest } else { r.binding(rest) }),
+                after: after.into_iter().map(|p| p.rewrite(r)).collect(),
+            },
+            Pattern::MAP(entries) => {
+                Pattern::MAP(entries.into_iter().map(|(k, p)| (k, p.rewrite(r))).collect())
+            }
+            Pattern::TUPLE(elements) => Pattern::TUPLE(elements.into_iter().map(|p| p.rewrite(r)).collect()),
+            Pattern::RANGE {
+                start,
+                end,
+                inclusive,
+            } => Pattern::RANGE {
+                start: start.rewrite(r),
+                end: end.rewrite(r),
+                inclusive,
+            },
+            Pattern::OR(alternatives) => {
+                Pattern::OR(alternatives.into_iter().map(|p| p.rewrite(r)).collect())
+            }
+            Pattern::VARIANT {
+                enum_name,
+                variant,
+                fields,
+            } => Pattern::VARIANT {
+                enum_name,
+                variant,
+                fields: fields.into_iter().map(|p| p.rewrite(r)).collect(),
+            },
+            Pattern::DEFAULT { pattern, default } => Pattern::DEFAULT {
+                pattern: Box::new(pattern.rewrite(r)),
+                default: default.rewrite(r),
+            },
+            Pattern::TYPED {
+                pattern,
+                annotation,
+            } => Pattern::TYPED {
+                pattern: Box::new(pattern.rewrite(r)),
+                annotation,
+            },
+            Pattern::WILDCARD => Pattern::WILDCARD,
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::rc::Rc;
+
+use crate::evaluator::{apply_function, iterate, set_element};
+use crate::object::{
+    BuiltinFunction, EnumType, HashKey, Object, ObjectType, SharedSet, TraitType, Variant,
+};
+
+thread_local! {
+    // `Result` and `Option` are ordinary enums every program starts out with
+    static RESULT: Rc<EnumType> = prelude_enum("Result", &[("Ok", &["value"]), ("Err", &["error"])]);
+    static OPTION: Rc<EnumType> = prelude_enum("Option", &[("Some", &["value"]), ("None", &[])]);
+}
+
+pub fn lookup(name: &str) -> Option<ObjectType> {
+    let func: BuiltinFunction = match name {
+        "len" => len,
+        "push" => push,
+        "map" => map,
+        "filter" => filter,
+        "iter" => iter,
+        "next" => next,
+        "collect" => collect,
+        "take" => take,
+        "zip" => zip,
+        "enumerate" => enumerate,
+        "chain" => chain,
+        "set" => set,
+        "add" => add,
+        "remove" => remove,
+        "contains" => contains,
+        "error" => error,
+        "unwrap" => unwrap,
+        "unwrap_or" => unwrap_or,
+        "map_err" => map_err,
+        "parse_int" => parse_int,
+        "read_file" => read_file,
+        _ => return None,
+    };
+    Some(ObjectType::Builtin(name.to_string(), func))
+}
+
+// `Ok`, `Err`, `Some`, `None` and the enums they belong to
+pub fn lookup_prelude(name: &str) -> Option<ObjectType> {
+    match name {
+        "Result" => Some(ObjectType::Enum(RESULT.with(Rc::clone))),
+        "Option" => Some(ObjectType::Enum(OPTION.with(Rc::clone))),
+        "Ok" => Some(ObjectType::Constructor(RESULT.with(Rc::clone), 0)),
+        "Err" => Some(ObjectType::Constructor(RESULT.with(Rc::clone), 1)),
+        "Some" => Some(ObjectType::Constructor(OPTION.with(Rc::clone), 0)),
+        "None" => Some(variant(OPTION.with(Rc::clone), 1, vec![])),
+        _ => None,
+    }
+}
+
+// Result and Option values print as `Ok(1)` rather than `Result.Ok(1)`
+pub fn is_prelude(enum_type: &Rc<EnumType>) -> bool {
+    RESULT.with(|result| Rc::ptr_eq(result, enum_type)) || OPTION.with(|option| Rc::ptr_eq(option, enum_type))
+}
+
+// splits a Result or Option into its variant name and payload
+pub fn prelude_parts(value: &ObjectType) -> Option<(&str, Option<ObjectType>)> {
+    match value {
+        ObjectType::Variant(variant) if is_prelude(&variant.enum_type) => {
+            Some((variant.name(), variant.values.first().cloned()))
+        }
+        _ => None,
+    }
+}
+
+fn prelude_enum(name: &str, variants: &[(&str, &[&str])]) -> Rc<EnumType> {
+    Rc::new(EnumType {
+        name: name.to_string(),
+        variants: variants
+            .iter()
+            .map(|(variant, fields)| {
+                (variant.to_string(), fields.iter().map(|f| f.to_string()).collect())
+            })
+            .collect(),
+        methods: RefCell::new(HashMap::new()),
+        traits: RefCell::new(vec![]),
+    })
+}
+
+fn variant(enum_type: Rc<EnumType>, index: usize, values: Vec<ObjectType>) -> ObjectType {
+    ObjectType::Variant(Rc::new(Variant {
+        enum_type,
+        index,
+        values,
+    }))
+}
+
+fn ok(value: ObjectType) -> ObjectType {
+    variant(RESULT.with(Rc::clone), 0, vec![value])
+}
+
+fn err(error: ObjectType) -> ObjectType {
+    variant(RESULT.with(Rc::clone), 1, vec![error])
+}
+
+// the traits the interpreter itself calls into: printing, `==`, `for` and `+`
+pub fn lookup_trait(name: &str) -> Option<ObjectType> {
+    let method = match name {
+        "Show" => "show",
+        "Eq" => "eq",
+        "Iterable" => "iter",
+        "Iterator" => "next",
+        "Add" => "add",
+        _ => return None,
+    };
+    Some(ObjectType::Trait(Rc::new(TraitType {
+        name: name.to_string(),
+        required: vec![method.to_string()],
+        provided: vec![],
+    })))
+}
+
+fn expect_args(name: &str, args: &[ObjectType], count: usize) -> Result<(), ObjectType> {
+    if args.len() != count {
+        return Err(ObjectType::error(
+            "ArgumentError",
+            format!(
+                "Wrong number of arguments to '{}': expected {}, got {}",
+                name,
+                count,
+                args.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn len(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("len", &args, 1) {
+        return err;
+    }
+    match &args[0] {
+        ObjectType::Array(elements) => ObjectType::Number(elements.len() as i64),
+        ObjectType::String(str) => ObjectType::Number(str.chars().count() as i64),
+        ObjectType::Set(elements) => ObjectType::Number(elements.borrow().len() as i64),
+        _ => ObjectType::error("TypeError", "Unsupported argument to 'len'"),
+    }
+}
+
+fn push(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("push", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(ObjectType::Array(mut elements)), Some(value)) => {
+            elements.push(value);
+            ObjectType::Array(elements)
+        }
+        _ => ObjectType::error("TypeError", "First argument to 'push' must be an array"),
+    }
+}
+
+// map(xs, f) gives an iterator that calls f as items are asked for,
+// `collect` turns it back into an array
+fn map(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("map", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(iterable), Some(func)) => {
+            let items = match iterate(iterable) {
+                Ok(items) => items,
+                Err(thrown) => return thrown,
+            };
+            ObjectType::iterator(
+                "iterator map",
+                items.map(move |item| match item {
+                    thrown @ ObjectType::Throw(_) => thrown,
+                    item => apply_function(func.clone(), vec![item]),
+                }),
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+// filter(xs, f) gives an iterator of the items for which f returns true,
+// skipping ahead as items are asked for
+fn filter(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("filter", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(iterable), Some(func)) => {
+            let mut items = match iterate(iterable) {
+                Ok(items) => items,
+                Err(thrown) => return thrown,
+            };
+            ObjectType::iterator(
+                "iterator filter",
+                std::iter::from_fn(move || {
+                    for item in items.by_ref() {
+                        if let ObjectType::Throw(_) = item {
+                            return Some(item);
+                        }
+                        match apply_function(func.clone(), vec![item.clone()]) {
+                            thrown @ ObjectType::Throw(_) => return Some(thrown),
+                            ObjectType::Boolean(true) => return Some(item),
+                            _ => {}
+                        }
+                    }
+                    None
+                }),
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+// the `{"value": ..., "done": ...}` that `next` hands back
+fn step(value: Option<ObjectType>) -> ObjectType {
+    let mut pair = BTreeMap::new();
+    pair.insert(HashKey::String("done".to_string()), ObjectType::Boolean(value.is_none()));
+    pair.insert(HashKey::String("value".to_string()), value.unwrap_or(ObjectType::Null));
+    ObjectType::Map(pair)
+}
+
+// reads what an Iterator's `next` returned, None once it is done
+pub fn read_step(step: &ObjectType) -> Result<Option<ObjectType>, ObjectType> {
+    let pair = match step {
+        ObjectType::Map(pair) => pair,
+        other => {
+            return Err(ObjectType::error(
+                "TypeError",
+                format!("'next' must return {{\"value\": ..., \"done\": ...}}, got {}", other.inspect()),
+            ));
+        }
+    };
+    match pair.get(&HashKey::String("done".to_string())) {
+        Some(ObjectType::Boolean(true)) => Ok(None),
+        _ => Ok(Some(
+            pair.get(&HashKey::String("value".to_string()))
+                .cloned()
+                .unwrap_or(ObjectType::Null),
+        )),
+    }
+}
+
+// iter(xs) walks an array, range, string, map or Iterable one `next` at a time
+fn iter(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("iter", &args, 1) {
+        return err;
+    }
+    match args.into_iter().next() {
+        Some(iterator @ ObjectType::Iterator(_)) => iterator,
+        Some(value) => match iterate(value) {
+            Ok(items) => ObjectType::iterator("iterator", items),
+            Err(thrown) => thrown,
+        },
+        None => unreachable!(),
+    }
+}
+
+// next(it) advances an iterator, `{"value": 1, "done": false}` or `{"value": null, "done": true}`
+fn next(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("next", &args, 1) {
+        return err;
+    }
+    match &args[0] {
+        ObjectType::Iterator(iterator) => match iterator.next() {
+            Some(thrown @ ObjectType::Throw(_)) => thrown,
+            item => step(item),
+        },
+        value => match value.trait_method("Iterator", "next") {
+            Some(next) => apply_function(next, vec![value.clone()]),
+            None => ObjectType::error(
+                "TypeError",
+                format!("'next' needs an iterator, got {}, try iter() first", value.inspect()),
+            ),
+        },
+    }
+}
+
+// collect(it) runs an iterable to the end and gathers its items into an array
+fn collect(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("collect", &args, 1) {
+        return err;
+    }
+    let items = match iterate(args.into_iter().next().unwrap()) {
+        Ok(items) => items,
+        Err(thrown) => return thrown,
+    };
+    let mut collected = vec![];
+    for item in items {
+        if let ObjectType::Throw(_) = item {
+            return item;
+        }
+        collected.push(item);
+    }
+    ObjectType::Array(collected)
+}
+
+// take(it, n) stops after the first n items, so it can end an infinite generator
+fn take(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("take", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(iterable), Some(ObjectType::Number(count))) => match iterate(iterable) {
+            Ok(items) => ObjectType::iterator("iterator take", items.take(count.max(0) as usize)),
+            Err(thrown) => thrown,
+        },
+        _ => ObjectType::error("TypeError", "Second argument to 'take' must be a number"),
+    }
+}
+
+// zip(a, b) pairs items up as `[x, y]` until either side runs out
+fn zip(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("zip", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    let (left, right) = match (iterate(args.next().unwrap()), iterate(args.next().unwrap())) {
+        (Ok(left), Ok(right)) => (left, right),
+        (Err(thrown), _) | (_, Err(thrown)) => return thrown,
+    };
+    ObjectType::iterator(
+        "iterator zip",
+        left.zip(right).map(|pair| match pair {
+            (thrown @ ObjectType::Throw(_), _) | (_, thrown @ ObjectType::Throw(_)) => thrown,
+            (left, right) => ObjectType::Array(vec![left, right]),
+        }),
+    )
+}
+
+// enumerate(it) pairs every item with its position as `[i, x]`
+fn enumerate(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("enumerate", &args, 1) {
+        return err;
+    }
+    match iterate(args.into_iter().next().unwrap()) {
+        Ok(items) => ObjectType::iterator(
+            "iterator enumerate",
+            items.enumerate().map(|(i, item)| match item {
+                thrown @ ObjectType::Throw(_) => thrown,
+                item => ObjectType::Array(vec![ObjectType::Number(i as i64), item]),
+            }),
+        ),
+        Err(thrown) => thrown,
+    }
+}
+
+// chain(a, b) walks a and then b
+fn chain(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("chain", &args, 2) {
+        return err;
+    }
+    let mut args = args.into_iter();
+    match (iterate(args.next().unwrap()), iterate(args.next().unwrap())) {
+        (Ok(first), Ok(second)) => ObjectType::iterator("iterator chain", first.chain(second)),
+        (Err(thrown), _) | (_, Err(thrown)) => thrown,
+    }
+}
+
+// set() is empty, set(xs) gathers the items of any iterable
+fn set(args: Vec<ObjectType>) -> ObjectType {
+    if args.is_empty() {
+        return ObjectType::set(BTreeSet::new());
+    }
+    if let Err(err) = expect_args("set", &args, 1) {
+        return err;
+    }
+    let items = match iterate(args.into_iter().next().unwrap()) {
+        Ok(items) => items,
+        Err(thrown) => return thrown,
+    };
+    let mut set = BTreeSet::new();
+    for item in items {
+        if let ObjectType::Throw(_) = item {
+            return item;
+        }
+        match set_element(item) {
+            Ok(element) => set.insert(element),
+            Err(err) => return err,
+        };
+    }
+    ObjectType::set(set)
+}
+
+// the set and element that `add`, `remove` and `contains` take
+fn set_args(name: &str, args: Vec<ObjectType>) -> Result<(SharedSet, HashKey), ObjectType> {
+    expect_args(name, &args, 2)?;
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(ObjectType::Set(set)), Some(value)) => Ok((set, set_element(value)?)),
+        _ => Err(ObjectType::error(
+            "TypeError",
+            format!("First argument to '{}' must be a set", name),
+        )),
+    }
+}
+
+// add(s, x) puts x into s, `set(s)` first makes a copy to add to instead
+fn add(args: Vec<ObjectType>) -> ObjectType {
+    match set_args("add", args) {
+        Ok((set, element)) => {
+            set.borrow_mut().insert(element);
+            ObjectType::Null
+        }
+        Err(err) => err,
+    }
+}
+
+// remove(s, x) takes x out of s, if it was there
+fn remove(args: Vec<ObjectType>) -> ObjectType {
+    match set_args("remove", args) {
+        Ok((set, element)) => {
+            set.borrow_mut().remove(&element);
+            ObjectType::Null
+        }
+        Err(err) => err,
+    }
+}
+
+// contains(s, x) is whether x is in s
+fn contains(args: Vec<ObjectType>) -> ObjectType {
+    match set_args("contains", args) {
+        Ok((set, element)) => ObjectType::Boolean(set.borrow().contains(&element)),
+        Err(err) => err,
+    }
+}
+
+// error(message) or error(kind, message) builds an error value for `throw`
+fn error(args: Vec<ObjectType>) -> ObjectType {
+    let (kind, message) = match args.as_slice() {
+        [ObjectType::String(message)] => ("Error", message),
+        [ObjectType::String(kind), ObjectType::String(message)] => (kind.as_str(), message),
+        _ => {
+            return ObjectType::error(
+                "ArgumentError",
+                "'error' takes a message or a kind and a message",
+            );
+        }
+    };
+    ObjectType::error_value(kind, message.clone())
+}
+
+// unwrap(x) takes the value out of an `Ok` or `Some` and throws on `Err` or `None`
+fn unwrap(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("unwrap", &args, 1) {
+        return err;
+    }
+    match prelude_parts(&args[0]) {
+        Some(("Ok" | "Some", Some(value))) => value,
+        Some(("Err", Some(error))) => {
+            ObjectType::error("UnwrapError", format!("Called unwrap on Err({})", error.inspect()))
+        }
+        Some(("None", _)) => ObjectType::error("UnwrapError", "Called unwrap on None"),
+        _ => ObjectType::error(
+            "TypeError",
+            format!("'unwrap' needs a Result or Option, got {}", args[0].inspect()),
+        ),
+    }
+}
+
+// unwrap_or(x, default) falls back to `default` for `Err` and `None`
+fn unwrap_or(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("unwrap_or", &args, 2) {
+        return err;
+    }
+    match prelude_parts(&args[0]) {
+        Some(("Ok" | "Some", Some(value))) => value,
+        Some(_) => args[1].clone(),
+        None => ObjectType::error(
+            "TypeError",
+            format!("'unwrap_or' needs a Result or Option, got {}", args[0].inspect()),
+        ),
+    }
+}
+
+// map_err(result, f) applies f to the error of an `Err` and passes `Ok` through
+fn map_err(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("map_err", &args, 2) {
+        return err;
+    }
+    match prelude_parts(&args[0]) {
+        Some(("Err", Some(error))) => match apply_function(args[1].clone(), vec![error]) {
+            thrown @ ObjectType::Throw(_) => thrown,
+            mapped => err(mapped),
+        },
+        Some(("Ok", _)) => args[0].clone(),
+        _ => ObjectType::error(
+            "TypeError",
+            format!("'map_err' needs a Result, got {}", args[0].inspect()),
+        ),
+    }
+}
+
+// parse_int(s) is `Ok(n)`, or `Err(message)` when s is not an integer
+fn parse_int(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("parse_int", &args, 1) {
+        return err;
+    }
+    match &args[0] {
+        ObjectType::String(str) => match str.trim().parse::<i64>() {
+            Ok(int) => ok(ObjectType::Number(int)),
+            Err(_) => err(ObjectType::String(format!("Cannot parse \"{}\" as an integer", str))),
+        },
+        _ => ObjectType::error("TypeError", "Argument to 'parse_int' must be a string"),
+    }
+}
+
+// read_file(path) is `Ok(contents)`, or `Err(message)` when the file can't be read
+fn read_file(args: Vec<ObjectType>) -> ObjectType {
+    if let Err(err) = expect_args("read_file", &args, 1) {
+        return err;
+    }
+    match &args[0] {
+        ObjectType::String(path) => match fs::read_to_string(path) {
+            Ok(contents) => ok(ObjectType::String(contents)),
+            Err(error) => err(ObjectType::String(format!("Cannot read \"{}\": {}", path, error))),
+        },
+        _ => ObjectType::error("TypeError", "Argument to 'read_file' must be a string"),
+    }
+}
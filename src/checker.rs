@@ -0,0 +1,872 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Clause, Expression, Pattern, Program, Statement, Type};
+use crate::token::Span;
+
+// a mismatch found by `ember check`
+#[derive(Debug, PartialEq)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+// checks annotations against the types that can be worked out without running the
+// program, whatever cannot be worked out is `any` and fits everywhere
+pub fn check(program: &Program) -> Vec<TypeError> {
+    let mut checker = Checker {
+        scopes: vec![HashMap::new()],
+        span: Span::default(),
+        returns: vec![],
+        errors: vec![],
+    };
+    for statement in &program.statements {
+        checker.check_statement(statement);
+    }
+    checker.errors
+}
+
+// what a call to a function with annotations can be checked against
+struct Signature {
+    parameters: Vec<Type>,
+    // parameters without a default
+    required: usize,
+    rest: bool,
+    returns: Type,
+}
+
+#[derive(Clone)]
+struct Binding {
+    ty: Type,
+    // only annotated names have assignments checked, the others just widen to `any`
+    annotated: bool,
+    signature: Option<Rc<Signature>>,
+}
+
+struct Checker {
+    scopes: Vec<HashMap<String, Binding>>,
+    // the innermost `let`, `ret`, call or operator, errors are reported at it
+    span: Span,
+    // the declared return type of each enclosing function
+    returns: Vec<Option<Type>>,
+    errors: Vec<TypeError>,
+}
+
+// whether a value of type `actual` can be used where `expected` is declared
+fn accepts(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (Type::Optional(_), Type::Null) => true,
+        (Type::Optional(expected), Type::Optional(actual)) => accepts(expected, actual),
+        (Type::Optional(expected), actual) => accepts(expected, actual),
+        (Type::Array(expected), Type::Array(actual)) => accepts(expected, actual),
+        (Type::Map(ek, ev), Type::Map(ak, av)) => accepts(ek, ak) && accepts(ev, av),
+        (expected, actual) => expected == actual,
+    }
+}
+
+// the type of a value that is one or the other, e.g. the branches of an `if`
+fn join(a: Type, b: Type) -> Type {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Type::Null, Type::Optional(t)) | (Type::Optional(t), Type::Null) => Type::Optional(t),
+        (Type::Null, Type::Any) | (Type::Any, Type::Null) => Type::Any,
+        (Type::Null, t) | (t, Type::Null) => Type::Optional(Box::new(t)),
+        _ => Type::Any,
+    }
+}
+
+fn int_like(ty: &Type) -> bool {
+    matches!(ty, Type::Int | Type::Any)
+}
+
+// the leftmost position recorded inside an expression
+fn span_of(expr: &Expression) -> Option<Span> {
+    match expr {
+        Expression::INFEX { left, span, .. } => span_of(left).or(Some(*span)),
+        Expression::CALL { span, .. } | Expression::PIPE { span, .. } => Some(*span),
+        Expression::PREFIX { right, .. } => span_of(right),
+        _ => None,
+    }
+}
+
+// the annotation a parameter was declared with, looking through its default
+fn annotation(pattern: &Pattern) -> Type {
+    match pattern {
+        Pattern::TYPED { annotation, .. } => annotation.clone(),
+        Pattern::DEFAULT { pattern, .. } => annotation(pattern),
+        _ => Type::Any,
+    }
+}
+
+impl Checker {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { span, message });
+    }
+
+    fn expect(&mut self, expected: &Type, actual: &Type, span: Span, what: &str) {
+        if !accepts(expected, actual) {
+            self.error(
+                span,
+                format!("Type mismatch for {}: expected {}, found {}", what, expected, actual),
+            );
+        }
+    }
+
+    fn declare(&mut self, name: &str, binding: Binding) {
+        self.scopes
+            .last_mut()
+            .expect("the checker always has a scope")
+            .insert(name.to_string(), binding);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn scoped<T>(&mut self, check: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = check(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn signature(&self, expr: &Expression) -> Option<Rc<Signature>> {
+        match expr {
+            Expression::IDENT(name) => self.lookup(name)?.signature.clone(),
+            Expression::FUNCTION {
+                parameters,
+                rest,
+                returns,
+                generator,
+                ..
+            } => Some(Rc::new(Signature {
+                parameters: parameters.iter().map(annotation).collect(),
+                required: parameters
+                    .iter()
+                    .filter(|p| !matches!(p, Pattern::DEFAULT { .. }))
+                    .count(),
+                rest: rest.is_some(),
+                // a generator function returns its generator, whatever the body says
+                returns: match returns {
+                    Some(returns) if !generator => returns.clone(),
+                    _ => Type::Any,
+                },
+            })),
+            _ => None,
+        }
+    }
+
+    // the type of the last statement, `any` when the block is left with `ret`, `break` or a throw
+    fn check_block(&mut self, statements: &[Statement]) -> Type {
+        let mut ty = Type::Null;
+        for statement in statements {
+            ty = self.check_statement(statement);
+        }
+        match statements.last() {
+            Some(Statement::Return(_) | Statement::Break | Statement::Continue) => Type::Any,
+            Some(Statement::Expression(stmt)) if matches!(stmt.expression, Expression::THROW(_)) => {
+                Type::Any
+            }
+            _ => ty,
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> Type {
+        match statement {
+            Statement::Expression(stmt) => self.infer(&stmt.expression),
+            Statement::Let(let_stmt) => {
+                self.span = let_stmt.span;
+                let ty = self.infer(&let_stmt.value);
+                let signature = self.signature(&let_stmt.value);
+                // mismatches are reported at the `let`, not at the last call in its value
+                self.span = let_stmt.span;
+                self.bind(&let_stmt.pattern, ty, signature);
+                Type::Null
+            }
+            Statement::Return(ret) => {
+                self.span = ret.span;
+                let ty = self.infer(&ret.return_value);
+                if let Some(Some(expected)) = self.returns.last().cloned() {
+                    self.expect(&expected, &ty, ret.span, "the return value");
+                }
+                Type::Any
+            }
+            Statement::Struct(struct_stmt) => {
+                self.declare(&struct_stmt.name, Binding { ty: Type::Any, annotated: false, signature: None });
+                Type::Null
+            }
+            Statement::Enum(enum_stmt) => {
+                self.declare(&enum_stmt.name, Binding { ty: Type::Any, annotated: false, signature: None });
+                Type::Null
+            }
+            // methods are reached through their type, so their names stay out of scope
+            Statement::Trait(trait_stmt) => {
+                self.scoped(|checker| {
+                    for method in &trait_stmt.provided {
+                        checker.infer(method);
+                    }
+                });
+                Type::Null
+            }
+            Statement::Impl(impl_stmt) => {
+                self.scoped(|checker| {
+                    for method in &impl_stmt.methods {
+                        checker.infer(method);
+                    }
+                });
+                Type::Null
+            }
+            Statement::Pub(inner) => self.check_statement(inner),
+            Statement::Import(import) => {
+                let names = match &import.alias {
+                    Some(alias) => vec![alias.clone()],
+                    None => import.names.clone(),
+                };
+                for name in names {
+                    self.declare(&name, Binding { ty: Type::Any, annotated: false, signature: None });
+                }
+                Type::Null
+            }
+            Statement::Infix(infix) => {
+                self.infer(&infix.function);
+                Type::Null
+            }
+            Statement::Assert(assert) => {
+                self.infer(&assert.condition);
+                if let Some(message) = &assert.message {
+                    self.infer(message);
+                }
+                Type::Null
+            }
+            Statement::Defer(expr) => {
+                self.infer(expr);
+                Type::Null
+            }
+            Statement::Break | Statement::Continue | Statement::Macro(_) => Type::Null,
+        }
+    }
+
+    // declares the names a pattern binds for a value of type `ty`
+    fn bind(&mut self, pattern: &Pattern, ty: Type, signature: Option<Rc<Signature>>) {
+        match pattern {
+            Pattern::BINDING(name) => self.declare(
+                name,
+                Binding {
+                    ty,
+                    annotated: false,
+                    signature,
+                },
+            ),
+            Pattern::TYPED {
+                pattern,
+                annotation,
+            } => {
+                let what = format!("'{}'", pattern);
+                self.expect(annotation, &ty, self.span, &what);
+                match &**pattern {
+                    Pattern::BINDING(name) => self.declare(
+                        name,
+                        Binding {
+                            ty: annotation.clone(),
+                            annotated: true,
+                            signature,
+                        },
+                    ),
+                    pattern => self.bind(pattern, annotation.clone(), None),
+                }
+            }
+            Pattern::ARRAY {
+                before,
+                rest,
+                after,
+            } => {
+                let element = match ty {
+                    Type::Array(element) => *element,
+                    _ => Type::Any,
+                };
+                for pattern in before.iter().chain(after) {
+                    self.bind(pattern, element.clone(), None);
+                }
+                if let Some(rest) = rest.as_ref().filter(|rest| *rest != "_") {
+                    self.bind(&Pattern::BINDING(rest.clone()), Type::Array(Box::new(element)), None);
+                }
+            }
+            Pattern::MAP(entries) => {
+                let value = match ty {
+                    Type::Map(_, value) => *value,
+                    _ => Type::Any,
+                };
+                for (_, pattern) in entries {
+                    self.bind(pattern, value.clone(), None);
+                }
+            }
+            Pattern::DEFAULT { pattern, default } => {
+                let default = self.infer(default);
+                self.bind(pattern, join(ty, default), None);
+            }
+            Pattern::TUPLE(elements) => {
+                for element in elements {
+                    self.bind(element, Type::Any, None);
+                }
+            }
+            Pattern::OR(alternatives) => {
+                for alternative in alternatives {
+                    self.bind(alternative, Type::Any, None);
+                }
+            }
+            Pattern::VARIANT { fields, .. } => {
+                for field in fields {
+                    self.bind(field, Type::Any, None);
+                }
+            }
+            Pattern::WILDCARD | Pattern::LITERAL(_) | Pattern::RANGE { .. } => {}
+        }
+    }
+
+    // the type of what `for` hands out for each item of `iterable`
+    fn item_type(&mut self, iterable: &Expression) -> Type {
+        let ty = self.infer(iterable);
+        match (iterable, ty) {
+            (Expression::RANGE { .. }, _) => Type::Int,
+            (_, Type::Array(element)) => *element,
+            (_, Type::String) => Type::String,
+            _ => Type::Any,
+        }
+    }
+
+    fn check_clauses(&mut self, clauses: &[Clause]) {
+        for clause in clauses {
+            match clause {
+                Clause::FOR { pattern, iterable } => {
+                    let item = self.item_type(iterable);
+                    self.bind(pattern, item, None);
+                }
+                Clause::IF(condition) => {
+                    self.infer(condition);
+                }
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::INT(_) => Type::Int,
+            Expression::BOOLEAN(_) => Type::Bool,
+            Expression::STRING(_) => Type::String,
+            Expression::ATOM(_) => Type::Atom,
+            Expression::NULL => Type::Null,
+            Expression::INTERPOLATION(parts) => {
+                for part in parts {
+                    self.infer(part);
+                }
+                Type::String
+            }
+            Expression::IDENT(name) => self.lookup(name).map(|b| b.ty.clone()).unwrap_or(Type::Any),
+            Expression::PLACEHOLDER => Type::Any,
+            Expression::OPERATOR(_) => Type::Function,
+            Expression::INFEX {
+                left,
+                operator,
+                right,
+                span,
+            } => {
+                let left = self.infer(left);
+                let right = self.infer(right);
+                self.span = *span;
+                self.infer_operator(operator, left, right, *span)
+            }
+            Expression::PREFIX { operator, right } => {
+                let right = self.infer(right);
+                let (valid, result) = match operator.as_str() {
+                    "-" => (int_like(&right), Type::Int),
+                    _ => (matches!(right, Type::Bool | Type::Any), Type::Bool),
+                };
+                if !valid {
+                    self.error(
+                        self.span,
+                        format!("Unsupported type for '{}' operator: {}", operator, right),
+                    );
+                }
+                result
+            }
+            Expression::ASSIGN { target, value } => {
+                let ty = self.infer(value);
+                match &**target {
+                    Expression::IDENT(name) => {
+                        let binding = self.lookup(name).cloned();
+                        match binding {
+                            Some(binding) if binding.annotated => {
+                                let what = format!("'{}'", name);
+                                self.expect(&binding.ty, &ty, self.span, &what);
+                            }
+                            // an unannotated variable that changes type could be anything
+                            Some(binding) if binding.ty != ty => {
+                                for scope in self.scopes.iter_mut().rev() {
+                                    if let Some(binding) = scope.get_mut(name) {
+                                        binding.ty = Type::Any;
+                                        binding.signature = None;
+                                        break;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    target => {
+                        self.infer(target);
+                    }
+                }
+                ty
+            }
+            Expression::PIPE {
+                value,
+                function,
+                span,
+            } => {
+                self.infer(value);
+                self.infer(function);
+                self.span = *span;
+                match self.signature(function) {
+                    Some(signature) => signature.returns.clone(),
+                    None => Type::Any,
+                }
+            }
+            Expression::IF {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.infer(condition);
+                let consequence = self.scoped(|checker| checker.check_block(consequence));
+                match alternative {
+                    Some(alternative) => {
+                        let alternative = self.scoped(|checker| checker.check_block(alternative));
+                        join(consequence, alternative)
+                    }
+                    None => join(consequence, Type::Null),
+                }
+            }
+            Expression::IFLET {
+                pattern,
+                value,
+                consequence,
+                alternative,
+            } => {
+                self.infer(value);
+                let consequence = self.scoped(|checker| {
+                    checker.bind(pattern, Type::Any, None);
+                    checker.check_block(consequence)
+                });
+                match alternative {
+                    Some(alternative) => {
+                        let alternative = self.scoped(|checker| checker.check_block(alternative));
+                        join(consequence, alternative)
+                    }
+                    None => join(consequence, Type::Null),
+                }
+            }
+            Expression::FUNCTION {
+                name,
+                parameters,
+                rest,
+                body,
+                generator,
+                returns,
+                contract,
+                span,
+            } => {
+                let signature = self.signature(expr);
+                // declared first so the body can call itself
+                if let Some(name) = name {
+                    self.declare(
+                        name,
+                        Binding {
+                            ty: Type::Function,
+                            annotated: false,
+                            signature: signature.clone(),
+                        },
+                    );
+                }
+                let expected = returns.clone().filter(|_| !generator);
+                self.scoped(|checker| {
+                    for parameter in parameters {
+                        checker.bind(parameter, annotation(parameter), None);
+                    }
+                    if let Some(rest) = rest {
+                        checker.bind(&Pattern::BINDING(rest.clone()), Type::Array(Box::new(Type::Any)), None);
+                    }
+                    for clause in &contract.requires {
+                        checker.infer(clause);
+                    }
+                    if !contract.ensures.is_empty() {
+                        checker.scoped(|checker| {
+                            let result = expected.clone().unwrap_or(Type::Any);
+                            checker.bind(&Pattern::BINDING("result".to_string()), result, None);
+                            for clause in &contract.ensures {
+                                checker.infer(clause);
+                            }
+                        });
+                    }
+                    checker.returns.push(expected.clone());
+                    let ty = checker.check_block(body);
+                    checker.returns.pop();
+
+                    // the last expression is returned too
+                    if let Some(expected) = expected {
+                        let at = match body.last() {
+                            Some(Statement::Expression(stmt)) => span_of(&stmt.expression),
+                            _ => None,
+                        };
+                        checker.expect(&expected, &ty, at.unwrap_or(*span), "the return value");
+                    }
+                });
+                Type::Function
+            }
+            Expression::CALL {
+                function,
+                arguments,
+                named,
+                span,
+            } => {
+                let callee = match &**function {
+                    // `quote(...)` holds syntax rather than values
+                    Expression::IDENT(name) if name == "quote" || name == "unquote" => return Type::Any,
+                    Expression::FIELD { object, .. } => {
+                        self.infer(object);
+                        None
+                    }
+                    function => {
+                        self.infer(function);
+                        Some(function)
+                    }
+                };
+                let arguments: Vec<(Type, &Expression)> =
+                    arguments.iter().map(|arg| (self.infer(arg), arg)).collect();
+                for (_, value) in named {
+                    self.infer(value);
+                }
+                self.span = *span;
+                self.check_call(callee, &arguments, named.is_empty(), *span)
+            }
+            Expression::SPREAD(value) => {
+                self.infer(value);
+                Type::Any
+            }
+            Expression::ARRAY(elements) => {
+                let mut element = None;
+                for expr in elements {
+                    let ty = match expr {
+                        Expression::SPREAD(_) => {
+                            self.infer(expr);
+                            Type::Any
+                        }
+                        expr => self.infer(expr),
+                    };
+                    element = Some(match element {
+                        Some(element) => join(element, ty),
+                        None => ty,
+                    });
+                }
+                Type::Array(Box::new(element.unwrap_or(Type::Any)))
+            }
+            Expression::TUPLE(elements) | Expression::SET(elements) => {
+                for element in elements {
+                    self.infer(element);
+                }
+                Type::Any
+            }
+            Expression::MAP(pairs) => {
+                let mut types: Option<(Type, Type)> = None;
+                for (key, value) in pairs {
+                    let (key, value) = (self.infer(key), self.infer(value));
+                    types = Some(match types {
+                        Some((k, v)) => (join(k, key), join(v, value)),
+                        None => (key, value),
+                    });
+                }
+                let (key, value) = types.unwrap_or((Type::Any, Type::Any));
+                Type::Map(Box::new(key), Box::new(value))
+            }
+            Expression::LISTCOMP { element, clauses } => self.scoped(|checker| {
+                checker.check_clauses(clauses);
+                Type::Array(Box::new(checker.infer(element)))
+            }),
+            Expression::MAPCOMP {
+                key,
+                value,
+                clauses,
+            } => self.scoped(|checker| {
+                checker.check_clauses(clauses);
+                Type::Map(Box::new(checker.infer(key)), Box::new(checker.infer(value)))
+            }),
+            Expression::INDEX { left, index } => {
+                let slice = matches!(**index, Expression::RANGE { .. });
+                let left = self.infer(left);
+                let index = self.infer(index);
+                match left {
+                    Type::Array(_) | Type::String if slice => left,
+                    Type::Array(element) => {
+                        if !int_like(&index) {
+                            self.error(self.span, format!("Array index must be int, found {}", index));
+                        }
+                        *element
+                    }
+                    Type::String => Type::String,
+                    Type::Map(_, value) => *value,
+                    _ => Type::Any,
+                }
+            }
+            Expression::FIELD { object, .. } => {
+                self.infer(object);
+                Type::Any
+            }
+            Expression::MATCH { value, arms } => {
+                self.infer(value);
+                let mut ty = None;
+                for arm in arms {
+                    let arm_ty = self.scoped(|checker| {
+                        checker.bind(&arm.pattern, Type::Any, None);
+                        if let Some(guard) = &arm.guard {
+                            checker.infer(guard);
+                        }
+                        checker.check_block(&arm.body)
+                    });
+                    ty = Some(match ty {
+                        Some(ty) => join(ty, arm_ty),
+                        None => arm_ty,
+                    });
+                }
+                ty.unwrap_or(Type::Null)
+            }
+            Expression::RANGE {
+                start, end, step, ..
+            } => {
+                for bound in [start, end, step].into_iter().flatten() {
+                    let ty = self.infer(bound);
+                    if !int_like(&ty) {
+                        self.error(self.span, format!("Range bounds must be int, found {}", ty));
+                    }
+                }
+                Type::Any
+            }
+            Expression::FOR {
+                pattern,
+                iterable,
+                body,
+            } => {
+                let item = self.item_type(iterable);
+                self.scoped(|checker| {
+                    checker.bind(pattern, item, None);
+                    checker.check_block(body);
+                });
+                Type::Null
+            }
+            Expression::STRUCT { name, fields } => {
+                for (_, value) in fields {
+                    self.infer(value);
+                }
+                Type::Named(name.clone())
+            }
+            Expression::TRY(try_expr) => {
+                let body = self.scoped(|checker| checker.check_block(&try_expr.body));
+                let result = match &try_expr.catch_body {
+                    Some(catch_body) => {
+                        let caught = self.scoped(|checker| {
+                            if let Some(name) = &try_expr.catch_name {
+                                checker.bind(&Pattern::BINDING(name.clone()), Type::Any, None);
+                            }
+                            checker.check_block(catch_body)
+                        });
+                        join(body, caught)
+                    }
+                    None => body,
+                };
+                if let Some(finally) = &try_expr.finally {
+                    self.scoped(|checker| checker.check_block(finally));
+                }
+                result
+            }
+            Expression::PROPAGATE(value)
+            | Expression::NULLSAFE(value)
+            | Expression::OPTCHAIN(value)
+            | Expression::THROW(value) => {
+                self.infer(value);
+                Type::Any
+            }
+            Expression::YIELD(value) => {
+                self.infer(value);
+                Type::Null
+            }
+        }
+    }
+
+    fn infer_operator(&mut self, operator: &str, left: Type, right: Type, span: Span) -> Type {
+        let result = match (operator, &left, &right) {
+            ("==" | "!=" | "in", _, _) => Some(Type::Bool),
+            ("??", Type::Optional(inner), _) => Some(join((**inner).clone(), right.clone())),
+            ("??", Type::Null, _) => Some(right.clone()),
+            ("??", _, _) => Some(left.clone()),
+            ("+", Type::Int, Type::Int | Type::Any) => Some(Type::Int),
+            ("+", Type::String, Type::String | Type::Any) => Some(Type::String),
+            // a struct may implement `Add`
+            ("+", Type::Any | Type::Named(_), _) => Some(Type::Any),
+            // sets are not tracked, so two unknown operands may be sets
+            ("-" | "|" | "&" | "^", Type::Any, Type::Any) => Some(Type::Any),
+            ("-" | "*" | "/" | "%", l, r) if int_like(l) && int_like(r) => Some(Type::Int),
+            ("<" | ">" | "<=" | ">=", l, r) if int_like(l) && int_like(r) => Some(Type::Bool),
+            ("+" | "-" | "*" | "/" | "%" | "<" | ">" | "<=" | ">=" | "|" | "&" | "^", _, _) => None,
+            // a declared operator is a function bound under its symbol
+            (operator, _, _) => Some(match self.lookup(operator).and_then(|b| b.signature.clone()) {
+                Some(signature) => signature.returns.clone(),
+                None => Type::Any,
+            }),
+        };
+        result.unwrap_or_else(|| {
+            self.error(
+                span,
+                format!(
+                    "Unsupported types for '{}' operator: {} and {}",
+                    operator, left, right
+                ),
+            );
+            Type::Any
+        })
+    }
+
+    // checks the arguments of a call against the callee's annotations and returns its result type
+    fn check_call(
+        &mut self,
+        callee: Option<&Expression>,
+        arguments: &[(Type, &Expression)],
+        positional_only: bool,
+        span: Span,
+    ) -> Type {
+        let callee = match callee {
+            Some(callee) => callee,
+            None => return Type::Any,
+        };
+        if arguments.iter().any(|(_, arg)| matches!(arg, Expression::PLACEHOLDER)) {
+            return Type::Function;
+        }
+        let signature = match self.signature(callee) {
+            Some(signature) => signature,
+            None => {
+                return match callee {
+                    Expression::IDENT(name) if name == "len" && self.lookup(name).is_none() => Type::Int,
+                    _ => Type::Any,
+                };
+            }
+        };
+
+        let name = match callee {
+            Expression::IDENT(name) => format!("'{}'", name),
+            _ => "the function".to_string(),
+        };
+        let spread = arguments.iter().any(|(_, arg)| matches!(arg, Expression::SPREAD(_)));
+        if positional_only
+            && !spread
+            && (arguments.len() < signature.required
+                || (arguments.len() > signature.parameters.len() && !signature.rest))
+        {
+            self.error(
+                span,
+                format!(
+                    "{} takes {} arguments, got {}",
+                    name,
+                    signature.parameters.len(),
+                    arguments.len()
+                ),
+            );
+        }
+        if !spread {
+            for (i, ((ty, _), expected)) in arguments.iter().zip(&signature.parameters).enumerate() {
+                let what = format!("argument {} of {}", i + 1, name);
+                self.expect(expected, ty, span, &what);
+            }
+        }
+        signature.returns.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn errors(input: &str) -> Vec<String> {
+        let program = Parser::new(Lexer::new(input.to_string())).parse_program().unwrap();
+        check(&program).iter().map(|e| e.to_string()).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_check_infers_unannotated_code() {
+        assert_eq!(
+            errors("let x = true + 1;"),
+            vec!["line 1, column 14: Unsupported types for '+' operator: bool and int"]
+        );
+        assert_eq!(
+            errors("let n = 1;\nlet s = \"a\";\nn * s"),
+            vec!["line 3, column 3: Unsupported types for '*' operator: int and string"]
+        );
+        assert!(errors("fun f(a, b) { a + b } let xs = [1, 2]; f(xs[0], len(xs)) - 1").is_empty());
+    }
+
+    #[test]
+    fn test_check_annotations() {
+        assert_eq!(
+            errors("let x: int = \"five\";"),
+            vec!["line 1, column 1: Type mismatch for 'x': expected int, found string"]
+        );
+        assert!(errors("let x: int? = null; let names: array<string> = [\"a\"]; let m: map<string, array<int>> = {\"a\": [1]};").is_empty());
+        assert_eq!(
+            errors("let xs: array<int> = [\"a\"];"),
+            vec!["line 1, column 1: Type mismatch for 'xs': expected array<int>, found array<string>"]
+        );
+        assert_eq!(
+            errors("let x: int = 1;\nx = false;"),
+            vec!["line 1, column 1: Type mismatch for 'x': expected int, found bool"]
+        );
+    }
+
+    #[test]
+    fn test_check_function_signatures() {
+        let input = "fun add(a: int, b: int) -> int { a + b }
+            add(1, true);
+            add(1);
+            let s: string = add(1, 2);";
+        assert_eq!(
+            errors(input),
+            vec![
+                "line 2, column 16: Type mismatch for argument 2 of 'add': expected int, found bool",
+                "line 3, column 16: 'add' takes 2 arguments, got 1",
+                "line 4, column 13: Type mismatch for 's': expected string, found int",
+            ]
+        );
+
+        let input = "fun name(id: int) -> string {\n  if id == 0 { ret null }\n  \"user\"\n}";
+        assert_eq!(
+            errors(input),
+            vec!["line 2, column 16: Type mismatch for the return value: expected string, found null"]
+        );
+        assert!(errors("fun name(id: int) -> string? { if id == 0 { ret null } \"user\" }").is_empty());
+
+        // a returned literal has no position of its own, the function's is used
+        assert_eq!(
+            errors("let a = 1;\n  fun r() -> string { 1 }"),
+            vec!["line 2, column 3: Type mismatch for the return value: expected string, found int"]
+        );
+        assert_eq!(
+            errors("let f = |x| x;\nlet g = fun() -> bool { \"no\" };"),
+            vec!["line 2, column 9: Type mismatch for the return value: expected bool, found string"]
+        );
+    }
+}
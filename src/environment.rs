@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::object::ObjectType;
+
+pub type Env = Rc<RefCell<Environment>>;
+
+pub struct Environment {
+    store: HashMap<String, ObjectType>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: None,
+        }))
+    }
+
+    // a new scope that falls back to `outer` for names it doesn't define
+    pub fn new_enclosed(outer: Env) -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<ObjectType> {
+        match self.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => match &self.outer {
+                Some(outer) => outer.borrow().get(name),
+                None => None,
+            },
+        }
+    }
+
+    pub fn set(&mut self, name: String, value: ObjectType) {
+        self.store.insert(name, value);
+    }
+
+    // rebinds an existing name in the scope that defines it, returns false if there is none
+    pub fn assign(&mut self, name: &str, value: ObjectType) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
+
+// closures point back at their environment, so printing it could recurse forever
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Environment({} names)", self.store.len())
+    }
+}
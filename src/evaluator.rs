@@ -1,68 +1,1861 @@
-use crate::ast::{Expression, Program, Statement};
-use crate::object::{Object, ObjectType};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 
-pub fn eval(Statement: Statement) -> impl Object {
-    match Statement {
-        Statement::Expression(expr) => eval_expression(expr.expression),
-        _ => panic!("Unknown statement type"),
+use crate::ast::{
+    Clause, Contract, Expression, ImplStatement, MatchArm, Pattern, Statement, TraitStatement, TryExpression,
+};
+use crate::assertion;
+use crate::builtins;
+use crate::environment::{Env, Environment};
+use crate::generator::Generator;
+use crate::macros;
+use crate::module;
+use crate::object::{
+    range_contains, range_iter, EnumType, HashKey, Instance, Object, ObjectType, Partial,
+    StructType, TraitType, Variant,
+};
+use crate::token::Span;
+
+// hands a `ret`, `break`, `continue` or throw straight back to the caller, the way `?` does for a Result
+macro_rules! check {
+    ($value:expr) => {
+        match $value {
+            value if value.is_unwinding() => return value,
+            value => value,
+        }
+    };
+}
+
+thread_local! {
+    // `--no-contracts` turns off `requires`, `ensures` and `invariant` checks
+    static CONTRACTS: Cell<bool> = const { Cell::new(true) };
+}
+
+pub fn set_contracts(enabled: bool) {
+    CONTRACTS.with(|contracts| contracts.set(enabled));
+}
+
+pub fn eval(statement: &Statement, env: &Env) -> ObjectType {
+    match statement {
+        Statement::Expression(expr) => eval_expression(&expr.expression, env),
+        Statement::Let(let_stmt) => {
+            let value = check!(eval_expression(&let_stmt.value, env));
+            let mut bindings = vec![];
+            if let Err(err) = match_pattern(&let_stmt.pattern, &value, &mut bindings, env) {
+                return ObjectType::error(
+                    "MatchError",
+                    format!("Cannot destructure `{}`: {}", let_stmt.pattern, err),
+                );
+            }
+            for (name, bound) in bindings {
+                env.borrow_mut().set(name, bound);
+            }
+            ObjectType::Null
+        }
+        Statement::Return(ret) => {
+            ObjectType::Return(Box::new(check!(eval_expression(&ret.return_value, env))))
+        }
+        Statement::Break => ObjectType::Break,
+        // blocks and generators handle their own, this one is outside of any
+        Statement::Defer(_) => ObjectType::error(
+            "SyntaxError",
+            "'defer' can only be used inside a block or function body",
+        ),
+        Statement::Continue => ObjectType::Continue,
+        Statement::Assert(assert) => assertion::eval_assert(assert, env),
+        Statement::Struct(struct_stmt) => {
+            let struct_type = StructType {
+                name: struct_stmt.name.clone(),
+                fields: struct_stmt.fields.clone(),
+                methods: RefCell::new(HashMap::new()),
+                traits: RefCell::new(vec![]),
+                invariants: struct_stmt.invariants.clone(),
+            };
+            env.borrow_mut().set(
+                struct_stmt.name.clone(),
+                ObjectType::Struct(Rc::new(struct_type)),
+            );
+            ObjectType::Null
+        }
+        Statement::Enum(enum_stmt) => {
+            let enum_type = EnumType {
+                name: enum_stmt.name.clone(),
+                variants: enum_stmt
+                    .variants
+                    .iter()
+                    .map(|v| (v.name.clone(), v.fields.clone()))
+                    .collect(),
+                methods: RefCell::new(HashMap::new()),
+                traits: RefCell::new(vec![]),
+            };
+            env.borrow_mut()
+                .set(enum_stmt.name.clone(), ObjectType::Enum(Rc::new(enum_type)));
+            ObjectType::Null
+        }
+        Statement::Trait(trait_stmt) => eval_trait_statement(trait_stmt, env),
+        Statement::Impl(impl_stmt) => eval_impl_statement(impl_stmt, env),
+        Statement::Pub(inner) => eval(inner, env),
+        Statement::Import(import) => {
+            let module = match module::import(&import.path) {
+                Ok(module) => module,
+                Err(thrown) => return thrown,
+            };
+            if import.names.is_empty() {
+                let alias = import
+                    .alias
+                    .clone()
+                    .unwrap_or_else(|| module::default_alias(&import.path));
+                env.borrow_mut().set(alias, ObjectType::Module(module));
+            } else {
+                for name in &import.names {
+                    let value = match module.get(name) {
+                        Some(value) => value,
+                        None => {
+                            return ObjectType::error(
+                                "ImportError",
+                                format!("Module \"{}\" does not export '{}'", module.name, name),
+                            );
+                        }
+                    };
+                    env.borrow_mut().set(name.clone(), value);
+                }
+            }
+            ObjectType::Null
+        }
+        // the function is named after the operator, so evaluating it binds the symbol
+        Statement::Infix(infix) => {
+            check!(eval_expression(&infix.function, env));
+            ObjectType::Null
+        }
+        // top-level ones are taken out by `macros::expand` before anything runs
+        Statement::Macro(definition) => ObjectType::error(
+            "SyntaxError",
+            format!("Macro '{}' must be declared at the top level", definition.name),
+        ),
+    }
+}
+
+fn eval_trait_statement(trait_stmt: &TraitStatement, env: &Env) -> ObjectType {
+    let provided = trait_stmt
+        .provided
+        .iter()
+        .filter_map(|method| method_closure(method, env))
+        .collect();
+    let trait_type = TraitType {
+        name: trait_stmt.name.clone(),
+        required: trait_stmt.required.clone(),
+        provided,
+    };
+    env.borrow_mut()
+        .set(trait_stmt.name.clone(), ObjectType::Trait(Rc::new(trait_type)));
+    ObjectType::Null
+}
+
+// adds the methods to the struct or enum type, so existing values pick them up too
+fn eval_impl_statement(impl_stmt: &ImplStatement, env: &Env) -> ObjectType {
+    let target = check!(eval_identifier(&impl_stmt.name, env));
+    let (methods, traits) = match &target {
+        ObjectType::Struct(struct_type) => (&struct_type.methods, &struct_type.traits),
+        ObjectType::Enum(enum_type) => (&enum_type.methods, &enum_type.traits),
+        other => {
+            return ObjectType::error(
+                "TypeError",
+                format!("Cannot impl a value that is not a struct or enum: {}", other.inspect()),
+            );
+        }
+    };
+
+    let functions: Vec<(String, ObjectType)> = impl_stmt
+        .methods
+        .iter()
+        .filter_map(|method| method_closure(method, env))
+        .collect();
+
+    if let Some(trait_name) = &impl_stmt.trait_name {
+        let trait_type = match env.borrow().get(trait_name).or_else(|| builtins::lookup_trait(trait_name)) {
+            Some(ObjectType::Trait(trait_type)) => trait_type,
+            Some(other) => {
+                return ObjectType::error("TypeError", format!("{} is not a trait", other.inspect()));
+            }
+            None => return ObjectType::error("NameError", format!("Unknown trait: {}", trait_name)),
+        };
+
+        for (name, _) in &functions {
+            let in_trait = trait_type.required.contains(name)
+                || trait_type.provided.iter().any(|(provided, _)| provided == name);
+            if !in_trait {
+                return ObjectType::error(
+                    "TypeError",
+                    format!("Method '{}' is not a member of trait {}", name, trait_type.name),
+                );
+            }
+        }
+        for required in &trait_type.required {
+            if !functions.iter().any(|(name, _)| name == required) {
+                return ObjectType::error(
+                    "TypeError",
+                    format!(
+                        "{} does not implement '{}' required by trait {}",
+                        impl_stmt.name, required, trait_type.name
+                    ),
+                );
+            }
+        }
+
+        // defaults only fill in what the impl leaves out
+        for (name, default) in &trait_type.provided {
+            if !functions.iter().any(|(method, _)| method == name) {
+                methods.borrow_mut().insert(name.clone(), default.clone());
+            }
+        }
+        traits.borrow_mut().push(trait_type.name.clone());
+    }
+
+    for (name, function) in functions {
+        methods.borrow_mut().insert(name, function);
+    }
+
+    ObjectType::Null
+}
+
+// turns a named `fun` from an impl or trait body into a closure over `env`
+fn method_closure(method: &Expression, env: &Env) -> Option<(String, ObjectType)> {
+    if let Expression::FUNCTION {
+        name: Some(name),
+        parameters,
+        rest,
+        body,
+        generator,
+        contract,
+        ..
+    } = method
+    {
+        let function = ObjectType::Function {
+            name: Some(name.clone()),
+            parameters: parameters.clone(),
+            rest: rest.clone(),
+            body: body.clone(),
+            env: env.clone(),
+            generator: *generator,
+            contract: Rc::new(contract.clone()),
+        };
+        return Some((name.clone(), function));
+    }
+    None
+}
+
+// evaluates statements in order, stopping early when a `ret`, `break`, `continue` or throw is hit.
+// The block's `defer`red expressions run on the way out however it is left
+pub(crate) fn eval_block(statements: &[Statement], env: &Env) -> ObjectType {
+    let mut result = ObjectType::Null;
+    let mut deferred = vec![];
+
+    for statement in statements {
+        if let Statement::Defer(expr) = statement {
+            deferred.push(expr.clone());
+            result = ObjectType::Null;
+            continue;
+        }
+        result = eval(statement, env);
+        if result.is_unwinding() {
+            break;
+        }
     }
+
+    run_deferred(&deferred, env, result)
+}
+
+// runs deferred expressions last to first, an error one of them throws is chained onto
+// the error already unwinding or, if there is none, becomes the block's result
+pub(crate) fn run_deferred(deferred: &[Expression], env: &Env, mut result: ObjectType) -> ObjectType {
+    for expr in deferred.iter().rev() {
+        let thrown = match eval_expression(expr, env) {
+            ObjectType::Throw(thrown) => *thrown,
+            value if value.is_unwinding() => ObjectType::error_value(
+                "SyntaxError",
+                "Deferred code cannot 'ret', 'break' or 'continue'",
+            ),
+            _ => continue,
+        };
+        result = match result {
+            ObjectType::Throw(original) => {
+                // a thrown plain value becomes `error(value)` so the later error has somewhere to go
+                let mut original = match *original {
+                    error @ ObjectType::Error(_) => error,
+                    value => ObjectType::error_value("Error", value.inspect()),
+                };
+                if let ObjectType::Error(error) = &mut original {
+                    error.suppressed.push(thrown);
+                }
+                ObjectType::Throw(Box::new(original))
+            }
+            _ => ObjectType::Throw(Box::new(thrown)),
+        };
+    }
+    result
 }
 
-fn eval_expression(expr: Expression) -> ObjectType {
+pub(crate) fn eval_expression(expr: &Expression, env: &Env) -> ObjectType {
     match expr {
-        Expression::INT(val) => ObjectType::Number(val),
-        Expression::BOOLEAN(val) => bool_to_bool_object(val),
+        Expression::INT(val) => ObjectType::Number(*val),
+        Expression::BOOLEAN(val) => bool_to_bool_object(*val),
+        Expression::IDENT(name) => eval_identifier(name, env),
         Expression::INFEX {
             left,
             operator,
             right,
-        } => eval_infex_expression(*left, operator, *right),
-        Expression::PREFIX { operator, right } => eval_prefex_expression(operator, *right),
-        _ => panic!("Unknown expression type"),
+            ..
+        } => eval_infex_expression(left, operator, right, env),
+        Expression::PREFIX { operator, right } => eval_prefex_expression(operator, right, env),
+        Expression::IF {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            if is_truthy(&check!(eval_expression(condition, env))) {
+                eval_block(consequence, env)
+            } else if let Some(alternative) = alternative {
+                eval_block(alternative, env)
+            } else {
+                ObjectType::Null
+            }
+        }
+        Expression::IFLET {
+            pattern,
+            value,
+            consequence,
+            alternative,
+        } => {
+            let value = check!(eval_expression(value, env));
+            let mut bindings = vec![];
+            if match_pattern(pattern, &value, &mut bindings, env).is_ok() {
+                let scope = Environment::new_enclosed(env.clone());
+                for (name, bound) in bindings {
+                    scope.borrow_mut().set(name, bound);
+                }
+                eval_block(consequence, &scope)
+            } else if let Some(alternative) = alternative {
+                eval_block(alternative, env)
+            } else {
+                ObjectType::Null
+            }
+        }
+        Expression::FUNCTION {
+            name,
+            parameters,
+            rest,
+            body,
+            generator,
+            contract,
+            ..
+        } => {
+            let function = ObjectType::Function {
+                name: name.clone(),
+                parameters: parameters.clone(),
+                rest: rest.clone(),
+                body: body.clone(),
+                env: env.clone(),
+                generator: *generator,
+                contract: Rc::new(contract.clone()),
+            };
+            // named functions are also bound in the scope they are declared in
+            if let Some(name) = name {
+                env.borrow_mut().set(name.clone(), function.clone());
+            }
+            function
+        }
+        Expression::CALL {
+            function,
+            arguments,
+            named,
+            span,
+        } => {
+            match &**function {
+                Expression::IDENT(name) if name == "quote" => {
+                    return macros::eval_quote(arguments, env);
+                }
+                Expression::IDENT(name) if name == "unquote" => {
+                    return ObjectType::error(
+                        "SyntaxError",
+                        "'unquote' can only be used inside 'quote'",
+                    );
+                }
+                _ => {}
+            }
+            // `p.len()` passes `p` as the first argument to the struct's method
+            let (function, receiver) = match &**function {
+                Expression::FIELD { object, field } => {
+                    match eval_method_lookup(check!(eval_expression(object, env)), field) {
+                        Ok(found) => found,
+                        Err(thrown) => return thrown,
+                    }
+                }
+                _ => (check!(eval_expression(function, env)), None),
+            };
+            // `f(1, _)` leaves the call for later
+            if arguments.contains(&Expression::PLACEHOLDER) {
+                return eval_partial(function, receiver, arguments, named, env);
+            }
+            let mut arguments = match eval_list(arguments, env) {
+                Ok(arguments) => arguments,
+                Err(thrown) => return thrown,
+            };
+            if let Some(receiver) = receiver {
+                arguments.insert(0, receiver);
+            }
+            let mut named_values = vec![];
+            for (name, arg) in named {
+                named_values.push((name.clone(), check!(eval_expression(arg, env))));
+            }
+            call_function(function, arguments, named_values, Some(*span))
+        }
+        Expression::PLACEHOLDER => ObjectType::error(
+            "SyntaxError",
+            "'_' can only stand for an argument of a call",
+        ),
+        // `(<+>)` is the declared operator's function
+        Expression::OPERATOR(operator) => match env.borrow().get(operator) {
+            Some(function) => function,
+            None => ObjectType::Operator(operator.clone()),
+        },
+        Expression::PIPE {
+            value,
+            function,
+            span,
+        } => {
+            let value = check!(eval_expression(value, env));
+            let function = check!(eval_expression(function, env));
+            call_function(function, vec![value], vec![], Some(*span))
+        }
+        Expression::SPREAD(_) => ObjectType::error(
+            "TypeError",
+            "'...' is only allowed in call arguments and array literals",
+        ),
+        Expression::STRING(val) => ObjectType::String(val.clone()),
+        Expression::ATOM(name) => ObjectType::atom(name),
+        Expression::INTERPOLATION(parts) => {
+            let mut out = String::new();
+            for part in parts {
+                out.push_str(&Object::to_string(&check!(eval_expression(part, env))));
+            }
+            ObjectType::String(out)
+        }
+        Expression::ARRAY(elements) => match eval_list(elements, env) {
+            Ok(elements) => ObjectType::Array(elements),
+            Err(thrown) => thrown,
+        },
+        Expression::TUPLE(elements) => {
+            let mut values = vec![];
+            for element in elements {
+                values.push(check!(eval_expression(element, env)));
+            }
+            ObjectType::Tuple(values)
+        }
+        Expression::SET(elements) => match eval_list(elements, env) {
+            Ok(elements) => match elements.into_iter().map(set_element).collect() {
+                Ok(set) => ObjectType::set(set),
+                Err(err) => err,
+            },
+            Err(thrown) => thrown,
+        },
+        Expression::MAP(pairs) => {
+            let mut map = BTreeMap::new();
+            for (key, value) in pairs {
+                let key = check!(eval_expression(key, env));
+                let hash_key = match HashKey::from_object(&key) {
+                    Some(hash_key) => hash_key,
+                    None => {
+                        return ObjectType::error(
+                            "TypeError",
+                            format!("Unusable as map key: {}", key.inspect()),
+                        );
+                    }
+                };
+                map.insert(hash_key, check!(eval_expression(value, env)));
+            }
+            ObjectType::Map(map)
+        }
+        Expression::LISTCOMP { element, clauses } => {
+            let mut elements = vec![];
+            let scope = Environment::new_enclosed(env.clone());
+            let result = eval_comprehension(clauses, &scope, &mut |scope| {
+                elements.push(eval_unwinding(element, scope)?);
+                Ok(())
+            });
+            match result {
+                Ok(()) => ObjectType::Array(elements),
+                Err(thrown) => thrown,
+            }
+        }
+        Expression::MAPCOMP {
+            key,
+            value,
+            clauses,
+        } => {
+            let mut map = BTreeMap::new();
+            let scope = Environment::new_enclosed(env.clone());
+            let result = eval_comprehension(clauses, &scope, &mut |scope| {
+                let key = eval_unwinding(key, scope)?;
+                let hash_key = HashKey::from_object(&key).ok_or_else(|| {
+                    ObjectType::error("TypeError", format!("Unusable as map key: {}", key.inspect()))
+                })?;
+                map.insert(hash_key, eval_unwinding(value, scope)?);
+                Ok(())
+            });
+            match result {
+                Ok(()) => ObjectType::Map(map),
+                Err(thrown) => thrown,
+            }
+        }
+        Expression::INDEX { left, index } => {
+            let left = check!(eval_expression(left, env));
+            let index = check!(eval_expression(index, env));
+            eval_index_expression(left, index)
+        }
+        Expression::MATCH { value, arms } => {
+            let value = check!(eval_expression(value, env));
+            eval_match_expression(&value, arms, env)
+        }
+        Expression::RANGE {
+            start,
+            end,
+            inclusive,
+            step,
+        } => {
+            let mut bounds = vec![];
+            for expr in [start, end, step] {
+                let bound = match expr {
+                    Some(expr) => match check!(eval_expression(expr, env)) {
+                        ObjectType::Number(val) => Some(val),
+                        other => {
+                            return ObjectType::error(
+                                "TypeError",
+                                format!("Range bounds must be numbers, got {}", other.inspect()),
+                            );
+                        }
+                    },
+                    None => None,
+                };
+                bounds.push(bound);
+            }
+            let step = bounds[2].unwrap_or(1);
+            if step == 0 {
+                return ObjectType::error("ValueError", "Range step cannot be zero");
+            }
+            ObjectType::Range {
+                start: bounds[0],
+                end: bounds[1],
+                step,
+                inclusive: *inclusive,
+            }
+        }
+        Expression::FOR {
+            pattern,
+            iterable,
+            body,
+        } => {
+            let iterable = check!(eval_expression(iterable, env));
+            eval_for_expression(pattern, iterable, body, env)
+        }
+        Expression::STRUCT { name, fields } => eval_struct_literal(name, fields, env),
+        Expression::FIELD { object, field } => {
+            let nullsafe = matches!(**object, Expression::NULLSAFE(_));
+            match check!(eval_expression(object, env)) {
+                // `m?.key` is null when the key is missing
+                ObjectType::Map(map) if nullsafe => {
+                    map.get(&HashKey::String(field.clone())).cloned().unwrap_or(ObjectType::Null)
+                }
+                object => eval_field_expression(object, field),
+            }
+        }
+        Expression::ASSIGN { target, value } => {
+            let value = check!(eval_expression(value, env));
+            match &**target {
+                Expression::IDENT(name) => {
+                    if !env.borrow_mut().assign(name, value.clone()) {
+                        return ObjectType::error(
+                            "NameError",
+                            format!("Cannot assign to undefined variable: {}", name),
+                        );
+                    }
+                }
+                Expression::FIELD { object, field } => match check!(eval_expression(object, env)) {
+                    ObjectType::Instance(instance) => {
+                        if !instance.set(field, value.clone()) {
+                            return ObjectType::error(
+                                "FieldError",
+                                format!("{} has no field '{}'", instance.struct_type.name, field),
+                            );
+                        }
+                    }
+                    tuple @ ObjectType::Tuple(_) => {
+                        return ObjectType::error(
+                            "TypeError",
+                            format!("Cannot set element '{}' of {}, tuples are immutable", field, tuple.inspect()),
+                        );
+                    }
+                    other => {
+                        return ObjectType::error(
+                            "TypeError",
+                            format!("Cannot set field '{}' of {}", field, other.inspect()),
+                        );
+                    }
+                },
+                other => {
+                    return ObjectType::error(
+                        "TypeError",
+                        format!("Invalid assignment target: {:?}", other),
+                    );
+                }
+            }
+            value
+        }
+        Expression::TRY(try_expr) => eval_try_expression(try_expr, env),
+        // `x?` unwraps an `Ok` or `Some` and returns an `Err` or `None` from the enclosing function
+        Expression::PROPAGATE(value) => {
+            let value = check!(eval_expression(value, env));
+            match builtins::prelude_parts(&value) {
+                Some(("Ok" | "Some", Some(inner))) => inner,
+                Some(_) => ObjectType::Return(Box::new(value)),
+                None => ObjectType::error(
+                    "TypeError",
+                    format!("The '?' operator needs a Result or Option, got {}", value.inspect()),
+                ),
+            }
+        }
+        Expression::NULL => ObjectType::Null,
+        Expression::NULLSAFE(object) => match check!(eval_expression(object, env)) {
+            ObjectType::Null => ObjectType::ShortCircuit,
+            value => value,
+        },
+        Expression::OPTCHAIN(chain) => match eval_expression(chain, env) {
+            ObjectType::ShortCircuit => ObjectType::Null,
+            value => value,
+        },
+        Expression::THROW(value) => ObjectType::Throw(Box::new(check!(eval_expression(value, env)))),
+        // a generator runs its own `yield` statements, see `generator::Generator`
+        Expression::YIELD(_) => ObjectType::error(
+            "SyntaxError",
+            "'yield' can only be used as a statement of a generator, outside of 'try'",
+        ),
+    }
+}
+
+// runs `emit` once for every combination the clauses let through, each `for` item
+// gets its own scope so none of the loop variables outlive the comprehension
+fn eval_comprehension(
+    clauses: &[Clause],
+    env: &Env,
+    emit: &mut dyn FnMut(&Env) -> Result<(), ObjectType>,
+) -> Result<(), ObjectType> {
+    match clauses.split_first() {
+        None => emit(env),
+        Some((Clause::IF(condition), rest)) => {
+            if is_truthy(&eval_unwinding(condition, env)?) {
+                eval_comprehension(rest, env, emit)?;
+            }
+            Ok(())
+        }
+        Some((Clause::FOR { pattern, iterable }, rest)) => {
+            for item in iterate(eval_unwinding(iterable, env)?)? {
+                let scope = bind_loop_item(pattern, item, env)?;
+                eval_comprehension(rest, &scope, emit)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// evaluates an expression, turning a `ret`, `break`, `continue` or throw into an Err to pass on
+fn eval_unwinding(expr: &Expression, env: &Env) -> Result<ObjectType, ObjectType> {
+    match eval_expression(expr, env) {
+        value if value.is_unwinding() => Err(value),
+        value => Ok(value),
+    }
+}
+
+// `f(1, _)` evaluates everything but the holes, which are filled when the result is called
+fn eval_partial(
+    function: ObjectType,
+    receiver: Option<ObjectType>,
+    arguments: &[Expression],
+    named: &[(String, Expression)],
+    env: &Env,
+) -> ObjectType {
+    let mut values: Vec<Option<ObjectType>> = receiver.into_iter().map(Some).collect();
+    for argument in arguments {
+        match argument {
+            Expression::PLACEHOLDER => values.push(None),
+            _ => match eval_list(std::slice::from_ref(argument), env) {
+                Ok(spread) => values.extend(spread.into_iter().map(Some)),
+                Err(thrown) => return thrown,
+            },
+        }
+    }
+    let mut named_values = vec![];
+    for (name, arg) in named {
+        named_values.push((name.clone(), check!(eval_expression(arg, env))));
+    }
+
+    ObjectType::Partial(Rc::new(Partial {
+        function,
+        arguments: values,
+        named: named_values,
+    }))
+}
+
+// runs `finally` however the body and handler were left, a `ret`, `break` or throw
+// inside it replaces whatever was unwinding
+fn eval_try_expression(try_expr: &TryExpression, env: &Env) -> ObjectType {
+    let result = match (eval_block(&try_expr.body, env), &try_expr.catch_body) {
+        (ObjectType::Throw(thrown), Some(catch_body)) => {
+            let scope = Environment::new_enclosed(env.clone());
+            if let Some(name) = &try_expr.catch_name {
+                scope.borrow_mut().set(name.clone(), *thrown);
+            }
+            eval_block(catch_body, &scope)
+        }
+        (result, _) => result,
+    };
+
+    if let Some(finally) = &try_expr.finally {
+        let cleanup = eval_block(finally, env);
+        if cleanup.is_unwinding() {
+            return cleanup;
+        }
+    }
+
+    result
+}
+
+pub(crate) fn eval_index_expression(left: ObjectType, index: ObjectType) -> ObjectType {
+    match (left, index) {
+        (ObjectType::Array(elements), ObjectType::Number(i)) => {
+            if i < 0 || i as usize >= elements.len() {
+                ObjectType::Null
+            } else {
+                elements[i as usize].clone()
+            }
+        }
+        (
+            value @ (ObjectType::Array(_) | ObjectType::String(_)),
+            ObjectType::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            },
+        ) => slice(value, start, end, step, inclusive),
+        (ObjectType::String(str), ObjectType::Number(i)) => {
+            match usize::try_from(i).ok().and_then(|i| str.chars().nth(i)) {
+                Some(ch) => ObjectType::String(ch.to_string()),
+                None => ObjectType::Null,
+            }
+        }
+        (ObjectType::Map(map), key) => match HashKey::from_object(&key) {
+            Some(hash_key) => map.get(&hash_key).cloned().unwrap_or(ObjectType::Null),
+            None => ObjectType::error(
+                "TypeError",
+                format!("Unusable as map key: {}", key.inspect()),
+            ),
+        },
+        _ => ObjectType::error("TypeError", "Unsupported types for index operator"),
+    }
+}
+
+pub(crate) fn eval_field_expression(object: ObjectType, field: &str) -> ObjectType {
+    match object {
+        ObjectType::Instance(instance) => instance.get(field).unwrap_or_else(|| {
+            ObjectType::error(
+                "FieldError",
+                format!("{} has no field '{}'", instance.struct_type.name, field),
+            )
+        }),
+        ObjectType::Enum(enum_type) => match enum_type.variant_index(field) {
+            Some(index) if enum_type.variants[index].1.is_empty() => {
+                ObjectType::Variant(Rc::new(Variant {
+                    enum_type,
+                    index,
+                    values: vec![],
+                }))
+            }
+            Some(index) => ObjectType::Constructor(enum_type, index),
+            None => enum_type.method(field).unwrap_or_else(|| {
+                ObjectType::error(
+                    "FieldError",
+                    format!("{} has no variant '{}'", enum_type.name, field),
+                )
+            }),
+        },
+        ObjectType::Module(module) => module.get(field).unwrap_or_else(|| {
+            ObjectType::error(
+                "FieldError",
+                format!("Module \"{}\" has no public member '{}'", module.name, field),
+            )
+        }),
+        // `m.key` reads a string key
+        ObjectType::Map(map) => map.get(&HashKey::String(field.to_string())).cloned().unwrap_or_else(|| {
+            ObjectType::error("FieldError", format!("Map has no key '{}'", field))
+        }),
+        // `t.0`
+        ObjectType::Tuple(elements) => match field.parse::<usize>().ok().and_then(|i| elements.get(i)) {
+            Some(element) => element.clone(),
+            None => ObjectType::error(
+                "FieldError",
+                format!("{} has no element '{}'", ObjectType::Tuple(elements.clone()).inspect(), field),
+            ),
+        },
+        // `e.kind`, `e.message`, `e.stack` and `e.suppressed` on a caught error
+        ObjectType::Error(error) => match field {
+            "kind" => ObjectType::String(error.kind),
+            "message" => ObjectType::String(error.message),
+            "stack" => ObjectType::Array(error.stack.into_iter().map(ObjectType::String).collect()),
+            "suppressed" => ObjectType::Array(error.suppressed),
+            _ => ObjectType::error("FieldError", format!("Errors have no field '{}'", field)),
+        },
+        other => ObjectType::error(
+            "TypeError",
+            format!("Cannot read field '{}' of {}", field, other.inspect()),
+        ),
+    }
+}
+
+fn eval_struct_literal(name: &str, fields: &[(String, Expression)], env: &Env) -> ObjectType {
+    let struct_type = match check!(eval_identifier(name, env)) {
+        ObjectType::Struct(struct_type) => struct_type,
+        other => return ObjectType::error("TypeError", format!("{} is not a struct", other.inspect())),
+    };
+
+    let mut values: Vec<Option<ObjectType>> = vec![None; struct_type.fields.len()];
+    for (field, expr) in fields {
+        let index = match struct_type.fields.iter().position(|f| f == field) {
+            Some(index) => index,
+            None => {
+                return ObjectType::error("FieldError", format!("{} has no field '{}'", name, field));
+            }
+        };
+        if values[index].is_some() {
+            return ObjectType::error(
+                "FieldError",
+                format!("Field '{}' of {} is given twice", field, name),
+            );
+        }
+        values[index] = Some(check!(eval_expression(expr, env)));
+    }
+
+    let mut complete = vec![];
+    for (value, field) in values.into_iter().zip(&struct_type.fields) {
+        match value {
+            Some(value) => complete.push(value),
+            None => {
+                return ObjectType::error(
+                    "FieldError",
+                    format!("Missing field '{}' in {} literal", field, name),
+                );
+            }
+        }
+    }
+
+    ObjectType::Instance(Rc::new(Instance {
+        struct_type,
+        fields: RefCell::new(complete),
+    }))
+}
+
+// finds what `object.name(...)` calls and the receiver to pass as `self`, if any
+pub(crate) fn eval_method_lookup(
+    object: ObjectType,
+    name: &str,
+) -> Result<(ObjectType, Option<ObjectType>), ObjectType> {
+    let missing = |message: String| Err(ObjectType::error("FieldError", message));
+
+    match &object {
+        ObjectType::Instance(instance) => {
+            // a field holding a closure is called as is
+            if let Some(value) = instance.get(name) {
+                return Ok((value, None));
+            }
+            match instance.struct_type.method(name) {
+                Some(method) => Ok((method, Some(object))),
+                None => missing(format!("{} has no method '{}'", instance.struct_type.name, name)),
+            }
+        }
+        // `Point.origin()` calls a method without a receiver
+        ObjectType::Struct(struct_type) => match struct_type.method(name) {
+            Some(method) => Ok((method, None)),
+            None => missing(format!("{} has no method '{}'", struct_type.name, name)),
+        },
+        // `Shape.Circle(2)` calls the variant's constructor
+        ObjectType::Enum(enum_type) => match enum_type.variant_index(name) {
+            Some(index) => Ok((ObjectType::Constructor(enum_type.clone(), index), None)),
+            None => match enum_type.method(name) {
+                Some(method) => Ok((method, None)),
+                None => missing(format!("{} has no variant or method '{}'", enum_type.name, name)),
+            },
+        },
+        ObjectType::Variant(variant) => match variant.enum_type.method(name) {
+            Some(method) => Ok((method, Some(object.clone()))),
+            None => missing(format!("{} has no method '{}'", variant.enum_type.name, name)),
+        },
+        ObjectType::Module(module) => match module.get(name) {
+            Some(value) => Ok((value, None)),
+            None => missing(format!("Module \"{}\" has no public member '{}'", module.name, name)),
+        },
+        ObjectType::Iterator(_) if name == "next" => Ok((builtins::lookup(name).unwrap(), Some(object))),
+        ObjectType::Set(_) if matches!(name, "add" | "remove" | "contains") => {
+            Ok((builtins::lookup(name).unwrap(), Some(object)))
+        }
+        other => Err(ObjectType::error(
+            "TypeError",
+            format!("Cannot call method '{}' on {}", name, other.inspect()),
+        )),
+    }
+}
+
+fn eval_for_expression(
+    pattern: &Pattern,
+    iterable: ObjectType,
+    body: &[Statement],
+    env: &Env,
+) -> ObjectType {
+    let items = match iterate(iterable) {
+        Ok(items) => items,
+        Err(thrown) => return thrown,
+    };
+
+    for item in items {
+        let scope = match bind_loop_item(pattern, item, env) {
+            Ok(scope) => scope,
+            Err(thrown) => return thrown,
+        };
+
+        match eval_block(body, &scope) {
+            ObjectType::Break => break,
+            result @ (ObjectType::Return(_) | ObjectType::Throw(_)) => return result,
+            _ => {}
+        }
+    }
+
+    ObjectType::Null
+}
+
+// every iteration gets a fresh scope so the loop variable doesn't leak,
+// an item that is a throw from a generator or adapter is passed on as the error
+pub(crate) fn bind_loop_item(pattern: &Pattern, item: ObjectType, env: &Env) -> Result<Env, ObjectType> {
+    if let ObjectType::Throw(_) = item {
+        return Err(item);
+    }
+
+    let scope = Environment::new_enclosed(env.clone());
+    let mut bindings = vec![];
+    if let Err(err) = match_pattern(pattern, &item, &mut bindings, &scope) {
+        return Err(ObjectType::error(
+            "MatchError",
+            format!("Cannot bind loop variable `{}`: {}", pattern, err),
+        ));
+    }
+    for (name, bound) in bindings {
+        scope.borrow_mut().set(name, bound);
+    }
+    Ok(scope)
+}
+
+// the values a `for` loop walks over, ranges are never materialized and
+// iterators are advanced one item at a time, a Throw item ends the walk with that error
+pub(crate) fn iterate(value: ObjectType) -> Result<Box<dyn Iterator<Item = ObjectType>>, ObjectType> {
+    // an Iterable hands back something we already know how to walk
+    if let Some(iter) = value.trait_method("Iterable", "iter") {
+        return match apply_function(iter, vec![value]) {
+            thrown @ ObjectType::Throw(_) => Err(thrown),
+            items => iterate(items),
+        };
+    }
+
+    // an Iterator is asked for `{"value": ..., "done": ...}` until it is done
+    if let Some(next) = value.trait_method("Iterator", "next") {
+        let mut done = false;
+        return Ok(Box::new(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let step = match apply_function(next.clone(), vec![value.clone()]) {
+                thrown @ ObjectType::Throw(_) => Err(thrown),
+                step => builtins::read_step(&step),
+            };
+            match step {
+                Ok(Some(item)) => Some(item),
+                Ok(None) => {
+                    done = true;
+                    None
+                }
+                Err(thrown) => {
+                    done = true;
+                    Some(thrown)
+                }
+            }
+        })));
+    }
+
+    match value {
+        ObjectType::Array(elements) => Ok(Box::new(elements.into_iter())),
+        ObjectType::String(str) => {
+            let chars: Vec<char> = str.chars().collect();
+            Ok(Box::new(chars.into_iter().map(|ch| ObjectType::String(ch.to_string()))))
+        }
+        // walks the elements the set had when the loop started
+        ObjectType::Set(set) => {
+            let elements = set.borrow().clone();
+            Ok(Box::new(elements.into_iter().map(|element| element.to_object())))
+        }
+        ObjectType::Map(map) => Ok(Box::new(
+            map.into_iter()
+                .map(|(key, value)| ObjectType::Array(vec![key.to_object(), value])),
+        )),
+        ObjectType::Range {
+            start,
+            end,
+            step,
+            inclusive,
+        } => match start {
+            Some(start) => Ok(Box::new(range_iter(start, end, step, inclusive).map(ObjectType::Number))),
+            None => Err(ObjectType::error("ValueError", "Cannot iterate a range without a start")),
+        },
+        ObjectType::Iterator(iterator) => Ok(Box::new(std::iter::from_fn(move || iterator.next()))),
+        other => Err(ObjectType::error(
+            "TypeError",
+            format!("Cannot iterate over {}", other.inspect()),
+        )),
+    }
+}
+
+// `arr[1..3]` and `s[..2]`, bounds are clamped to the value's length
+fn slice(
+    value: ObjectType,
+    start: Option<i64>,
+    end: Option<i64>,
+    step: i64,
+    inclusive: bool,
+) -> ObjectType {
+    if step < 0 {
+        return ObjectType::error("ValueError", "Cannot slice with a negative step");
+    }
+
+    let len = match &value {
+        ObjectType::Array(elements) => elements.len(),
+        ObjectType::String(str) => str.chars().count(),
+        _ => 0,
+    };
+    let clamp = |i: i64| i.clamp(0, len as i64) as usize;
+    let start = clamp(start.unwrap_or(0));
+    let end = match end {
+        Some(end) if inclusive => clamp(end.saturating_add(1)),
+        Some(end) => clamp(end),
+        None => len,
+    };
+    let indices = (start..end.max(start)).step_by(step as usize);
+
+    match value {
+        ObjectType::Array(elements) => {
+            ObjectType::Array(indices.map(|i| elements[i].clone()).collect())
+        }
+        ObjectType::String(str) => {
+            let chars: Vec<char> = str.chars().collect();
+            ObjectType::String(indices.map(|i| chars[i]).collect())
+        }
+        other => other,
+    }
+}
+
+// a set holds the values that can be map keys
+pub(crate) fn set_element(value: ObjectType) -> Result<HashKey, ObjectType> {
+    HashKey::from_object(&value)
+        .ok_or_else(|| ObjectType::error("TypeError", format!("Unusable as set element: {}", value.inspect())))
+}
+
+// `x in xs` for ranges, arrays, sets, map keys and substrings
+fn eval_in_expression(item: ObjectType, container: ObjectType) -> ObjectType {
+    let contained = match (item, container) {
+        (
+            ObjectType::Number(val),
+            ObjectType::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            },
+        ) => range_contains(start, end, step, inclusive, val),
+        (item, ObjectType::Array(elements)) => elements.contains(&item),
+        (item, ObjectType::Map(map)) => match HashKey::from_object(&item) {
+            Some(key) => map.contains_key(&key),
+            None => false,
+        },
+        (item, ObjectType::Set(set)) => match HashKey::from_object(&item) {
+            Some(key) => set.borrow().contains(&key),
+            None => false,
+        },
+        (ObjectType::String(needle), ObjectType::String(haystack)) => haystack.contains(&needle),
+        (item, container) => {
+            return ObjectType::error(
+                "TypeError",
+                format!(
+                    "Unsupported types for 'in' operator: {} in {}",
+                    item.inspect(),
+                    container.inspect()
+                ),
+            );
+        }
+    };
+    bool_to_bool_object(contained)
+}
+
+fn eval_match_expression(value: &ObjectType, arms: &[MatchArm], env: &Env) -> ObjectType {
+    match select_arm(value, arms, env) {
+        Ok((arm, scope)) => eval_block(&arm.body, &scope),
+        Err(thrown) => thrown,
+    }
+}
+
+// the first arm whose pattern matches and whose guard holds, with the scope its body runs in
+pub(crate) fn select_arm<'a>(
+    value: &ObjectType,
+    arms: &'a [MatchArm],
+    env: &Env,
+) -> Result<(&'a MatchArm, Env), ObjectType> {
+    for arm in arms {
+        let mut bindings = vec![];
+        if match_pattern(&arm.pattern, value, &mut bindings, env).is_err() {
+            continue;
+        }
+
+        // bindings only live for the arm
+        let scope = Environment::new_enclosed(env.clone());
+        for (name, bound) in bindings {
+            scope.borrow_mut().set(name, bound);
+        }
+
+        if let Some(guard) = &arm.guard {
+            match eval_expression(guard, &scope) {
+                guard if guard.is_unwinding() => return Err(guard),
+                guard if !is_truthy(&guard) => continue,
+                _ => {}
+            }
+        }
+
+        return Ok((arm, scope));
+    }
+
+    Err(ObjectType::error(
+        "MatchError",
+        format!("No match arm matched value: {}", value.inspect()),
+    ))
+}
+
+// evaluates an expression inside a pattern, a throw there makes the pattern fail
+fn eval_pattern_expression(expr: &Expression, env: &Env) -> Result<ObjectType, String> {
+    match eval_expression(expr, env) {
+        ObjectType::Throw(thrown) => Err(thrown.inspect()),
+        value => Ok(value),
+    }
+}
+
+// checks `value` against `pattern`, collecting the names it binds or describing why it didn't fit
+pub(crate) fn match_pattern(
+    pattern: &Pattern,
+    value: &ObjectType,
+    bindings: &mut Vec<(String, ObjectType)>,
+    env: &Env,
+) -> Result<(), String> {
+    match pattern {
+        Pattern::WILDCARD => Ok(()),
+        Pattern::BINDING(name) => {
+            bindings.push((name.clone(), value.clone()));
+            Ok(())
+        }
+        Pattern::LITERAL(literal) => {
+            let expected = eval_pattern_expression(literal, env)?;
+            if expected == *value {
+                Ok(())
+            } else {
+                Err(format!("Expected {}, got {}", expected.inspect(), value.inspect()))
+            }
+        }
+        Pattern::RANGE {
+            start,
+            end,
+            inclusive,
+        } => {
+            let bounds = (eval_pattern_expression(start, env)?, eval_pattern_expression(end, env)?);
+            let contained = match (bounds, value) {
+                ((ObjectType::Number(start), ObjectType::Number(end)), ObjectType::Number(val)) => {
+                    if *inclusive {
+                        (start..=end).contains(val)
+                    } else {
+                        (start..end).contains(val)
+                    }
+                }
+                _ => false,
+            };
+            if contained {
+                Ok(())
+            } else {
+                Err(format!("Expected a number in {}, got {}", pattern, value.inspect()))
+            }
+        }
+        Pattern::OR(alternatives) => {
+            for alternative in alternatives {
+                // an alternative that fails part way must not leave bindings behind
+                let mut attempt = vec![];
+                if match_pattern(alternative, value, &mut attempt, env).is_ok() {
+                    bindings.extend(attempt);
+                    return Ok(());
+                }
+            }
+            Err(format!("Expected {}, got {}", pattern, value.inspect()))
+        }
+        Pattern::DEFAULT { pattern, .. } => match_pattern(pattern, value, bindings, env),
+        // annotations are for `ember check`, they are not enforced at runtime
+        Pattern::TYPED { pattern, .. } => match_pattern(pattern, value, bindings, env),
+        Pattern::VARIANT {
+            enum_name,
+            variant,
+            fields,
+        } => {
+            let enum_type = match eval_pattern_expression(&Expression::IDENT(enum_name.clone()), env)? {
+                ObjectType::Enum(enum_type) => enum_type,
+                other => return Err(format!("{} is not an enum", other.inspect())),
+            };
+            let index = enum_type
+                .variant_index(variant)
+                .ok_or_else(|| format!("{} has no variant '{}'", enum_name, variant))?;
+            if enum_type.variants[index].1.len() != fields.len() {
+                return Err(format!(
+                    "{}.{} has {} fields, the pattern has {}",
+                    enum_name,
+                    variant,
+                    enum_type.variants[index].1.len(),
+                    fields.len()
+                ));
+            }
+
+            match value {
+                ObjectType::Variant(value)
+                    if Rc::ptr_eq(&value.enum_type, &enum_type) && value.index == index =>
+                {
+                    for (pattern, field) in fields.iter().zip(&value.values) {
+                        match_pattern(pattern, field, bindings, env)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(format!("Expected {}, got {}", pattern, value.inspect())),
+            }
+        }
+        Pattern::ARRAY {
+            before,
+            rest,
+            after,
+        } => {
+            let elements = match value {
+                ObjectType::Array(elements) => elements,
+                _ => return Err(format!("Expected an array, got {}", value.inspect())),
+            };
+
+            let required = before
+                .iter()
+                .filter(|p| !matches!(p, Pattern::DEFAULT { .. }))
+                .count()
+                + after.len();
+            let length_error = || {
+                if rest.is_some() {
+                    format!(
+                        "Expected an array of at least {} elements, got {}",
+                        required,
+                        elements.len()
+                    )
+                } else {
+                    format!(
+                        "Expected an array of {} elements, got {}",
+                        required,
+                        elements.len()
+                    )
+                }
+            };
+
+            if elements.len() < after.len() {
+                return Err(length_error());
+            }
+            // everything in front of the `after` patterns belongs to `before` and the rest
+            let head = &elements[..elements.len() - after.len()];
+            if rest.is_none() && head.len() > before.len() {
+                return Err(length_error());
+            }
+
+            for (i, pattern) in before.iter().enumerate() {
+                match (head.get(i), pattern) {
+                    (Some(element), _) => match_pattern(pattern, element, bindings, env)?,
+                    (None, Pattern::DEFAULT { pattern, default }) => {
+                        let default = eval_pattern_expression(default, env)?;
+                        match_pattern(pattern, &default, bindings, env)?
+                    }
+                    (None, _) => return Err(length_error()),
+                }
+            }
+            for (pattern, element) in after.iter().zip(&elements[head.len()..]) {
+                match_pattern(pattern, element, bindings, env)?;
+            }
+
+            if let Some(name) = rest
+                && name != "_"
+            {
+                let middle = head.get(before.len()..).unwrap_or(&[]).to_vec();
+                bindings.push((name.clone(), ObjectType::Array(middle)));
+            }
+            Ok(())
+        }
+        Pattern::TUPLE(patterns) => match value {
+            ObjectType::Tuple(elements) if elements.len() == patterns.len() => {
+                for (pattern, element) in patterns.iter().zip(elements) {
+                    match_pattern(pattern, element, bindings, env)?;
+                }
+                Ok(())
+            }
+            _ => Err(format!(
+                "Expected a tuple of {} elements, got {}",
+                patterns.len(),
+                value.inspect()
+            )),
+        },
+        Pattern::MAP(entries) => {
+            let map = match value {
+                ObjectType::Map(map) => map,
+                _ => return Err(format!("Expected a map, got {}", value.inspect())),
+            };
+
+            for (key, pattern) in entries {
+                match (map.get(&HashKey::String(key.clone())), pattern) {
+                    (Some(entry), _) => match_pattern(pattern, entry, bindings, env)?,
+                    (None, Pattern::DEFAULT { pattern, default }) => {
+                        let default = eval_pattern_expression(default, env)?;
+                        match_pattern(pattern, &default, bindings, env)?
+                    }
+                    (None, _) => {
+                        return Err(format!("Missing key \"{}\" in {}", key, value.inspect()));
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn eval_identifier(name: &str, env: &Env) -> ObjectType {
+    if let Some(value) = env.borrow().get(name) {
+        return value;
+    }
+    let builtin = builtins::lookup(name)
+        .or_else(|| builtins::lookup_trait(name))
+        .or_else(|| builtins::lookup_prelude(name));
+    match builtin {
+        Some(builtin) => builtin,
+        None => ObjectType::error("NameError", format!("Unknown identifier: {}", name)),
+    }
+}
+
+// evaluates list elements, splicing in the contents of `...array`
+fn eval_list(elements: &[Expression], env: &Env) -> Result<Vec<ObjectType>, ObjectType> {
+    let mut values = vec![];
+
+    for element in elements {
+        let value = match element {
+            Expression::SPREAD(inner) => inner,
+            _ => element,
+        };
+        let value = match eval_expression(value, env) {
+            value if value.is_unwinding() => return Err(value),
+            value => value,
+        };
+        match (element, value) {
+            (Expression::SPREAD(_), ObjectType::Array(items)) => values.extend(items),
+            (Expression::SPREAD(_), other) => {
+                return Err(ObjectType::error(
+                    "TypeError",
+                    format!("Cannot spread non-array value: {}", other.inspect()),
+                ));
+            }
+            (_, value) => values.push(value),
+        }
+    }
+
+    Ok(values)
+}
+
+pub fn apply_function(function: ObjectType, arguments: Vec<ObjectType>) -> ObjectType {
+    call_function(function, arguments, vec![], None)
+}
+
+// calls `function`, reporting argument errors against the call site when there is one
+pub(crate) fn call_function(
+    function: ObjectType,
+    arguments: Vec<ObjectType>,
+    named: Vec<(String, ObjectType)>,
+    span: Option<Span>,
+) -> ObjectType {
+    let at = |err: String| at_span(err, span);
+
+    match function {
+        ObjectType::Function {
+            name,
+            parameters,
+            rest,
+            body,
+            env,
+            generator,
+            contract,
+        } => {
+            let scope = Environment::new_enclosed(env.clone());
+            if let Err(thrown) = bind_arguments(&parameters, rest.as_deref(), arguments, named, span, &scope) {
+                return thrown;
+            }
+
+            let contracts = CONTRACTS.with(Cell::get);
+            let function_name = name.as_deref().unwrap_or("<anonymous>");
+            if contracts {
+                let failed = format!("Precondition of {}", function_name);
+                if let Err(thrown) = check_clauses(&failed, &contract.requires, &scope, &parameters, &rest, &scope) {
+                    return thrown;
+                }
+            }
+
+            // the body only starts running when the generator is first advanced
+            if generator {
+                let name = format!("generator {}", name.as_deref().unwrap_or("<anonymous>"));
+                return ObjectType::iterator(name, Generator::new(body, scope));
+            }
+
+            let result = match eval_block(&body, &scope) {
+                ObjectType::Return(value) => *value,
+                ObjectType::Break | ObjectType::Continue => ObjectType::error(
+                    "SyntaxError",
+                    "'break' and 'continue' can only be used inside a loop",
+                ),
+                // record the call on the way out so the error carries a stack trace
+                ObjectType::Throw(mut thrown) => {
+                    if let ObjectType::Error(error) = &mut *thrown {
+                        let name = name.as_deref().unwrap_or("<anonymous>");
+                        error.stack.push(match span {
+                            Some(span) => format!("{} at {}", name, span),
+                            None => name.to_string(),
+                        });
+                    }
+                    ObjectType::Throw(thrown)
+                }
+                value => value,
+            };
+            if !contracts || result.is_unwinding() {
+                return result;
+            }
+            match check_contract(function_name, &contract, &env, &parameters, &rest, &scope, result) {
+                Ok(result) => result,
+                Err(thrown) => thrown,
+            }
+        }
+        ObjectType::Constructor(enum_type, index) => {
+            let (variant, fields) = &enum_type.variants[index];
+            if !named.is_empty() {
+                return ObjectType::error(
+                    "ArgumentError",
+                    at(format!("{}.{} does not take named arguments", enum_type.name, variant)),
+                );
+            }
+            if fields.len() != arguments.len() {
+                return ObjectType::error(
+                    "ArgumentError",
+                    at(format!(
+                        "Wrong number of arguments to {}.{}: expected {}, got {}",
+                        enum_type.name,
+                        variant,
+                        fields.len(),
+                        arguments.len()
+                    )),
+                );
+            }
+            ObjectType::Variant(Rc::new(Variant {
+                enum_type,
+                index,
+                values: arguments,
+            }))
+        }
+        ObjectType::Operator(operator) => {
+            if !named.is_empty() || arguments.len() != 2 {
+                return ObjectType::error(
+                    "ArgumentError",
+                    at(format!(
+                        "The operator ({}) takes 2 arguments, got {}",
+                        operator,
+                        arguments.len() + named.len()
+                    )),
+                );
+            }
+            let mut arguments = arguments.into_iter();
+            apply_operator(&operator, arguments.next().unwrap(), arguments.next().unwrap())
+        }
+        // the arguments fill the holes in order, any left over come after the bound ones
+        ObjectType::Partial(partial) => {
+            let holes = partial.arguments.iter().filter(|arg| arg.is_none()).count();
+            if arguments.len() < holes {
+                return ObjectType::error(
+                    "ArgumentError",
+                    at(format!(
+                        "Wrong number of arguments to {}: expected at least {}, got {}",
+                        ObjectType::Partial(partial.clone()).inspect(),
+                        holes,
+                        arguments.len()
+                    )),
+                );
+            }
+            let mut arguments = arguments.into_iter();
+            let mut filled: Vec<ObjectType> = partial
+                .arguments
+                .iter()
+                .map(|arg| arg.clone().unwrap_or_else(|| arguments.next().unwrap()))
+                .collect();
+            filled.extend(arguments);
+            let mut all_named = partial.named.clone();
+            all_named.extend(named);
+            call_function(partial.function.clone(), filled, all_named, span)
+        }
+        ObjectType::Builtin(name, func) => {
+            if !named.is_empty() {
+                return ObjectType::error(
+                    "ArgumentError",
+                    at(format!("Builtin '{}' does not take named arguments", name)),
+                );
+            }
+            func(arguments)
+        }
+        other => ObjectType::error("TypeError", at(format!("Not a function: {}", other.inspect()))),
     }
 }
 
-fn eval_prefex_expression(operator: String, right: Expression) -> ObjectType {
-    let right = eval_expression(right);
+// checks a call's `ensures` clauses and, for a method, its struct's invariants,
+// `scope` is the call's scope with the arguments bound
+fn check_contract(
+    name: &str,
+    contract: &Contract,
+    env: &Env,
+    parameters: &[Pattern],
+    rest: &Option<String>,
+    scope: &Env,
+    result: ObjectType,
+) -> Result<ObjectType, ObjectType> {
+    if !contract.ensures.is_empty() {
+        let check = Environment::new_enclosed(scope.clone());
+        check.borrow_mut().set("result".to_string(), result.clone());
+        let failed = format!("Postcondition of {}", name);
+        let mut shown = parameters.to_vec();
+        shown.push(Pattern::BINDING("result".to_string()));
+        check_clauses(&failed, &contract.ensures, &check, &shown, rest, &check)?;
+    }
+
+    // a method is a function whose first parameter is `self`
+    let receiver = match parameters.first() {
+        Some(param) if parameter_name(param) == Some("self") => scope.borrow().get("self"),
+        _ => None,
+    };
+    if let Some(ObjectType::Instance(instance)) = receiver {
+        let invariants = &instance.struct_type.invariants;
+        if !invariants.is_empty() {
+            let check = Environment::new_enclosed(env.clone());
+            check
+                .borrow_mut()
+                .set("self".to_string(), ObjectType::Instance(instance.clone()));
+            let failed = format!("Invariant of {} after {}", instance.struct_type.name, name);
+            check_clauses(&failed, invariants, &check, parameters, rest, scope)?;
+        }
+    }
+    Ok(result)
+}
 
-    match operator.as_str() {
+// evaluates each clause in `env`, the first one that is not truthy fails with its source
+// text and the values of the `shown` parameters as they are in `values`
+fn check_clauses(
+    failed: &str,
+    clauses: &[Expression],
+    env: &Env,
+    shown: &[Pattern],
+    rest: &Option<String>,
+    values: &Env,
+) -> Result<(), ObjectType> {
+    for clause in clauses {
+        match eval_expression(clause, env) {
+            thrown @ ObjectType::Throw(_) => return Err(thrown),
+            value if is_truthy(&value) => {}
+            _ => {
+                let names = shown
+                    .iter()
+                    .filter_map(parameter_name)
+                    .chain(rest.as_deref());
+                let arguments: Vec<String> = names
+                    .filter_map(|name| {
+                        let value = values.borrow().get(name)?;
+                        Some(format!("{} = {}", name, value.inspect()))
+                    })
+                    .collect();
+                let mut message = format!("{} failed: {}", failed, clause);
+                if !arguments.is_empty() {
+                    message.push_str(&format!(" ({})", arguments.join(", ")));
+                }
+                return Err(ObjectType::error("ContractError", message));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn at_span(err: String, span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{} at {}", err, span),
+        None => err,
+    }
+}
+
+// fills parameters from positional then named arguments, falling back to defaults
+fn bind_arguments(
+    parameters: &[Pattern],
+    rest: Option<&str>,
+    arguments: Vec<ObjectType>,
+    named: Vec<(String, ObjectType)>,
+    span: Option<Span>,
+    scope: &Env,
+) -> Result<(), ObjectType> {
+    let fail = |message: String| Err(ObjectType::error("ArgumentError", at_span(message, span)));
+
+    let mut slots: Vec<Option<ObjectType>> = vec![None; parameters.len()];
+    let mut extra = vec![];
+
+    for (i, arg) in arguments.into_iter().enumerate() {
+        match slots.get_mut(i) {
+            Some(slot) => *slot = Some(arg),
+            None => extra.push(arg),
+        }
+    }
+    if !extra.is_empty() && rest.is_none() {
+        return fail(format!(
+            "Wrong number of arguments: expected at most {}, got {}",
+            parameters.len(),
+            parameters.len() + extra.len()
+        ));
+    }
+
+    for (name, value) in named {
+        let index = match parameters
+            .iter()
+            .position(|param| parameter_name(param) == Some(name.as_str()))
+        {
+            Some(index) => index,
+            None => return fail(format!("Unknown argument name '{}'", name)),
+        };
+        if slots[index].is_some() {
+            return fail(format!("Duplicate argument '{}'", name));
+        }
+        slots[index] = Some(value);
+    }
+
+    // parameters are bound in order so defaults can refer to earlier ones
+    for (param, slot) in parameters.iter().zip(slots) {
+        let value = match (slot, param) {
+            (Some(value), _) => value,
+            (None, Pattern::DEFAULT { default, .. }) => match eval_expression(default, scope) {
+                thrown @ ObjectType::Throw(_) => return Err(thrown),
+                value => value,
+            },
+            (None, _) => return fail(format!("Missing argument for parameter `{}`", param)),
+        };
+
+        let mut bindings = vec![];
+        if let Err(err) = match_pattern(param, &value, &mut bindings, scope) {
+            return fail(format!("Cannot bind argument to `{}`: {}", param, err));
+        }
+        for (name, bound) in bindings {
+            scope.borrow_mut().set(name, bound);
+        }
+    }
+
+    if let Some(rest) = rest {
+        scope.borrow_mut().set(rest.to_string(), ObjectType::Array(extra));
+    }
+
+    Ok(())
+}
+
+// the name a parameter can be passed by, if it is a plain identifier
+fn parameter_name(param: &Pattern) -> Option<&str> {
+    match param {
+        Pattern::BINDING(name) => Some(name),
+        Pattern::DEFAULT { pattern, .. } | Pattern::TYPED { pattern, .. } => parameter_name(pattern),
+        _ => None,
+    }
+}
+
+fn eval_prefex_expression(operator: &str, right: &Expression, env: &Env) -> ObjectType {
+    let right = check!(eval_expression(right, env));
+    apply_prefix(operator, right)
+}
+
+pub(crate) fn apply_prefix(operator: &str, right: ObjectType) -> ObjectType {
+    match operator {
         "-" => match right {
-            ObjectType::Number(val) => ObjectType::Number(-val),
-            _ => panic!("Unsupported type for '-' operator"),
+            ObjectType::Number(val) => match val.checked_neg() {
+                Some(negated) => ObjectType::Number(negated),
+                None => ObjectType::error("OverflowError", format!("Integer overflow in -({})", val)),
+            },
+            _ => ObjectType::error("TypeError", "Unsupported type for '-' operator"),
         },
         "!" => match right {
             ObjectType::Boolean(val) => ObjectType::Boolean(!val),
-            _ => panic!("Unsupported type for '!' operator"),
+            _ => ObjectType::error("TypeError", "Unsupported type for '!' operator"),
         },
-        _ => panic!("Unknown prefix operator"),
+        _ => ObjectType::error("SyntaxError", "Unknown prefix operator"),
     }
 }
 
-fn eval_infex_expression(left: Expression, operator: String, right: Expression) -> ObjectType {
-    let left = eval_expression(left);
-    let right = eval_expression(right);
+fn eval_infex_expression(
+    left: &Expression,
+    operator: &str,
+    right: &Expression,
+    env: &Env,
+) -> ObjectType {
+    let left = check!(eval_expression(left, env));
 
-    match operator.as_str() {
-        "+" => match (left, right) {
-            (ObjectType::Number(l), ObjectType::Number(r)) => ObjectType::Number(l + r),
-            _ => panic!("Unsupported types for '+' operator"),
+    // the fallback of `??` is only evaluated when it is needed
+    if operator == "??" {
+        return match left {
+            ObjectType::Null => eval_expression(right, env),
+            value => value,
+        };
+    }
+
+    let right = check!(eval_expression(right, env));
+    apply_infix(operator, left, right, env)
+}
+
+// applies a binary operator to evaluated operands, a declared operator is bound under its symbol
+pub(crate) fn apply_infix(operator: &str, left: ObjectType, right: ObjectType, env: &Env) -> ObjectType {
+    let declared = env.borrow().get(operator);
+    if let Some(function) = declared {
+        return apply_function(function, vec![left, right]);
+    }
+    apply_operator(operator, left, right)
+}
+
+// the binary operators on values, shared by infix expressions and `(+)` function values
+fn apply_operator(operator: &str, left: ObjectType, right: ObjectType) -> ObjectType {
+    if operator == "+"
+        && let Some(add) = left.trait_method("Add", "add")
+    {
+        return apply_function(add, vec![left, right]);
+    }
+
+    let unsupported = |left: &ObjectType, right: &ObjectType| {
+        ObjectType::error(
+            "TypeError",
+            format!(
+                "Unsupported types for '{}' operator: {} and {}",
+                operator,
+                left.inspect(),
+                right.inspect()
+            ),
+        )
+    };
+
+    match operator {
+        "+" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => checked(operator, *l, *r, l.checked_add(*r)),
+            (ObjectType::String(l), ObjectType::String(r)) => ObjectType::String(l.clone() + r),
+            _ => unsupported(&left, &right),
+        },
+        "-" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => checked(operator, *l, *r, l.checked_sub(*r)),
+            (ObjectType::Set(l), ObjectType::Set(r)) => ObjectType::set(&*l.borrow() - &*r.borrow()),
+            _ => unsupported(&left, &right),
+        },
+        "|" => match (&left, &right) {
+            (ObjectType::Set(l), ObjectType::Set(r)) => ObjectType::set(&*l.borrow() | &*r.borrow()),
+            _ => unsupported(&left, &right),
+        },
+        "&" => match (&left, &right) {
+            (ObjectType::Set(l), ObjectType::Set(r)) => ObjectType::set(&*l.borrow() & &*r.borrow()),
+            _ => unsupported(&left, &right),
+        },
+        "^" => match (&left, &right) {
+            (ObjectType::Set(l), ObjectType::Set(r)) => ObjectType::set(&*l.borrow() ^ &*r.borrow()),
+            _ => unsupported(&left, &right),
+        },
+        "*" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => checked(operator, *l, *r, l.checked_mul(*r)),
+            _ => unsupported(&left, &right),
+        },
+        "/" => match (&left, &right) {
+            (ObjectType::Number(_), ObjectType::Number(0)) => {
+                ObjectType::error("ZeroDivisionError", "Division by zero")
+            }
+            (ObjectType::Number(l), ObjectType::Number(r)) => checked(operator, *l, *r, l.checked_div(*r)),
+            _ => unsupported(&left, &right),
         },
-        "-" => match (left, right) {
-            (ObjectType::Number(l), ObjectType::Number(r)) => ObjectType::Number(l - r),
-            _ => panic!("Unsupported types for '-' operator"),
+        "%" => match (&left, &right) {
+            (ObjectType::Number(_), ObjectType::Number(0)) => {
+                ObjectType::error("ZeroDivisionError", "Modulo by zero")
+            }
+            (ObjectType::Number(l), ObjectType::Number(r)) => checked(operator, *l, *r, l.checked_rem(*r)),
+            _ => unsupported(&left, &right),
         },
-        "*" => match (left, right) {
-            (ObjectType::Number(l), ObjectType::Number(r)) => ObjectType::Number(l * r),
-            _ => panic!("Unsupported types for '*' operator"),
+        // on sets these ask whether one is a (proper) subset of the other
+        "<" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => bool_to_bool_object(l < r),
+            (ObjectType::Set(l), ObjectType::Set(r)) => bool_to_bool_object(l.borrow().is_subset(&r.borrow()) && l != r),
+            _ => unsupported(&left, &right),
         },
-        "/" => match (left, right) {
-            (ObjectType::Number(l), ObjectType::Number(r)) => ObjectType::Number(l / r),
-            _ => panic!("Unsupported types for '/' operator"),
+        ">" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => bool_to_bool_object(l > r),
+            (ObjectType::Set(l), ObjectType::Set(r)) => bool_to_bool_object(l.borrow().is_superset(&r.borrow()) && l != r),
+            _ => unsupported(&left, &right),
         },
-        _ => panic!("Unknown operator"),
+        "<=" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => bool_to_bool_object(l <= r),
+            (ObjectType::Set(l), ObjectType::Set(r)) => bool_to_bool_object(l.borrow().is_subset(&r.borrow())),
+            _ => unsupported(&left, &right),
+        },
+        ">=" => match (&left, &right) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => bool_to_bool_object(l >= r),
+            (ObjectType::Set(l), ObjectType::Set(r)) => bool_to_bool_object(l.borrow().is_superset(&r.borrow())),
+            _ => unsupported(&left, &right),
+        },
+        "in" => eval_in_expression(left, right),
+        "??" => match left {
+            ObjectType::Null => right,
+            value => value,
+        },
+        "==" => bool_to_bool_object(left == right),
+        "!=" => bool_to_bool_object(left != right),
+        _ => ObjectType::error("SyntaxError", format!("Unknown operator '{}'", operator)),
+    }
+}
+
+// the result of integer arithmetic, or an error the program can catch when it doesn't fit in an int
+fn checked(operator: &str, left: i64, right: i64, result: Option<i64>) -> ObjectType {
+    match result {
+        Some(value) => ObjectType::Number(value),
+        None => ObjectType::error(
+            "OverflowError",
+            format!("Integer overflow in {} {} {}", left, operator, right),
+        ),
     }
 }
 
+pub(crate) fn is_truthy(value: &ObjectType) -> bool {
+    !matches!(value, ObjectType::Boolean(false) | ObjectType::Null)
+}
+
 fn bool_to_bool_object(val: bool) -> ObjectType {
     if val {
         ObjectType::Boolean(true)
@@ -70,3 +1863,994 @@ fn bool_to_bool_object(val: bool) -> ObjectType {
         ObjectType::Boolean(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run(input: &str) -> ObjectType {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let mut program = parser.parse_program().unwrap();
+        if let Err(thrown) = macros::expand(&mut program, &mut macros::Macros::new()) {
+            panic!("{}", thrown.inspect());
+        }
+        let env = Environment::new();
+        let mut result = ObjectType::Null;
+        for stmnt in program.statements.iter() {
+            result = eval(stmnt, &env);
+            if let ObjectType::Throw(_) = result {
+                panic!("{}", result.inspect());
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_eval_named_function_call() {
+        let result = run("fun add(a, b) { a + b; } add(2, 3);");
+        assert_eq!(result, ObjectType::Number(5));
+    }
+
+    #[test]
+    fn test_eval_anonymous_function() {
+        let result = run("let double = fun(x) { x * 2 }; double(21);");
+        assert_eq!(result, ObjectType::Number(42));
+    }
+
+    #[test]
+    fn test_eval_lambda_captures_environment() {
+        let result = run("let n = 10; let add_n = |x| x + n; add_n(5);");
+        assert_eq!(result, ObjectType::Number(15));
+    }
+
+    #[test]
+    fn test_eval_closure_outlives_scope() {
+        let result = run("fun adder(n) { |x| x + n } let add_three = adder(3); add_three(4);");
+        assert_eq!(result, ObjectType::Number(7));
+    }
+
+    #[test]
+    fn test_eval_lambda_passed_to_map() {
+        let result = run("collect(map([1, 2, 3], |x| x * 2));");
+        assert_eq!(
+            result,
+            ObjectType::Array(vec![
+                ObjectType::Number(2),
+                ObjectType::Number(4),
+                ObjectType::Number(6)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_match_literals_and_alternatives() {
+        let input = "fun describe(n) { match n { 0 => \"zero\", 1 | 2 => \"small\", 3..=9 => \"digit\", _ => \"big\" } }
+            [describe(0), describe(2), describe(9), describe(10)];";
+        assert_eq!(
+            run(input),
+            ObjectType::Array(vec![
+                ObjectType::String("zero".to_string()),
+                ObjectType::String("small".to_string()),
+                ObjectType::String("digit".to_string()),
+                ObjectType::String("big".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_match_array_rest_pattern() {
+        let input = "match [1, 2, 3] { [] => 0, [first, ..rest] => first + len(rest) }";
+        assert_eq!(run(input), ObjectType::Number(3));
+    }
+
+    #[test]
+    fn test_eval_match_map_pattern_with_guard() {
+        let input = "let person = {\"name\": \"ada\", \"age\": 36};
+            match person { {age} if age < 18 => \"minor\", {name, age: 30..40} => name, _ => \"other\" }";
+        assert_eq!(run(input), ObjectType::String("ada".to_string()));
+    }
+
+    #[test]
+    fn test_eval_match_bindings_do_not_leak() {
+        let input = "let x = 1; match 5 { x if x > 10 => x, _ => x }";
+        assert_eq!(run(input), ObjectType::Number(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "No match arm matched")]
+    fn test_eval_match_without_matching_arm() {
+        run("match 3 { 1 => 1, 2 => 2 }");
+    }
+
+    #[test]
+    fn test_eval_destructuring_let() {
+        let input = "let pair = [1, [2, 3]]; let [a, [b, c]] = pair;
+            let {name, age} = {\"name\": \"ada\", \"age\": 36};
+            let [x, y = 10] = [5];
+            [a + b + c, age, x + y]";
+        assert_eq!(
+            run(input),
+            ObjectType::Array(vec![
+                ObjectType::Number(6),
+                ObjectType::Number(36),
+                ObjectType::Number(15),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_destructuring_parameters() {
+        let input = "fun dist([ax, ay], {x, y = 0}) { x - ax + y - ay } dist([1, 2], {\"x\": 4})";
+        assert_eq!(run(input), ObjectType::Number(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot destructure `[a, b]`: Expected an array of 2 elements, got 3")]
+    fn test_eval_destructuring_shape_mismatch() {
+        run("let [a, b] = [1, 2, 3];");
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing key \"age\"")]
+    fn test_eval_destructuring_missing_key() {
+        run("let {age} = {\"name\": \"ada\"};");
+    }
+
+    #[test]
+    fn test_eval_default_and_named_arguments() {
+        let input = "fun greet(name, greeting = \"hi\") { greeting + \" \" + name }
+            [greet(\"a\"), greet(\"a\", \"hey\"), greet(greeting: \"yo\", name: \"b\")]";
+        assert_eq!(
+            run(input),
+            ObjectType::Array(vec![
+                ObjectType::String("hi a".to_string()),
+                ObjectType::String("hey a".to_string()),
+                ObjectType::String("yo b".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_rest_parameter_and_spread() {
+        let input = "fun count(first, ...xs) { first + len(xs) } let args = [2, 3]; count(10, ...args, 4)";
+        assert_eq!(run(input), ObjectType::Number(13));
+    }
+
+    #[test]
+    fn test_eval_spread_in_array_literal() {
+        let input = "let xs = [2, 3]; [1, ...xs, 4]";
+        assert_eq!(
+            run(input),
+            ObjectType::Array(vec![
+                ObjectType::Number(1),
+                ObjectType::Number(2),
+                ObjectType::Number(3),
+                ObjectType::Number(4),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong number of arguments: expected at most 1, got 2 at line 1, column 17")]
+    fn test_eval_too_many_arguments() {
+        run("fun f(a) { a } f(1, 2)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate argument 'a' at line 1, column 17")]
+    fn test_eval_duplicate_argument() {
+        run("fun f(a) { a } f(1, a: 2)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown argument name 'b' at line 2, column 2")]
+    fn test_eval_unknown_argument_name() {
+        run("fun f(a) { a }\nf(b: 2)");
+    }
+
+    #[test]
+    fn test_eval_range_values() {
+        assert_eq!(run("0..10 step 2").inspect(), "0..10 step 2");
+        assert_eq!(run("let n = 3; 1..=n").inspect(), "1..=3");
+        assert_eq!(run("0..5 == 0..5"), ObjectType::Boolean(true));
+    }
+
+    #[test]
+    fn test_eval_range_membership() {
+        let input = "[3 in 1..5, 5 in 1..5, 5 in 1..=5, 4 in 0..10 step 2, 5 in 0..10 step 2, 2 in [1, 2]]";
+        assert_eq!(
+            run(input),
+            ObjectType::Array(vec![
+                ObjectType::Boolean(true),
+                ObjectType::Boolean(false),
+                ObjectType::Boolean(true),
+                ObjectType::Boolean(true),
+                ObjectType::Boolean(false),
+                ObjectType::Boolean(true),
+            ])
+        );
+
+        // an open start only bounds from above
+        let input = "[5 in ..10, -9223372036854775807 in ..10, 10 in ..10, 10 in ..=10, 9223372036854775807 in 0.., -1 in 0..]";
+        assert_eq!(run(input).inspect(), "[true, true, false, true, true, false]");
+    }
+
+    #[test]
+    fn test_eval_for_over_range() {
+        let input = "fun first_square_over(limit) { for x in 1.. { if x * x > limit { ret x; } } }
+            first_square_over(20)";
+        assert_eq!(run(input), ObjectType::Number(5));
+    }
+
+    #[test]
+    fn test_eval_for_with_break_and_continue() {
+        let input = "fun last_even(xs) { for [i, x] in xs { if x == 0 { continue; } if x > 6 { break; } if x in 0..100 step 2 { ret i; } } }
+            last_even([[0, 1], [1, 0], [2, 4], [3, 8]])";
+        assert_eq!(run(input), ObjectType::Number(2));
+    }
+
+    #[test]
+    fn test_eval_slicing() {
+        let input = "let arr = [1, 2, 3, 4]; let s = \"hello\"; [arr[1..3], s[..2], s[3..], arr[..=1], s[0..10 step 2]]";
+        assert_eq!(run(input).inspect(), "[[2, 3], he, lo, [1, 2], hlo]");
+    }
+
+    #[test]
+    fn test_eval_struct_literal_and_inspect() {
+        let input = "struct Point { x, y } let y = 2; let p = Point { x: 1, y }; [p.x + p.y, p]";
+        assert_eq!(run(input).inspect(), "[3, Point { x: 1, y: 2 }]");
+    }
+
+    #[test]
+    fn test_eval_struct_methods() {
+        let input = "struct Point { x, y }
+            impl Point {
+                fun origin() { Point { x: 0, y: 0 } }
+                fun len(self) { self.x * self.x + self.y * self.y }
+                fun move_by(self, dx) { self.x = self.x + dx; self }
+            }
+            let p = Point.origin();
+            p.move_by(3);
+            p.len()";
+        assert_eq!(run(input), ObjectType::Number(9));
+    }
+
+    #[test]
+    fn test_eval_struct_equality() {
+        let input = "struct Point { x, y } [Point { x: 1, y: 2 } == Point { x: 1, y: 2 }, Point { x: 1, y: 2 } == Point { x: 2, y: 2 }]";
+        assert_eq!(run(input).inspect(), "[true, false]");
+    }
+
+    #[test]
+    fn test_eval_variable_assignment() {
+        let input = "let total = 0; for x in 1..=4 { total = total + x; } total";
+        assert_eq!(run(input), ObjectType::Number(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing field 'y' in Point literal")]
+    fn test_eval_struct_literal_missing_field() {
+        run("struct Point { x, y } Point { x: 1 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "Point has no field 'z'")]
+    fn test_eval_unknown_field_assignment() {
+        run("struct Point { x, y } let p = Point { x: 1, y: 2 }; p.z = 3;");
+    }
+
+    #[test]
+    fn test_eval_enum_constructors_and_inspect() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Empty } [Shape.Circle(2), Shape.Rect(1, 3), Shape.Empty, Shape.Circle]";
+        assert_eq!(
+            run(input).inspect(),
+            "[Shape.Circle(2), Shape.Rect(1, 3), Shape.Empty, Shape.Circle]"
+        );
+    }
+
+    #[test]
+    fn test_eval_enum_equality() {
+        let input = "enum Shape { Circle(r), Empty }
+            [Shape.Circle(2) == Shape.Circle(2), Shape.Circle(2) == Shape.Circle(3), Shape.Empty == Shape.Empty]";
+        assert_eq!(run(input).inspect(), "[true, false, true]");
+    }
+
+    #[test]
+    fn test_eval_enum_match_and_methods() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Empty }
+            impl Shape {
+                fun area(self) {
+                    match self { Shape.Circle(r) => 3 * r * r, Shape.Rect(w, h) => w * h, Shape.Empty => 0 }
+                }
+            }
+            collect(map([Shape.Circle(2), Shape.Rect(2, 5), Shape.Empty], |s| s.area()))";
+        assert_eq!(run(input).inspect(), "[12, 10, 0]");
+    }
+
+    #[test]
+    fn test_eval_if_let() {
+        let input = "enum Shape { Circle(r), Empty }
+            fun radius(s) { if let Shape.Circle(r) = s { r } else { 0 } }
+            [radius(Shape.Circle(4)), radius(Shape.Empty)]";
+        assert_eq!(run(input).inspect(), "[4, 0]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong number of arguments to Shape.Rect: expected 2, got 1")]
+    fn test_eval_enum_constructor_arity() {
+        run("enum Shape { Rect(w, h) } Shape.Rect(1)");
+    }
+
+    #[test]
+    fn test_eval_functions_stored_in_array() {
+        let result = run("let fs = [|x| x + 1, fun(x) { x * 10 }]; fs[1](fs[0](1));");
+        assert_eq!(result, ObjectType::Number(20));
+    }
+
+    #[test]
+    fn test_eval_trait_default_method_dispatch() {
+        let input = "trait Named { fun name(self); fun greet(self) { \"hi \" + self.name() } }
+            struct Dog { n }
+            struct Cat { n }
+            impl Named for Dog { fun name(self) { \"dog \" + self.n } }
+            impl Named for Cat { fun name(self) { \"cat\" } fun greet(self) { \"meow\" } }
+            collect(map([Dog { n: \"rex\" }, Cat { n: \"tom\" }], |a| a.greet()))";
+        assert_eq!(run(input).inspect(), "[hi dog rex, meow]");
+    }
+
+    #[test]
+    fn test_eval_builtin_trait_hooks() {
+        let prelude = "struct Point { x, y }
+            impl Show for Point { fun show(self) { \"<pt>\" } }
+            impl Eq for Point { fun eq(self, other) { self.x == other.x } }
+            impl Add for Point { fun add(self, other) { Point { x: self.x + other.x, y: self.y + other.y } } }
+            struct Bag { items }
+            impl Iterable for Bag { fun iter(self) { self.items } }
+            let a = Point { x: 1, y: 2 };
+            let b = Point { x: 1, y: 5 };";
+        assert_eq!(run(&format!("{} a", prelude)).inspect(), "<pt>");
+        assert_eq!(run(&format!("{} [a]", prelude)).inspect(), "[<pt>]");
+        assert_eq!(run(&format!("{} a == b", prelude)), ObjectType::Boolean(true));
+        assert_eq!(run(&format!("{} (a + b).y", prelude)), ObjectType::Number(7));
+        let sum = "let bag = Bag { items: [1, 2, 3] }; let total = 0; for i in bag { total = total + i }; total";
+        assert_eq!(run(&format!("{} {}", prelude, sum)), ObjectType::Number(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "Point does not implement 'show' required by trait Show")]
+    fn test_eval_trait_missing_required_method() {
+        run("struct Point { x } impl Show for Point { }");
+    }
+
+    #[test]
+    #[should_panic(expected = "Method 'size' is not a member of trait Show")]
+    fn test_eval_trait_unknown_method() {
+        run("struct Point { x } impl Show for Point { fun show(self) { 1 } fun size(self) { 1 } }");
+    }
+
+    // writes the files into a fresh directory and returns its path
+    fn module_dir(test: &str, files: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("ember_modules_{}", test));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (name, source) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir.display().to_string()
+    }
+
+    #[test]
+    fn test_eval_import_module() {
+        let dir = module_dir(
+            "import",
+            &[
+                ("lib/math.em", "import \"consts.em\" as c; pub let pi = c.pi; pub fun sq(x) { x * x }"),
+                ("lib/consts.em", "pub let pi = 3;"),
+            ],
+        );
+        let input = format!(
+            "import \"{dir}/lib/math.em\" as m; from \"{dir}/lib/math.em\" import sq; sq(m.pi) + m.sq(2)"
+        );
+        assert_eq!(run(&input), ObjectType::Number(13));
+    }
+
+    #[test]
+    fn test_eval_module_evaluated_once() {
+        let dir = module_dir("once", &[("shapes.em", "pub struct Point { x }")]);
+        let input = format!(
+            "import \"{dir}/shapes.em\" as a; import \"{dir}/shapes.em\"; a.Point == shapes.Point"
+        );
+        assert_eq!(run(&input), ObjectType::Boolean(true));
+    }
+
+    #[test]
+    fn test_eval_import_syntax_error() {
+        let dir = module_dir("syntax", &[("broken.em", "pub let a = 1;\nlet = 2;\npub let b = 3;")]);
+        let input = format!("try {{ import \"{dir}/broken.em\" as m; m.a }} catch e {{ [e.kind, e.message] }}");
+        assert_eq!(
+            run(&input).inspect(),
+            format!("[ImportError, Cannot parse module \"{dir}/broken.em\": Unexpected token Token {{ t: ASSIGN, literal: \"=\" }} in pattern]")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "does not export 'secret'")]
+    fn test_eval_import_private_name() {
+        let dir = module_dir("private", &[("math.em", "let secret = 1; pub let pi = 3;")]);
+        run(&format!("from \"{dir}/math.em\" import pi, secret;"));
+    }
+
+    #[test]
+    #[should_panic(expected = "a.em -> ")]
+    fn test_eval_import_cycle() {
+        let dir = module_dir(
+            "cycle",
+            &[("a.em", "import \"b.em\" as b;"), ("b.em", "import \"a.em\" as a;")],
+        );
+        run(&format!("import \"{dir}/a.em\" as a;"));
+    }
+
+    #[test]
+    fn test_eval_throw_and_catch() {
+        assert_eq!(run("try { throw \"boom\" } catch e { e }"), ObjectType::String("boom".to_string()));
+        assert_eq!(run("try { 1 } catch e { 2 }"), ObjectType::Number(1));
+
+        let input = "let e = try { throw error(\"Custom\", \"bad\") } catch e { e }; [e.kind, e.message]";
+        assert_eq!(run(input).inspect(), "[Custom, bad]");
+    }
+
+    #[test]
+    fn test_eval_runtime_errors_are_catchable() {
+        let kind = |body: &str| run(&format!("try {{ {} }} catch e {{ e.kind }}", body)).inspect();
+        assert_eq!(kind("1 / 0"), "ZeroDivisionError");
+        assert_eq!(kind("1 + true"), "TypeError");
+        assert_eq!(kind("nope"), "NameError");
+        assert_eq!(kind("len(1, 2)"), "ArgumentError");
+        assert_eq!(kind("match 1 { 2 => 3 }"), "MatchError");
+
+        let message = |body: &str| run(&format!("try {{ {} }} catch e {{ e.message }}", body)).inspect();
+        let min = "(-9223372036854775807 - 1)";
+        assert_eq!(message("9223372036854775807 + 1"), "Integer overflow in 9223372036854775807 + 1");
+        assert_eq!(message(&format!("{} - 1", min)), "Integer overflow in -9223372036854775808 - 1");
+        assert_eq!(message("4611686018427387904 * 2"), "Integer overflow in 4611686018427387904 * 2");
+        assert_eq!(message(&format!("{} / -1", min)), "Integer overflow in -9223372036854775808 / -1");
+        assert_eq!(message(&format!("{} % -1", min)), "Integer overflow in -9223372036854775808 % -1");
+        assert_eq!(message(&format!("-{}", min)), "Integer overflow in -(-9223372036854775808)");
+        assert_eq!(kind("9223372036854775807 + 1"), "OverflowError");
+    }
+
+    #[test]
+    fn test_eval_error_stack_trace() {
+        let input = "fun inner() { 1 / 0 } fun outer() { inner() }
+            try { outer() } catch e { e.stack }";
+        assert_eq!(
+            run(input).inspect(),
+            "[inner at line 1, column 42, outer at line 2, column 24]"
+        );
+    }
+
+    #[test]
+    fn test_eval_finally_always_runs() {
+        let input = "let log = [];
+            fun f() { try { ret 1 } finally { log = push(log, \"ret\") } }
+            f();
+            for i in 0..3 { try { break } finally { log = push(log, \"break\") } };
+            try { try { throw 1 } finally { log = push(log, \"inner\") } } catch e { log = push(log, \"caught\") };
+            log";
+        assert_eq!(run(input).inspect(), "[ret, break, inner, caught]");
+
+        // a throw inside `finally` replaces the one being unwound
+        assert_eq!(run("try { try { throw 1 } finally { throw 2 } } catch e { e }"), ObjectType::Number(2));
+        assert_eq!(run("fun f() { try { ret 1 } finally { 2 } } f()"), ObjectType::Number(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Uncaught ZeroDivisionError: Division by zero")]
+    fn test_eval_uncaught_error() {
+        run("let x = 1 / 0; x");
+    }
+
+    #[test]
+    fn test_eval_result_and_option_values() {
+        assert_eq!(run("[Ok(1), Err(\"no\"), Some(2), None]").inspect(), "[Ok(1), Err(no), Some(2), None]");
+        assert_eq!(run("Some(1) == Some(1)"), ObjectType::Boolean(true));
+
+        let input = "fun describe(o) { match o { Some(x) => x, None => 0 } }
+            [describe(Some(5)), describe(None)]";
+        assert_eq!(run(input).inspect(), "[5, 0]");
+    }
+
+    #[test]
+    fn test_eval_question_mark_propagation() {
+        let input = "fun add(a, b) { Ok(parse_int(a)? + parse_int(b)?) }
+            [add(\"1\", \"2\"), add(\"1\", \"x\")]";
+        assert_eq!(
+            run(input).inspect(),
+            "[Ok(3), Err(Cannot parse \"x\" as an integer)]"
+        );
+
+        let input = "fun first(xs) { let x = if len(xs) > 0 { Some(xs[0]) } else { None }?; Some(x * 10) }
+            [first([4]), first([])]";
+        assert_eq!(run(input).inspect(), "[Some(40), None]");
+    }
+
+    #[test]
+    fn test_eval_result_builtins() {
+        assert_eq!(run("unwrap(Ok(1))"), ObjectType::Number(1));
+        assert_eq!(run("unwrap_or(None, 7)"), ObjectType::Number(7));
+        assert_eq!(run("unwrap_or(Some(3), 7)"), ObjectType::Number(3));
+        assert_eq!(run("map_err(Err(1), |e| e + 1)").inspect(), "Err(2)");
+        assert_eq!(run("map_err(Ok(1), |e| e + 1)").inspect(), "Ok(1)");
+        assert_eq!(run("try { unwrap(None) } catch e { e.kind }").inspect(), "UnwrapError");
+    }
+
+    #[test]
+    fn test_eval_null_and_coalesce() {
+        assert_eq!(run("null"), ObjectType::Null);
+        assert_eq!(run("null ?? 1"), ObjectType::Number(1));
+        assert_eq!(run("2 ?? 1"), ObjectType::Number(2));
+        assert_eq!(run("false ?? 1"), ObjectType::Boolean(false));
+        // the fallback isn't evaluated when the left side is present
+        assert_eq!(run("1 ?? 1 / 0"), ObjectType::Number(1));
+        assert_eq!(run("null ?? 1 == 1"), ObjectType::Boolean(true));
+        assert_eq!(run("match null { null => 1, _ => 2 }"), ObjectType::Number(1));
+    }
+
+    #[test]
+    fn test_eval_optional_chaining() {
+        let prelude = "struct User { address } struct Address { city }
+            let home = User { address: Address { city: \"Cairo\" } };
+            let nowhere = User { address: null };
+            let nobody = null;";
+        let eval = |expr: &str| run(&format!("{} {}", prelude, expr));
+
+        assert_eq!(eval("home?.address?.city").inspect(), "Cairo");
+        assert_eq!(eval("nowhere.address?.city"), ObjectType::Null);
+        // the rest of the chain is skipped once a link is null
+        assert_eq!(eval("nobody?.address.city.len"), ObjectType::Null);
+        assert_eq!(eval("nobody?.address ?? \"unknown\"").inspect(), "unknown");
+
+        let prelude = "let home = {\"address\": {\"city\": \"Cairo\"}};
+            let nowhere = {\"address\": null};
+            let unknown = {\"name\": \"Ann\"};";
+        let eval = |expr: &str| run(&format!("{} {}", prelude, expr));
+        assert_eq!(eval("home?.address?.city").inspect(), "Cairo");
+        assert_eq!(eval("home.address.city").inspect(), "Cairo");
+        assert_eq!(eval("nowhere?.address?.city"), ObjectType::Null);
+        assert_eq!(eval("unknown?.address?.city ?? \"unknown\"").inspect(), "unknown");
+        assert_eq!(
+            eval("try { unknown.address } catch e { [e.kind, e.message] }").inspect(),
+            "[FieldError, Map has no key 'address']"
+        );
+
+        assert_eq!(run("let f = null; f?.(1)"), ObjectType::Null);
+        assert_eq!(run("let f = |x| x + 1; f?.(1)"), ObjectType::Number(2));
+        assert_eq!(run("let arr = null; arr?.[0]"), ObjectType::Null);
+        assert_eq!(run("let arr = [5]; arr?.[0]"), ObjectType::Number(5));
+    }
+
+    #[test]
+    fn test_eval_string_interpolation() {
+        let input = "let name = \"Ann\"; let count = 2;
+            \"Hello, ${name}! You have ${count + 1} messages\"";
+        assert_eq!(run(input).inspect(), "Hello, Ann! You have 3 messages");
+
+        let input = "let xs = [1, 2]; \"${xs} ${{\"k\": 1}} ${if true { \"yes\" } else { \"no\" }} ${\"in${len(xs)}ner\"}\"";
+        assert_eq!(run(input).inspect(), "[1, 2] {k: 1} yes in2ner");
+        assert_eq!(run("\"cost: \\${x}\"").inspect(), "cost: ${x}");
+    }
+
+    #[test]
+    fn test_eval_generator_suspends_and_resumes() {
+        let input = "let calls = 0;
+            fun count(start) { for i in start.. { calls = calls + 1; yield i; } }
+            let g = count(5);
+            [g.next(), next(g), calls]";
+        assert_eq!(
+            run(input).inspect(),
+            "[{done: false, value: 5}, {done: false, value: 6}, 2]"
+        );
+
+        let input = "fun pick(xs) {
+                for x in xs { if x == 3 { continue; } if x > 4 { break; } yield x; }
+                match xs { [first, ..] => { yield first * 10; }, _ => {} }
+                ret 0;
+                yield 99;
+            }
+            let seen = [];
+            for x in pick([1, 2, 3, 4, 5, 6]) { seen = push(seen, x); }
+            let g = pick([]);
+            [seen, collect(pick([7])), g.next()]";
+        assert_eq!(
+            run(input).inspect(),
+            "[[1, 2, 4, 10], [70], {done: true, value: Null}]"
+        );
+    }
+
+    #[test]
+    fn test_eval_generator_errors() {
+        let input = "fun bad() { yield 1; throw \"boom\"; yield 2; }
+            let g = bad();
+            let first = g.next();
+            let caught = try { g.next() } catch e { e };
+            [first[\"value\"], caught, g.next()[\"done\"]]";
+        assert_eq!(run(input).inspect(), "[1, boom, true]");
+
+        let input = "fun nested() { let x = [yield 1]; } try { collect(nested()) } catch e { e.kind }";
+        assert_eq!(run(input).inspect(), "SyntaxError");
+    }
+
+    #[test]
+    fn test_eval_lazy_adapters() {
+        let input = "let calls = 0;
+            fun naturals() { for i in 0.. { calls = calls + 1; yield i; } }
+            let squares = map(naturals(), |x| x * x);
+            let big = filter(squares, |x| x > 10);
+            [collect(take(big, 2)), calls]";
+        assert_eq!(run(input).inspect(), "[[16, 25], 6]");
+
+        let input = "collect(zip(enumerate(\"ab\"), chain([1], 5..)))";
+        assert_eq!(run(input).inspect(), "[[[0, a], 1], [[1, b], 5]]");
+
+        assert_eq!(run("collect(map([1, 2], |x| x + 1))").inspect(), "[2, 3]");
+
+        // arrays are adapted lazily too, nothing runs until an item is asked for
+        let input = "let seen = [];
+            let doubled = map([1, 2, 3, 4], |x| { seen = push(seen, x); x * 2 });
+            let small = filter(doubled, |x| { seen = push(seen, -x); x < 5 });
+            let before = len(seen);
+            [before, collect(take(small, 1)), seen]";
+        assert_eq!(run(input).inspect(), "[0, [2], [1, -2]]");
+        assert_eq!(run("let it = iter(1..3); it.next(); collect(it)").inspect(), "[2]");
+    }
+
+    #[test]
+    fn test_eval_iterator_trait() {
+        let input = "struct Countdown { n }
+            impl Iterator for Countdown {
+                fun next(self) {
+                    if self.n == 0 { ret {\"done\": true}; }
+                    self.n = self.n - 1;
+                    {\"value\": self.n + 1, \"done\": false}
+                }
+            }
+            collect(take(Countdown { n: 5 }, 3))";
+        assert_eq!(run(input).inspect(), "[5, 4, 3]");
+    }
+
+    #[test]
+    fn test_eval_operator_values() {
+        assert_eq!(run("(+)(1, 2)"), ObjectType::Number(3));
+        assert_eq!(run("let ops = [(*), (-)]; ops[1](10, ops[0](2, 3))"), ObjectType::Number(4));
+        assert_eq!(run("(+)").inspect(), "(+)");
+        assert_eq!(run("(??)(null, 7)"), ObjectType::Number(7));
+    }
+
+    #[test]
+    fn test_eval_partial_application() {
+        let input = "fun sub(a, b) { a - b }
+            let from_ten = sub(10, _);
+            let minus_one = sub(_, 1);
+            [from_ten(3), minus_one(3)]";
+        assert_eq!(run(input).inspect(), "[7, 2]");
+
+        assert_eq!(run("fun sub(a, b) { a - b } sub(_, 1)").inspect(), "sub(_, 1)");
+        assert_eq!(run("let add = (+)(_, _); add(2, 5)"), ObjectType::Number(7));
+        assert_eq!(run("collect(map([1, 2], (*)(_, 3)))").inspect(), "[3, 6]");
+    }
+
+    #[test]
+    fn test_eval_pipe_operator() {
+        let input = "fun is_valid(x) { x > 1 }
+            [1, 2, 3] |> filter(_, is_valid) |> map(_, |x| x * 10) |> collect |> len";
+        assert_eq!(run(input), ObjectType::Number(2));
+
+        assert_eq!(run("let x = 4 |> (+)(1, _); x"), ObjectType::Number(5));
+    }
+
+    #[test]
+    fn test_eval_list_comprehension() {
+        let input = "[x * x for x in 0..10 if x % 2 == 0]";
+        assert_eq!(run(input).inspect(), "[0, 4, 16, 36, 64]");
+
+        let input = "[[x, y] for x in 1..=3 if x != 2 for y in \"ab\"]";
+        assert_eq!(run(input).inspect(), "[[1, a], [1, b], [3, a], [3, b]]");
+
+        // loop variables stay inside the comprehension
+        let input = "let x = \"outer\"; let squares = [x * x for x in [1, 2]]; [x, squares]";
+        assert_eq!(run(input).inspect(), "[outer, [1, 4]]");
+    }
+
+    #[test]
+    fn test_eval_map_comprehension() {
+        let input = "let pairs = [[\"a\", 1], [\"b\", 2]]; {k: v * 10 for [k, v] in pairs}";
+        assert_eq!(run(input).inspect(), "{a: 10, b: 20}");
+
+        let input = "{k: v for [k, v] in {\"x\": 1, \"y\": 2} if v > 1}";
+        assert_eq!(run(input).inspect(), "{y: 2}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unusable as map key")]
+    fn test_eval_map_comprehension_bad_key() {
+        run("{[x]: x for x in 0..2}");
+    }
+
+    #[test]
+    fn test_eval_macro_expansion() {
+        let input = "macro unless(cond, body) { quote(if !unquote(cond) { unquote(body) }) }
+            let x = 1;
+            unless(x > 5, x + 10)";
+        assert_eq!(run(input), ObjectType::Number(11));
+
+        // the expansion only evaluates each argument where the template puts it
+        let input = "macro twice(e) { quote(unquote(e) + unquote(e)) } let n = 4; twice(n * 2)";
+        assert_eq!(run(input), ObjectType::Number(16));
+
+        assert_eq!(run("let x = 2; quote(1 + unquote(x) * y)").inspect(), "quote(1 + 2 * y)");
+    }
+
+    #[test]
+    fn test_eval_macro_hygiene() {
+        // the macro's `one` must not capture the caller's
+        let input = "macro add_one(x) { quote(if true { let one = 1; unquote(x) + one }) }
+            let one = 100;
+            [add_one(one), one]";
+        assert_eq!(run(input).inspect(), "[101, 100]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Macro 'unless' expects 2 arguments, got 1")]
+    fn test_eval_macro_arity() {
+        run("macro unless(cond, body) { quote(if !unquote(cond) { unquote(body) }) } unless(true)");
+    }
+
+    #[test]
+    #[should_panic(expected = "'unquote' can only be used inside 'quote'")]
+    fn test_eval_unquote_outside_quote() {
+        run("unquote(1)");
+    }
+
+    #[test]
+    fn test_eval_user_defined_operators() {
+        let input = "infix 60 <+> (a, b) { [a, b] }
+            infix right 75 <^> (a, b) { a * 10 + b }
+            [1 <+> 2 * 3, 1 <^> 2 <^> 3, (<+>)(4, 5), [6] |> (<+>)(_, 7)]";
+        assert_eq!(run(input).inspect(), "[[1, 6], 33, [4, 5], [[6], 7]]");
+    }
+
+    #[test]
+    fn test_eval_defer_runs_in_reverse_on_exit() {
+        let input = "let log = [];
+            fun f() { defer log = push(log, 1); defer log = push(log, 2); log = push(log, 0); ret 5; log = push(log, 9) }
+            let result = f();
+            for i in 0..3 { defer log = push(log, i * 10); if i == 1 { break } };
+            try { if true { defer log = push(log, \"thrown\"); throw 1 } } catch e { log = push(log, e) };
+            [result, log]";
+        assert_eq!(run(input).inspect(), "[5, [0, 2, 1, 0, 10, thrown, 1]]");
+
+        let input = "let log = [];
+            fun gen() { defer log = push(log, \"done\"); yield 1; yield 2 }
+            [collect(gen()), log]";
+        assert_eq!(run(input).inspect(), "[[1, 2], [done]]");
+    }
+
+    #[test]
+    fn test_eval_defer_chains_errors() {
+        let input = "fun read() {
+                defer throw error(\"CloseError\", \"close failed\");
+                throw error(\"IOError\", \"read failed\")
+            }
+            try { read() } catch e { [e.kind, e.suppressed[0].kind, e] }";
+        assert_eq!(
+            run(input).inspect(),
+            "[IOError, CloseError, IOError: read failed (while cleaning up: CloseError: close failed)]"
+        );
+
+        // a thrown plain value is turned into an error to carry the deferred one
+        let input = "fun k() { defer 1 / 0; throw \"orig\" } try { k() } catch e { [e.kind, e.message, e.suppressed[0].kind] }";
+        assert_eq!(run(input).inspect(), "[Error, orig, ZeroDivisionError]");
+
+        // with nothing unwinding the deferred error is thrown on its own
+        let input = "fun close() { defer throw error(\"CloseError\", \"x\"); 1 } try { close() } catch e { e.kind }";
+        assert_eq!(run(input).inspect(), "CloseError");
+    }
+
+    #[test]
+    #[should_panic(expected = "'defer' can only be used inside a block or function body")]
+    fn test_eval_defer_at_top_level() {
+        run("defer 1;");
+    }
+
+    #[test]
+    fn test_eval_requires_and_ensures() {
+        let message = |call: &str| {
+            let input = format!(
+                "fun half(x) requires x > 0 ensures result * 2 == x {{ x / 2 }}
+                try {{ {} }} catch e {{ e.kind + \": \" + e.message }}",
+                call
+            );
+            run(&input).inspect()
+        };
+        assert_eq!(message("half(8)"), "4");
+        assert_eq!(message("half(-4)"), "ContractError: Precondition of half failed: x > 0 (x = -4)");
+        assert_eq!(
+            message("half(5)"),
+            "ContractError: Postcondition of half failed: result * 2 == x (x = 5, result = 2)"
+        );
+
+        let input = "fun sqrt(x) requires x >= 0 ensures result >= 0 { len([i for i in 1..=x if i * i <= x]) }
+            [sqrt(10), try { sqrt(-1) } catch e { e.message }]";
+        assert_eq!(run(input).inspect(), "[3, Precondition of sqrt failed: x >= 0 (x = -1)]");
+    }
+
+    #[test]
+    fn test_eval_struct_invariants() {
+        let input = "struct Account { balance } invariant self.balance >= 0
+            impl Account {
+                fun withdraw(self, amount) { self.balance = self.balance - amount; self.balance }
+            }
+            let account = Account { balance: 10 };
+            let left = account.withdraw(4);
+            let e = try { account.withdraw(amount: 50) } catch e { e.message };
+            [left, e]";
+        assert_eq!(
+            run(input).inspect(),
+            "[6, Invariant of Account after withdraw failed: self.balance >= 0 (self = Account { balance: -44 }, amount = 50)]"
+        );
+    }
+
+    #[test]
+    fn test_eval_contracts_disabled() {
+        set_contracts(false);
+        let result = run("fun half(x) requires x > 0 { x / 2 } half(-4)");
+        set_contracts(true);
+        assert_eq!(result, ObjectType::Number(-2));
+    }
+
+    #[test]
+    fn test_eval_power_assert() {
+        assert_eq!(run("let xs = [1, 2]; assert len(xs) == 2, \"two\"; 1"), ObjectType::Number(1));
+
+        let input = "let xs = [1, 2, 3];
+            let n = 4;
+            try { assert len(xs) == n + 1, \"xs should have grown\" } catch e { e.message }";
+        assert_eq!(
+            run(input).inspect(),
+            [
+                "Assertion failed: xs should have grown",
+                "",
+                "assert len(xs) == n + 1",
+                "       |   |   |  | |",
+                "       3   |   |  4 5",
+                "           |   false",
+                "           [1, 2, 3]",
+            ]
+            .join("\n")
+        );
+
+        let input = "struct Point { x, y }
+            impl Point { fun sum(self) { self.x + self.y } }
+            let p = Point { x: 1, y: 2 };
+            try { assert !(p.sum() > p.x * 2) } catch e { e.message }";
+        assert_eq!(
+            run(input).inspect(),
+            [
+                "Assertion failed",
+                "",
+                "assert !(p.sum() > p.x * 2)",
+                "       | | |     | | | |",
+                "       | | 3     | | 1 2",
+                "       | |       | Point { x: 1, y: 2 }",
+                "       | |       true",
+                "       | Point { x: 1, y: 2 }",
+                "       false",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_eval_tuples() {
+        let input = "fun divmod(a, b) { ret a / b, a % b }
+            let (q, r) = divmod(7, 2);
+            let t = (1, \"a\", [2]);
+            [q, r, t, t.0, t.2, (5,), (1, 2) == (1, 2), (1, 2) == (2, 1), (1, 2) == [1, 2], (3)]";
+        assert_eq!(
+            run(input).inspect(),
+            "[3, 1, (1, a, [2]), 1, [2], (5,), true, false, false, 3]"
+        );
+
+        let input = "match (9, 0) { (n, 0) => n, (_, d) => d }";
+        assert_eq!(run(input), ObjectType::Number(9));
+
+        // tuples of hashable values are keys and set elements
+        let input = "let grid = {(1, 0): \"b\", (0, 1): \"a\"};
+            [grid[(1, 0)], (0, 1) in grid, grid, set([(1, 2), (0, 5), (1, 2)])]";
+        assert_eq!(run(input).inspect(), "[b, true, {(0, 1): a, (1, 0): b}, set{(0, 5), (1, 2)}]");
+
+        let message = |body: &str| run(&format!("try {{ {} }} catch e {{ e.message }}", body)).inspect();
+        assert_eq!(message("let t = (1, 2); t.0 = 3"), "Cannot set element '0' of (1, 2), tuples are immutable");
+        assert_eq!(message("(1, 2).2"), "(1, 2) has no element '2'");
+        assert_eq!(message("{(1, [2]): 0}"), "Unusable as map key: (1, [2])");
+        assert_eq!(
+            message("let (a, b) = (1, 2, 3)"),
+            "Cannot destructure `(a, b)`: Expected a tuple of 2 elements, got (1, 2, 3)"
+        );
+    }
+
+    #[test]
+    fn test_eval_sets() {
+        let input = "let s = set{3, 1, 2, 1};
+            let t = set([2, 3, 4]);
+            [s, len(s), 2 in s, 5 in s, t, set(), s.contains(1), contains(t, 1)]";
+        assert_eq!(
+            run(input).inspect(),
+            "[set{1, 2, 3}, 3, true, false, set{2, 3, 4}, set{}, true, false]"
+        );
+
+        // `add` and `remove` change the set in place, `set(s)` copies it
+        let input = "let s = set{1, 2, 3};
+            s.add(0);
+            remove(s, 3);
+            let alias = s;
+            alias.add(9);
+            let copy = set(s);
+            copy.remove(9);
+            [s, copy, s.add(4)]";
+        assert_eq!(run(input).inspect(), "[set{0, 1, 2, 4, 9}, set{0, 1, 2}, Null]");
+
+        let input = "let a = set{1, 2, 3}; let b = set{3, 4};
+            [a | b, a & b, a - b, a ^ b, a | b & set{1}, a == set{3, 2, 1}]";
+        assert_eq!(
+            run(input).inspect(),
+            "[set{1, 2, 3, 4}, set{3}, set{1, 2}, set{1, 2, 4}, set{1, 2, 3}, true]"
+        );
+
+        let input = "let a = set{1, 2}; let b = set{1, 2, 3};
+            [a < b, b < b, a <= b, b <= b, b > a, b >= a, a >= b, 2 <= 3, 3 >= 4]";
+        assert_eq!(run(input).inspect(), "[true, false, true, true, true, true, false, true, false]");
+
+        let input = "let a = set{1, 2}; let b = set{1, 2, 3}; if a <= b { if len(a) >= 2 { 1 } else { 2 } } else { 3 }";
+        assert_eq!(run(input), ObjectType::Number(1));
+
+        // membership and boolean methods are conditions of their own
+        let input = "let s = set{1}; let ok = s.contains(1); let v = null;
+            [if s.contains(1) { 1 } else { 2 }, if ok { 1 } else { 2 }, if v ?? false { 1 } else { 2 }, if 2 in s { 1 } else { 2 }]";
+        assert_eq!(run(input).inspect(), "[1, 1, 2, 2]");
+
+        // ordered by value whatever order they were added in
+        let input = "[x for x in set{\"b\", 10, \"a\", -1}]";
+        assert_eq!(run(input).inspect(), "[-1, 10, a, b]");
+
+        let message = |body: &str| run(&format!("try {{ {} }} catch e {{ e.message }}", body)).inspect();
+        assert_eq!(message("set{1, [2]}"), "Unusable as set element: [2]");
+        assert_eq!(message("add(set{}, {\"a\": 1})"), "Unusable as set element: {a: 1}");
+        assert_eq!(message("set{1} | [2]"), "Unsupported types for '|' operator: set{1} and [2]");
+        assert_eq!(message("1 & 2"), "Unsupported types for '&' operator: 1 and 2");
+    }
+
+    #[test]
+    fn test_eval_atoms() {
+        let input = "let state = :pending;
+            let counts = {:ok: 2, :error: 1};
+            [state, state == :pending, :ok == :error, :ok == \"ok\", counts, counts[:ok], :error in counts]";
+        assert_eq!(
+            run(input).inspect(),
+            "[:pending, true, false, false, {:error: 1, :ok: 2}, 2, true]"
+        );
+
+        let input = "fun describe(s) { match s { :ok => \"done\", :error | :failed => \"broken\", other => other } }
+            [describe(:ok), describe(:failed), describe(:waiting)]";
+        assert_eq!(run(input).inspect(), "[done, broken, :waiting]");
+
+        // every `:ok` is the same interned value
+        match (run(":ok"), run("[:ok][0]")) {
+            (ObjectType::Atom(a), ObjectType::Atom(b)) => assert!(Rc::ptr_eq(&a, &b)),
+            other => panic!("expected two atoms, got {:?}", other),
+        }
+    }
+}
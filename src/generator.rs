@@ -0,0 +1,258 @@
+use std::rc::Rc;
+
+use crate::ast::{Expression, Pattern, Statement};
+use crate::environment::{Env, Environment};
+use crate::evaluator::{
+    bind_loop_item, eval, eval_expression, is_truthy, iterate, match_pattern, run_deferred,
+    select_arm,
+};
+use crate::object::ObjectType;
+
+// the blocks a suspended generator is inside of, so `yield` can return to the caller
+// and the next call picks up where it left off. Only the statements directly in these
+// blocks are stepped through, `if`, `if let`, `match` and `for` at statement position
+// open a new frame and everything else is evaluated in one go
+enum Frame {
+    // `next` indexes the statement to run when the generator is resumed
+    Block {
+        statements: Rc<Vec<Statement>>,
+        next: usize,
+        env: Env,
+        // the `defer`red expressions seen so far, run when the frame is popped
+        deferred: Vec<Expression>,
+    },
+    // a `for` loop between two iterations
+    Loop {
+        pattern: Box<Pattern>,
+        items: Box<dyn Iterator<Item = ObjectType>>,
+        body: Rc<Vec<Statement>>,
+        env: Env,
+    },
+}
+
+// what a generator function's call returns, each item is the value of a `yield`
+pub struct Generator {
+    frames: Vec<Frame>,
+}
+
+impl Generator {
+    pub fn new(body: Vec<Statement>, env: Env) -> Generator {
+        Generator {
+            frames: vec![Frame::Block {
+                statements: Rc::new(body),
+                next: 0,
+                env,
+                deferred: vec![],
+            }],
+        }
+    }
+
+    fn enter(&mut self, statements: &[Statement], env: Env) {
+        self.frames.push(Frame::Block {
+            statements: Rc::new(statements.to_vec()),
+            next: 0,
+            env,
+            deferred: vec![],
+        });
+    }
+
+    // leaves the innermost frame, running its deferred expressions on top of `result`
+    fn pop(&mut self, result: ObjectType) -> ObjectType {
+        match self.frames.pop() {
+            Some(Frame::Block { deferred, env, .. }) => run_deferred(&deferred, &env, result),
+            _ => result,
+        }
+    }
+
+    // runs one statement, returning the value it yielded or the error it threw
+    fn step(&mut self, statement: &Statement, env: &Env) -> Option<ObjectType> {
+        let expression = match statement {
+            Statement::Expression(stmt) => &stmt.expression,
+            Statement::Defer(expr) => {
+                if let Some(Frame::Block { deferred, .. }) = self.frames.last_mut() {
+                    deferred.push(expr.clone());
+                }
+                return None;
+            }
+            _ => return self.settle(eval(statement, env)),
+        };
+
+        match expression {
+            Expression::YIELD(value) => match eval_expression(value, env) {
+                value if value.is_unwinding() => self.settle(value),
+                value => Some(value),
+            },
+            Expression::IF {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let condition = eval_expression(condition, env);
+                if condition.is_unwinding() {
+                    return self.settle(condition);
+                }
+                if is_truthy(&condition) {
+                    self.enter(consequence, env.clone());
+                } else if let Some(alternative) = alternative {
+                    self.enter(alternative, env.clone());
+                }
+                None
+            }
+            Expression::IFLET {
+                pattern,
+                value,
+                consequence,
+                alternative,
+            } => {
+                let value = eval_expression(value, env);
+                if value.is_unwinding() {
+                    return self.settle(value);
+                }
+                let mut bindings = vec![];
+                if match_pattern(pattern, &value, &mut bindings, env).is_ok() {
+                    let scope = Environment::new_enclosed(env.clone());
+                    for (name, bound) in bindings {
+                        scope.borrow_mut().set(name, bound);
+                    }
+                    self.enter(consequence, scope);
+                } else if let Some(alternative) = alternative {
+                    self.enter(alternative, env.clone());
+                }
+                None
+            }
+            Expression::MATCH { value, arms } => {
+                let value = eval_expression(value, env);
+                if value.is_unwinding() {
+                    return self.settle(value);
+                }
+                match select_arm(&value, arms, env) {
+                    Ok((arm, scope)) => {
+                        self.enter(&arm.body, scope);
+                        None
+                    }
+                    Err(thrown) => self.settle(thrown),
+                }
+            }
+            Expression::FOR {
+                pattern,
+                iterable,
+                body,
+            } => {
+                let iterable = eval_expression(iterable, env);
+                if iterable.is_unwinding() {
+                    return self.settle(iterable);
+                }
+                match iterate(iterable) {
+                    Ok(items) => {
+                        self.frames.push(Frame::Loop {
+                            pattern: pattern.clone(),
+                            items,
+                            body: Rc::new(body.clone()),
+                            env: env.clone(),
+                        });
+                        None
+                    }
+                    Err(thrown) => self.settle(thrown),
+                }
+            }
+            _ => self.settle(eval_expression(expression, env)),
+        }
+    }
+
+    // pops every frame, handing out the error if there is one once the deferred code has run
+    fn finish(&mut self, mut result: ObjectType) -> Option<ObjectType> {
+        while !self.frames.is_empty() {
+            result = self.pop(result);
+        }
+        match result {
+            thrown @ ObjectType::Throw(_) => Some(thrown),
+            _ => None,
+        }
+    }
+
+    // unwinds the frames for a `ret`, `break`, `continue` or throw, handing out the error if there is one
+    fn settle(&mut self, result: ObjectType) -> Option<ObjectType> {
+        match result {
+            // the generator is finished either way
+            ObjectType::Return(_) => self.finish(ObjectType::Null),
+            thrown @ ObjectType::Throw(_) => self.finish(thrown),
+            jump @ (ObjectType::Break | ObjectType::Continue) => {
+                let mut result = ObjectType::Null;
+                while let Some(Frame::Block { .. }) = self.frames.last() {
+                    result = self.pop(result);
+                }
+                if let ObjectType::Throw(_) = result {
+                    return self.finish(result);
+                }
+                if self.frames.is_empty() {
+                    return Some(ObjectType::error(
+                        "SyntaxError",
+                        "'break' and 'continue' can only be used inside a loop",
+                    ));
+                }
+                // `break` leaves the loop too, `continue` goes on with its next item
+                if let ObjectType::Break = jump {
+                    self.frames.pop();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = ObjectType;
+
+    // runs the body up to its next `yield`, None once it has returned or run off the end
+    fn next(&mut self) -> Option<ObjectType> {
+        loop {
+            let (statements, index, env) = match self.frames.last_mut()? {
+                Frame::Block {
+                    statements,
+                    next,
+                    env,
+                    ..
+                } => {
+                    if *next == statements.len() {
+                        if let thrown @ ObjectType::Throw(_) = self.pop(ObjectType::Null) {
+                            return self.finish(thrown);
+                        }
+                        continue;
+                    }
+                    *next += 1;
+                    (statements.clone(), *next - 1, env.clone())
+                }
+                Frame::Loop {
+                    pattern,
+                    items,
+                    body,
+                    env,
+                } => {
+                    let item = match items.next() {
+                        Some(item) => item,
+                        None => {
+                            self.frames.pop();
+                            continue;
+                        }
+                    };
+                    let body = body.clone();
+                    match bind_loop_item(pattern, item, env) {
+                        Ok(scope) => self.frames.push(Frame::Block {
+                            statements: body,
+                            next: 0,
+                            env: scope,
+                            deferred: vec![],
+                        }),
+                        Err(thrown) => return self.settle(thrown),
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(out) = self.step(&statements[index], &env) {
+                return Some(out);
+            }
+        }
+    }
+}
@@ -1,23 +1,36 @@
+use crate::token::Span;
 use crate::token::Token;
 use crate::token::TokenType;
 
 pub struct Lexer{
-    input: String,
+    // positions count chars, so non-ASCII text in strings doesn't shift the slices below
+    input: Vec<char>,
     index: usize,
     next_index:usize,
-    pub ch: char
+    pub ch: char,
+    line: usize,
+    column: usize,
+    // where the last token returned by `next_token` started
+    pub span: Span,
+    // one entry per open `${` in a string, counting the `{` nested inside it
+    interpolations: Vec<usize>,
 }
 
 impl Lexer {
     pub fn new(input: String) -> Lexer{
-        let mut l = Lexer { input: input, index: 0, next_index: 0, ch: '\0' };
+        let mut l = Lexer { input: input.chars().collect(), index: 0, next_index: 0, ch: '\0', line: 1, column: 0, span: Span::default(), interpolations: vec![] };
         l.read_char();
         return l;
     }
 
 
     pub fn read_char(&mut self){
-        self.ch =self.input.chars().nth(self.next_index).unwrap_or('\0');
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 0;
+        }
+        self.column += 1;
+        self.ch =self.input.get(self.next_index).copied().unwrap_or('\0');
         
         self.index = self.next_index;
         self.next_index += 1;
@@ -32,7 +45,7 @@ impl Lexer {
 
         let e = self.index;
 
-        return String::from(&self.input[s..e]); // from start index to end
+        return self.input[s..e].iter().collect(); // from start index to end
     }
 
     pub  fn read_number(&mut self) -> String {
@@ -43,11 +56,39 @@ impl Lexer {
 
         let e = self.index;
 
-        return String::from(&self.input[s..e]); // from start index to end
+        return self.input[s..e].iter().collect(); // from start index to end
     }
 
 
-    
+    // reads the text of a string literal up to its closing '"' or the next `${`,
+    // the current char is the opening '"' or the '}' closing an interpolation.
+    // The bool is true when the text stopped at a `${`
+    pub fn read_string(&mut self) -> Result<(String, bool), String> {
+        let mut out = String::new();
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => return Ok((out, false)),
+                '\0' => return Err(out),
+                '$' if self.peek_char() == '{' => {
+                    self.read_char(); // Move to the '{'
+                    self.interpolations.push(0);
+                    return Ok((out, true));
+                }
+                '\\' => {
+                    self.read_char();
+                    match self.ch {
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        '\0' => return Err(out),
+                        other => out.push(other),
+                    }
+                }
+                other => out.push(other),
+            }
+        }
+    }
+
     pub fn skip_spaces(&mut self){
         let space_types = [' ', '\t', '\n'];
         while space_types.contains(&self.ch){
@@ -55,47 +96,152 @@ impl Lexer {
         }
     }
 
+    // reads a run of symbol characters and leaves the lexer on its last one. A known
+    // operator gets its own token type and any other run is an OPERATOR for the parser
+    // to look up, except that a `-` or `!` after a known operator starts the next
+    // token so `x=-1` and `a*!b` still lex as two operators
+    fn read_operator(&mut self) -> Token {
+        let mut run = String::from(self.ch);
+        let mut next = self.next_index;
+        while let Some(ch) = self.input.get(next).copied().filter(|ch| Lexer::is_symbol(*ch)) {
+            run.push(ch);
+            next += 1;
+        }
+
+        let known = (1..=run.len()).rev().find(|&n| Lexer::operator_type(&run[..n]).is_some());
+        let (t, len) = match known {
+            Some(n) if run[n..].chars().all(|ch| ch == '-' || ch == '!') => {
+                (Lexer::operator_type(&run[..n]).unwrap(), n)
+            }
+            _ => (TokenType::OPERATOR, run.len()),
+        };
+        for _ in 1..len {
+            self.read_char();
+        }
+        run.truncate(len);
+        Token { t, literal: run }
+    }
+
+    fn operator_type(symbols: &str) -> Option<TokenType> {
+        let t = match symbols {
+            "+" => TokenType::PLUS,
+            "-" => TokenType::MINUS,
+            "*" => TokenType::STAR,
+            "/" => TokenType::SLASH,
+            "%" => TokenType::PERCENT,
+            ">" => TokenType::GT,
+            "<" => TokenType::LT,
+            "=" => TokenType::ASSIGN,
+            "==" => TokenType::EQ,
+            "=>" => TokenType::FATARROW,
+            "!" => TokenType::BANG,
+            "!=" => TokenType::NEQ,
+            // built in, but without a token type of their own
+            ">=" | "<=" | "&" | "^" => TokenType::OPERATOR,
+            _ => return None,
+        };
+        Some(t)
+    }
+
+    fn is_symbol(ch: char) -> bool {
+        return "+-*/%<>=!&^~".contains(ch);
+    }
+
     fn is_letter(ch : char) -> bool{
         return ch.is_ascii_alphabetic() || ch == '_';
     
     }
     fn is_digit(ch : char) -> bool{
-        return ch <= '9' && ch >= '0';
+        return ch.is_ascii_digit();
     
     }
     pub fn peek_char(&self)-> char{
-        return self.input.chars().nth(self.next_index).unwrap_or('\0');
+        return self.input.get(self.next_index).copied().unwrap_or('\0');
+    }
+
+    // whether the current char comes straight after a name, a literal or a closing bracket
+    fn follows_word(&self) -> bool {
+        let previous = self.index.checked_sub(1).and_then(|i| self.input.get(i).copied());
+        previous.is_some_and(|ch| Lexer::is_letter(ch) || Lexer::is_digit(ch) || "\")]}".contains(ch))
     }
 
     pub fn next_token(&mut self) -> Token{
         self.skip_spaces();
+        self.span = Span { line: self.line, column: self.column };
         let tok:Token = match self.ch {
-            '+'     => Token { t: TokenType::PLUS, literal: String::from(self.ch) },
-            '-'     => Token { t: TokenType::MINUS,literal: String::from(self.ch) },
-            '*'     => Token { t: TokenType::STAR,literal: String::from(self.ch) },
-            '/'     => Token { t: TokenType::SLASH,literal: String::from(self.ch) },
-            '>'     => Token { t: TokenType::GT,literal: String::from(self.ch) },
-            '<'     => Token { t: TokenType::LT,literal: String::from(self.ch) },
+            ch if Lexer::is_symbol(ch) => self.read_operator(),
             ','     => Token { t: TokenType::COMMA, literal: String::from(self.ch) },
             '('     => Token { t: TokenType::LPAREN, literal: String::from(self.ch) },
             ')'     => Token { t: TokenType::RPAREN, literal: String::from(self.ch) },
-            '{'     => Token { t: TokenType::LBRACE, literal: String::from(self.ch) },
-            '}'     => Token { t: TokenType::RBRACE, literal: String::from(self.ch) },
+            '{'     => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                Token { t: TokenType::LBRACE, literal: String::from(self.ch) }
+            },
+            // the '}' closing a `${` carries on with the rest of the string
+            '}' if self.interpolations.last() == Some(&0) => {
+                self.interpolations.pop();
+                match self.read_string() {
+                    Ok((str, true)) => Token { t: TokenType::TEMPLATEMID, literal: str },
+                    Ok((str, false)) => Token { t: TokenType::TEMPLATEEND, literal: str },
+                    Err(str) => Token { t: TokenType::ILLEGAL, literal: str },
+                }
+            },
+            '}'     => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                Token { t: TokenType::RBRACE, literal: String::from(self.ch) }
+            },
+            '['     => Token { t: TokenType::LBRACKET, literal: String::from(self.ch) },
+            ']'     => Token { t: TokenType::RBRACKET, literal: String::from(self.ch) },
+            '|'     => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    Token { t: TokenType::PIPEARROW, literal: String::from("|>") }
+                } else {
+                    Token { t: TokenType::PIPE, literal: String::from(self.ch) }
+                }
+            },
             ';'     => Token { t: TokenType::SEMICOLON, literal: String::from(self.ch) },
-            '='     => {
-                if self.peek_char() == '=' {
+            // `:ok` is an atom when the name follows right away and the ':' does not end
+            // a key or a name, as in `{a: :ok}`, `{"a":1}` and `x: int`
+            ':' if Lexer::is_letter(self.peek_char()) && !self.follows_word() => {
+                self.read_char(); // Skip the ':'
+                return Token { t: TokenType::ATOM, literal: self.read_identifier() };
+            },
+            ':'     => Token { t: TokenType::COLON, literal: String::from(self.ch) },
+            '?'     => {
+                if self.peek_char() == '.' {
+                    self.read_char();
+                    Token { t: TokenType::QUESTIONDOT, literal: String::from("?.") }
+                } else if self.peek_char() == '?' {
                     self.read_char();
-                    Token { t: TokenType::EQ, literal: String::from("==") }
-                }else {
-                    Token { t: TokenType::ASSIGN, literal: String::from(self.ch) }
+                    Token { t: TokenType::COALESCE, literal: String::from("??") }
+                } else {
+                    Token { t: TokenType::QUESTION, literal: String::from(self.ch) }
                 }
             },
-            '!'     => {
-                if self.peek_char() == '=' {
+            '"'     => match self.read_string() {
+                Ok((str, false)) => Token { t: TokenType::STRING, literal: str },
+                Ok((str, true)) => Token { t: TokenType::TEMPLATE, literal: str },
+                Err(str) => Token { t: TokenType::ILLEGAL, literal: str },
+            },
+            '.'     => {
+                if self.peek_char() == '.' {
                     self.read_char();
-                    Token { t: TokenType::NEQ, literal: String::from("!=") }
-                }else {
-                    Token { t: TokenType::BANG, literal: String::from(self.ch) }
+                    if self.peek_char() == '.' {
+                        self.read_char();
+                        Token { t: TokenType::ELLIPSIS, literal: String::from("...") }
+                    } else if self.peek_char() == '=' {
+                        self.read_char();
+                        Token { t: TokenType::DOTDOTEQ, literal: String::from("..=") }
+                    } else {
+                        Token { t: TokenType::DOTDOT, literal: String::from("..") }
+                    }
+                } else {
+                    Token { t: TokenType::DOT, literal: String::from(self.ch) }
                 }
             },
             '\0'    => Token { t: TokenType::EOF, literal: String::from(self.ch) },
@@ -121,6 +267,7 @@ impl Lexer {
 
 #[cfg(test)]
 mod lexer_tests{
+    use crate::token::Span;
     use crate::token::Token;
     use crate::token::TokenType;
 
@@ -128,7 +275,7 @@ mod lexer_tests{
 
     #[test]
     fn test_next_token(){
-        let input = String::from("+=(){},;");
+        let input = String::from("+ =(){},;");
 
         let tests = [
             Token{t:TokenType::PLUS, literal: String::from("+")},
@@ -178,7 +325,7 @@ mod lexer_tests{
 
     #[test]
     fn test_arithmetic_operators() {
-        let input = String::from("+-*/");
+        let input = String::from("+ - * /");
 
         let tests = [
             Token { t: TokenType::PLUS, literal: String::from("+") },
@@ -203,10 +350,9 @@ mod lexer_tests{
         let input = String::from(">= <= != ==");
 
         let tests = [
-            Token { t: TokenType::GT, literal: String::from(">") },
-            Token { t: TokenType::ASSIGN, literal: String::from("=") },
-            Token { t: TokenType::LT, literal: String::from("<") },
-            Token { t: TokenType::ASSIGN, literal: String::from("=") },
+            // runs of symbols are one token, the parser decides if they are declared
+            Token { t: TokenType::OPERATOR, literal: String::from(">=") },
+            Token { t: TokenType::OPERATOR, literal: String::from("<=") },
             Token { t: TokenType::NEQ, literal: String::from("!=") },
             Token { t: TokenType::EQ, literal: String::from("==") },
             Token { t: TokenType::EOF, literal: String::from("\0") },
@@ -248,6 +394,171 @@ mod lexer_tests{
         }
     }
 
+    #[test]
+    fn test_lambda_and_array_tokens() {
+        let input = String::from("|x| [x]");
+
+        let tests = [
+            Token { t: TokenType::PIPE, literal: String::from("|") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::PIPE, literal: String::from("|") },
+            Token { t: TokenType::LBRACKET, literal: String::from("[") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::RBRACKET, literal: String::from("]") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            println!("Testing: {:?} with type: {}", tok.t, tok.literal);
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let input = String::from("match x { [a, ..rest] => 1..=5, \"hi\": _ }");
+
+        let tests = [
+            Token { t: TokenType::MATCH, literal: String::from("match") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::LBRACE, literal: String::from("{") },
+            Token { t: TokenType::LBRACKET, literal: String::from("[") },
+            Token { t: TokenType::IDENT, literal: String::from("a") },
+            Token { t: TokenType::COMMA, literal: String::from(",") },
+            Token { t: TokenType::DOTDOT, literal: String::from("..") },
+            Token { t: TokenType::IDENT, literal: String::from("rest") },
+            Token { t: TokenType::RBRACKET, literal: String::from("]") },
+            Token { t: TokenType::FATARROW, literal: String::from("=>") },
+            Token { t: TokenType::INT, literal: String::from("1") },
+            Token { t: TokenType::DOTDOTEQ, literal: String::from("..=") },
+            Token { t: TokenType::INT, literal: String::from("5") },
+            Token { t: TokenType::COMMA, literal: String::from(",") },
+            Token { t: TokenType::STRING, literal: String::from("hi") },
+            Token { t: TokenType::COLON, literal: String::from(":") },
+            Token { t: TokenType::IDENT, literal: String::from("_") },
+            Token { t: TokenType::RBRACE, literal: String::from("}") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            println!("Testing: {:?} with type: {}", tok.t, tok.literal);
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_pipe_tokens() {
+        let input = String::from("xs |> f |x| x");
+
+        let tests = [
+            Token { t: TokenType::IDENT, literal: String::from("xs") },
+            Token { t: TokenType::PIPEARROW, literal: String::from("|>") },
+            Token { t: TokenType::IDENT, literal: String::from("f") },
+            Token { t: TokenType::PIPE, literal: String::from("|") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::PIPE, literal: String::from("|") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_question_tokens() {
+        let input = String::from("a?.b ?? null; f()?");
+
+        let tests = [
+            Token { t: TokenType::IDENT, literal: String::from("a") },
+            Token { t: TokenType::QUESTIONDOT, literal: String::from("?.") },
+            Token { t: TokenType::IDENT, literal: String::from("b") },
+            Token { t: TokenType::COALESCE, literal: String::from("??") },
+            Token { t: TokenType::NULL, literal: String::from("null") },
+            Token { t: TokenType::SEMICOLON, literal: String::from(";") },
+            Token { t: TokenType::IDENT, literal: String::from("f") },
+            Token { t: TokenType::LPAREN, literal: String::from("(") },
+            Token { t: TokenType::RPAREN, literal: String::from(")") },
+            Token { t: TokenType::QUESTION, literal: String::from("?") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_interpolated_string_tokens() {
+        let input = String::from("\"a ${x + {}} b ${\"c${y}\"}!\"");
+
+        let tests = [
+            Token { t: TokenType::TEMPLATE, literal: String::from("a ") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::PLUS, literal: String::from("+") },
+            Token { t: TokenType::LBRACE, literal: String::from("{") },
+            Token { t: TokenType::RBRACE, literal: String::from("}") },
+            Token { t: TokenType::TEMPLATEMID, literal: String::from(" b ") },
+            Token { t: TokenType::TEMPLATE, literal: String::from("c") },
+            Token { t: TokenType::IDENT, literal: String::from("y") },
+            Token { t: TokenType::TEMPLATEEND, literal: String::from("") },
+            Token { t: TokenType::TEMPLATEEND, literal: String::from("!") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let input = String::from("let x = 1;\n  f(...xs)");
+
+        let tests = [
+            (TokenType::LET, 1, 1),
+            (TokenType::IDENT, 1, 5),
+            (TokenType::ASSIGN, 1, 7),
+            (TokenType::INT, 1, 9),
+            (TokenType::SEMICOLON, 1, 10),
+            (TokenType::IDENT, 2, 3),
+            (TokenType::LPAREN, 2, 4),
+            (TokenType::ELLIPSIS, 2, 5),
+            (TokenType::IDENT, 2, 8),
+            (TokenType::RPAREN, 2, 10),
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for (t, line, column) in tests {
+            let tok = lex.next_token();
+            println!("Testing: {:?} at {}", tok.t, lex.span);
+            assert!(tok.t == t, "There is an error with the Token Types");
+            assert!(lex.span == Span { line, column }, "There is an error with the Token Spans");
+        }
+    }
+
     #[test]
     fn test_illegal_characters() {
         let input = String::from("@#$");
@@ -268,4 +579,97 @@ mod lexer_tests{
             assert!(tok.literal == test.literal, "There is an error with the Token Literals");
         }
     }
+
+    #[test]
+    fn test_symbol_run_tokens() {
+        let input = String::from("a <+> b x=-1 !!c |>");
+
+        let tests = [
+            Token { t: TokenType::IDENT, literal: String::from("a") },
+            Token { t: TokenType::OPERATOR, literal: String::from("<+>") },
+            Token { t: TokenType::IDENT, literal: String::from("b") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            // a trailing `-` or `!` is a prefix operator of its own
+            Token { t: TokenType::ASSIGN, literal: String::from("=") },
+            Token { t: TokenType::MINUS, literal: String::from("-") },
+            Token { t: TokenType::INT, literal: String::from("1") },
+            Token { t: TokenType::BANG, literal: String::from("!") },
+            Token { t: TokenType::BANG, literal: String::from("!") },
+            Token { t: TokenType::IDENT, literal: String::from("c") },
+            Token { t: TokenType::PIPEARROW, literal: String::from("|>") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_atoms() {
+        let input = String::from("[:ok, :not_found] {a: :error} {\"b\":c} x: int");
+
+        let tests = [
+            Token { t: TokenType::LBRACKET, literal: String::from("[") },
+            Token { t: TokenType::ATOM, literal: String::from("ok") },
+            Token { t: TokenType::COMMA, literal: String::from(",") },
+            Token { t: TokenType::ATOM, literal: String::from("not_found") },
+            Token { t: TokenType::RBRACKET, literal: String::from("]") },
+            Token { t: TokenType::LBRACE, literal: String::from("{") },
+            Token { t: TokenType::IDENT, literal: String::from("a") },
+            Token { t: TokenType::COLON, literal: String::from(":") },
+            Token { t: TokenType::ATOM, literal: String::from("error") },
+            Token { t: TokenType::RBRACE, literal: String::from("}") },
+            // a ':' straight after a key is never an atom
+            Token { t: TokenType::LBRACE, literal: String::from("{") },
+            Token { t: TokenType::STRING, literal: String::from("b") },
+            Token { t: TokenType::COLON, literal: String::from(":") },
+            Token { t: TokenType::IDENT, literal: String::from("c") },
+            Token { t: TokenType::RBRACE, literal: String::from("}") },
+            Token { t: TokenType::IDENT, literal: String::from("x") },
+            Token { t: TokenType::COLON, literal: String::from(":") },
+            Token { t: TokenType::IDENT, literal: String::from("int") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_strings() {
+        let input = String::from("let s = \"héllo ✓\"; let abc = 12; abc");
+
+        let tests = [
+            Token { t: TokenType::LET, literal: String::from("let") },
+            Token { t: TokenType::IDENT, literal: String::from("s") },
+            Token { t: TokenType::ASSIGN, literal: String::from("=") },
+            Token { t: TokenType::STRING, literal: String::from("héllo ✓") },
+            Token { t: TokenType::SEMICOLON, literal: String::from(";") },
+            Token { t: TokenType::LET, literal: String::from("let") },
+            Token { t: TokenType::IDENT, literal: String::from("abc") },
+            Token { t: TokenType::ASSIGN, literal: String::from("=") },
+            Token { t: TokenType::INT, literal: String::from("12") },
+            Token { t: TokenType::SEMICOLON, literal: String::from(";") },
+            Token { t: TokenType::IDENT, literal: String::from("abc") },
+            Token { t: TokenType::EOF, literal: String::from("\0") },
+        ];
+
+        let mut lex = Lexer::new(input);
+
+        for test in tests.iter() {
+            let tok = lex.next_token();
+            assert!(tok.t == test.t, "There is an error with the Token Types");
+            assert!(tok.literal == test.literal, "There is an error with the Token Literals");
+        }
+    }
 }
@@ -0,0 +1,285 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Expression, MacroStatement, Program, Rewriter, Statement};
+use crate::environment::{Env, Environment};
+use crate::evaluator::{eval_block, eval_expression};
+use crate::object::{Object, ObjectType};
+
+// how many times a macro's output may expand into further macro calls
+const MAX_DEPTH: usize = 64;
+
+// the macros known to a program, the REPL keeps them across lines
+pub type Macros = HashMap<String, Rc<MacroStatement>>;
+
+thread_local! {
+    // numbers the fresh names `quote` gives the bindings it introduces
+    static GENSYM: Cell<usize> = const { Cell::new(0) };
+}
+
+// takes the top-level `macro` declarations out of the program and replaces every
+// call to one of them with the syntax it returns
+pub fn expand(program: &mut Program, macros: &mut Macros) -> Result<(), ObjectType> {
+    let statements = std::mem::take(&mut program.statements);
+    let mut remaining = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Macro(definition) => {
+                macros.insert(definition.name.clone(), Rc::new(definition));
+            }
+            statement => remaining.push(statement),
+        }
+    }
+
+    let mut expander = Expander {
+        macros,
+        depth: 0,
+        error: None,
+    };
+    program.statements = remaining
+        .into_iter()
+        .map(|statement| statement.rewrite(&mut expander))
+        .collect();
+
+    match expander.error {
+        Some(thrown) => Err(thrown),
+        None => Ok(()),
+    }
+}
+
+struct Expander<'a> {
+    macros: &'a Macros,
+    depth: usize,
+    // the first failure, the rest of the program is still walked but no longer expanded
+    error: Option<ObjectType>,
+}
+
+impl Expander<'_> {
+    fn call(&mut self, definition: &MacroStatement, expr: &Expression) -> Result<Expression, ObjectType> {
+        let (arguments, named) = match expr {
+            Expression::CALL {
+                arguments, named, ..
+            } => (arguments, named),
+            _ => unreachable!("only calls are expanded"),
+        };
+        if !named.is_empty() || arguments.iter().any(|arg| matches!(arg, Expression::SPREAD(_))) {
+            return Err(ObjectType::error(
+                "MacroError",
+                format!("Macro '{}' only takes positional arguments", definition.name),
+            ));
+        }
+        if arguments.len() != definition.parameters.len() {
+            return Err(ObjectType::error(
+                "MacroError",
+                format!(
+                    "Macro '{}' expects {} arguments, got {}",
+                    definition.name,
+                    definition.parameters.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        if self.depth == MAX_DEPTH {
+            return Err(ObjectType::error(
+                "MacroError",
+                format!("Expansion of macro '{}' nests too deeply", definition.name),
+            ));
+        }
+
+        // the body runs now, with the arguments as syntax rather than values
+        let env = Environment::new();
+        for (parameter, argument) in definition.parameters.iter().zip(arguments) {
+            env.borrow_mut()
+                .set(parameter.clone(), ObjectType::Quote(Box::new(argument.clone())));
+        }
+        let expanded = match eval_block(&definition.body, &env) {
+            ObjectType::Return(value) => *value,
+            thrown @ ObjectType::Throw(_) => return Err(thrown),
+            value => value,
+        };
+        let expanded = to_expression(expanded).map_err(|value| {
+            ObjectType::error(
+                "MacroError",
+                format!("Macro '{}' must return syntax, got {}", definition.name, value.inspect()),
+            )
+        })?;
+
+        // whatever the macro produced may call macros in turn
+        self.depth += 1;
+        let expanded = expanded.rewrite(self);
+        self.depth -= 1;
+        Ok(expanded)
+    }
+}
+
+impl Rewriter for Expander<'_> {
+    fn expression(&mut self, expr: &Expression) -> Option<Expression> {
+        if self.error.is_some() {
+            return None;
+        }
+        let definition = match expr {
+            Expression::CALL { function, .. } => match &**function {
+                Expression::IDENT(name) => self.macros.get(name)?.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match self.call(&definition, expr) {
+            Ok(expanded) => Some(expanded),
+            Err(thrown) => {
+                self.error.get_or_insert(thrown);
+                Some(Expression::NULL)
+            }
+        }
+    }
+}
+
+// the value of `quote(expr)`: the expression with every `unquote(x)` replaced by the
+// syntax of x's value, and the names it binds renamed so they cannot capture the caller's
+pub fn eval_quote(arguments: &[Expression], env: &Env) -> ObjectType {
+    let template = match arguments {
+        [template] => template.clone(),
+        _ => {
+            return ObjectType::error(
+                "TypeError",
+                format!("quote expects 1 argument, got {}", arguments.len()),
+            );
+        }
+    };
+
+    let mut binders = Binders { names: vec![] };
+    let template = template.rewrite(&mut binders);
+
+    let mut splicer = Splicer {
+        renamed: binders
+            .names
+            .into_iter()
+            .map(|name| {
+                let fresh = GENSYM.with(|counter| {
+                    counter.set(counter.get() + 1);
+                    // '@' cannot appear in a source identifier, so nothing the caller writes can clash
+                    format!("{}@{}", name, counter.get())
+                });
+                (name, fresh)
+            })
+            .collect(),
+        env,
+        error: None,
+    };
+    let quoted = template.rewrite(&mut splicer);
+    match splicer.error {
+        Some(thrown) => thrown,
+        None => ObjectType::Quote(Box::new(quoted)),
+    }
+}
+
+fn is_unquote(expr: &Expression) -> Option<&[Expression]> {
+    match expr {
+        Expression::CALL {
+            function,
+            arguments,
+            ..
+        } if **function == Expression::IDENT("unquote".to_string()) => Some(arguments),
+        _ => None,
+    }
+}
+
+// collects the names a template binds outside of its `unquote`s
+struct Binders {
+    names: Vec<String>,
+}
+
+impl Rewriter for Binders {
+    fn expression(&mut self, expr: &Expression) -> Option<Expression> {
+        is_unquote(expr).map(|_| expr.clone())
+    }
+
+    fn binding(&mut self, name: String) -> String {
+        if !self.names.contains(&name) {
+            self.names.push(name.clone());
+        }
+        name
+    }
+}
+
+// renames the template's own bindings and fills in its `unquote`s, the spliced
+// syntax belongs to the caller so it is left as it is
+struct Splicer<'a> {
+    renamed: HashMap<String, String>,
+    env: &'a Env,
+    error: Option<ObjectType>,
+}
+
+impl Rewriter for Splicer<'_> {
+    fn expression(&mut self, expr: &Expression) -> Option<Expression> {
+        if let Expression::IDENT(name) = expr {
+            return self.renamed.get(name).map(|fresh| Expression::IDENT(fresh.clone()));
+        }
+        let arguments = is_unquote(expr)?;
+        let spliced = match arguments {
+            [argument] => match eval_expression(argument, self.env) {
+                thrown @ ObjectType::Throw(_) => Err(thrown),
+                value => to_expression(value).map_err(|value| {
+                    ObjectType::error(
+                        "TypeError",
+                        format!("Cannot unquote {}", value.inspect()),
+                    )
+                }),
+            },
+            _ => Err(ObjectType::error(
+                "TypeError",
+                format!("unquote expects 1 argument, got {}", arguments.len()),
+            )),
+        };
+        match spliced {
+            Ok(spliced) => Some(spliced),
+            Err(thrown) => {
+                self.error.get_or_insert(thrown);
+                Some(Expression::NULL)
+            }
+        }
+    }
+
+    fn binding(&mut self, name: String) -> String {
+        self.renamed.get(&name).cloned().unwrap_or(name)
+    }
+}
+
+// the syntax that evaluates to `value`, plain data is turned back into literals
+fn to_expression(value: ObjectType) -> Result<Expression, ObjectType> {
+    match value {
+        ObjectType::Quote(expr) => Ok(*expr),
+        ObjectType::Number(int) => Ok(Expression::INT(int)),
+        ObjectType::String(str) => Ok(Expression::STRING(str)),
+        ObjectType::Boolean(bool) => Ok(Expression::BOOLEAN(bool)),
+        ObjectType::Null => Ok(Expression::NULL),
+        ObjectType::Atom(name) => Ok(Expression::ATOM(name.to_string())),
+        ObjectType::Array(elements) => Ok(Expression::ARRAY(
+            elements
+                .into_iter()
+                .map(to_expression)
+                .collect::<Result<_, _>>()?,
+        )),
+        ObjectType::Tuple(elements) => Ok(Expression::TUPLE(
+            elements
+                .into_iter()
+                .map(to_expression)
+                .collect::<Result<_, _>>()?,
+        )),
+        ObjectType::Set(elements) => Ok(Expression::SET(
+            elements
+                .borrow()
+                .iter()
+                .map(|element| to_expression(element.to_object()))
+                .collect::<Result<_, _>>()?,
+        )),
+        ObjectType::Map(pairs) => Ok(Expression::MAP(
+            pairs
+                .into_iter()
+                .map(|(key, value)| Ok((to_expression(key.to_object())?, to_expression(value)?)))
+                .collect::<Result<_, _>>()?,
+        )),
+        other => Err(other),
+    }
+}
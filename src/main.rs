@@ -1,3 +1,5 @@
+#![allow(clippy::upper_case_acronyms, clippy::needless_return)]
+
 use std::io::{self, BufReader};
 use std::env;
 
@@ -8,13 +10,26 @@ mod parser;
 mod ast;
 mod object;
 mod evaluator;
+mod environment;
+mod builtins;
+mod module;
+mod generator;
+mod macros;
+mod checker;
+mod assertion;
 
 use repl::start;
 
 fn main() {
 
     // get the args from the command line
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // `--no-contracts` skips `requires`, `ensures` and `invariant` checks for speed
+    if let Some(index) = args.iter().position(|arg| arg == "--no-contracts") {
+        args.remove(index);
+        evaluator::set_contracts(false);
+    }
 
     // check if the args is empty
     if args.len() == 1 {
@@ -29,8 +44,16 @@ fn main() {
         return;
     }
 
+    // `ember check file.em` reports type errors without running the file
+    if args.len() == 3 && args[1] == "check" {
+        check_file(&args[2]);
+        return;
+    }
+
     let filename = &args[1];
     println!("Running file: {}", filename);
+    // imports in the file are resolved relative to it
+    module::enter_file(std::path::Path::new(filename));
     let rfile = std::fs::File::open(filename).expect("Error opening file");
     let reader = BufReader::new(rfile);
 
@@ -40,3 +63,32 @@ fn main() {
 
 
 }
+
+fn check_file(filename: &str) {
+    let input = std::fs::read_to_string(filename).expect("Error opening file");
+    let mut parser = parser::Parser::new(lexer::Lexer::new(input));
+    let mut program = match parser.parse_program() {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            std::process::exit(1);
+        }
+    };
+    for warning in parser.warnings() {
+        eprintln!("{}: warning: {}", filename, warning);
+    }
+    if let Err(err) = macros::expand(&mut program, &mut macros::Macros::new()) {
+        eprintln!("{}: {}", filename, object::Object::inspect(&err));
+        std::process::exit(1);
+    }
+
+    let errors = checker::check(&program);
+    if errors.is_empty() {
+        println!("{}: no type errors", filename);
+        return;
+    }
+    for error in &errors {
+        eprintln!("{}: {}", filename, error);
+    }
+    std::process::exit(1);
+}
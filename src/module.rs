@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::ast::{Expression, Pattern, Statement};
+use crate::environment::Environment;
+use crate::evaluator;
+use crate::macros;
+use crate::lexer::Lexer;
+use crate::object::{Module, ObjectType};
+use crate::parser::Parser;
+
+thread_local! {
+    // every module is evaluated once, keyed by its canonical path
+    static CACHE: RefCell<HashMap<PathBuf, Rc<Module>>> = RefCell::new(HashMap::new());
+    // the files currently being evaluated, innermost last
+    static STACK: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+// marks `path` as the file being run, so its imports resolve next to it
+pub fn enter_file(path: &Path) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    STACK.with(|stack| stack.borrow_mut().push(path));
+}
+
+// loads the module at `path`, evaluating it the first time it is imported
+pub fn import(path: &str) -> Result<Rc<Module>, ObjectType> {
+    let resolved = match resolve(path) {
+        Some(resolved) => resolved,
+        None => {
+            return Err(ObjectType::error(
+                "ImportError",
+                format!("Cannot find module \"{}\"", path),
+            ));
+        }
+    };
+
+    if let Some(module) = CACHE.with(|cache| cache.borrow().get(&resolved).cloned()) {
+        return Ok(module);
+    }
+
+    let chain = STACK.with(|stack| stack.borrow().clone());
+    if let Some(start) = chain.iter().position(|file| *file == resolved) {
+        let mut files: Vec<String> = chain[start..].iter().map(|f| f.display().to_string()).collect();
+        files.push(resolved.display().to_string());
+        return Err(ObjectType::error(
+            "ImportError",
+            format!("Import cycle: {}", files.join(" -> ")),
+        ));
+    }
+
+    let source = match fs::read_to_string(&resolved) {
+        Ok(source) => source,
+        Err(err) => {
+            return Err(ObjectType::error(
+                "ImportError",
+                format!("Cannot read module \"{}\": {}", path, err),
+            ));
+        }
+    };
+    let mut parser = Parser::new(Lexer::new(source));
+    let mut program = match parser.parse_program() {
+        Ok(program) => program,
+        Err(err) => {
+            return Err(ObjectType::error(
+                "ImportError",
+                format!("Cannot parse module \"{}\": {}", path, err),
+            ));
+        }
+    };
+    for warning in parser.warnings() {
+        eprintln!("{}: warning: {}", resolved.display(), warning);
+    }
+    // a module's macros are its own, they are not exported
+    macros::expand(&mut program, &mut macros::Macros::new())?;
+
+    let env = Environment::new();
+    STACK.with(|stack| stack.borrow_mut().push(resolved.clone()));
+    let mut failure = None;
+    for statement in program.statements.iter() {
+        if let thrown @ ObjectType::Throw(_) = evaluator::eval(statement, &env) {
+            failure = Some(thrown);
+            break;
+        }
+    }
+    STACK.with(|stack| stack.borrow_mut().pop());
+    if let Some(thrown) = failure {
+        return Err(thrown);
+    }
+
+    let mut exports = HashMap::new();
+    for statement in program.statements.iter() {
+        if let Statement::Pub(inner) = statement {
+            for name in declared_names(inner) {
+                if let Some(value) = env.borrow().get(&name) {
+                    exports.insert(name, value);
+                }
+            }
+        }
+    }
+
+    let module = Rc::new(Module {
+        name: path.to_string(),
+        exports,
+    });
+    CACHE.with(|cache| cache.borrow_mut().insert(resolved, module.clone()));
+    Ok(module)
+}
+
+// the name `import "lib/utils.em";` binds when there is no `as`
+pub fn default_alias(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// looks next to the importing file first, then in each `EMBER_PATH` directory
+fn resolve(path: &str) -> Option<PathBuf> {
+    let base = STACK
+        .with(|stack| stack.borrow().last().and_then(|file| file.parent().map(Path::to_path_buf)))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let mut candidates = vec![base.join(path)];
+    if let Some(search) = env::var_os("EMBER_PATH") {
+        candidates.extend(env::split_paths(&search).map(|dir| dir.join(path)));
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| candidate.canonicalize().ok())
+}
+
+// the names a top-level declaration introduces
+fn declared_names(statement: &Statement) -> Vec<String> {
+    match statement {
+        Statement::Let(let_stmt) => {
+            let mut names = vec![];
+            pattern_names(&let_stmt.pattern, &mut names);
+            names
+        }
+        Statement::Expression(expr) => match &expr.expression {
+            Expression::FUNCTION { name: Some(name), .. } => vec![name.clone()],
+            _ => vec![],
+        },
+        Statement::Struct(struct_stmt) => vec![struct_stmt.name.clone()],
+        Statement::Enum(enum_stmt) => vec![enum_stmt.name.clone()],
+        Statement::Trait(trait_stmt) => vec![trait_stmt.name.clone()],
+        _ => vec![],
+    }
+}
+
+fn pattern_names(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::BINDING(name) => names.push(name.clone()),
+        Pattern::ARRAY {
+            before,
+            rest,
+            after,
+        } => {
+            before.iter().for_each(|p| pattern_names(p, names));
+            if let Some(rest) = rest
+                && rest != "_"
+            {
+                names.push(rest.clone());
+            }
+            after.iter().for_each(|p| pattern_names(p, names));
+        }
+        Pattern::MAP(entries) => entries.iter().for_each(|(_, p)| pattern_names(p, names)),
+        Pattern::TUPLE(elements) => elements.iter().for_each(|p| pattern_names(p, names)),
+        // every alternative binds the same names
+        Pattern::OR(alternatives) => {
+            if let Some(first) = alternatives.first() {
+                pattern_names(first, names);
+            }
+        }
+        Pattern::VARIANT { fields, .. } => fields.iter().for_each(|p| pattern_names(p, names)),
+        Pattern::DEFAULT { pattern, .. } | Pattern::TYPED { pattern, .. } => {
+            pattern_names(pattern, names)
+        }
+        Pattern::WILDCARD | Pattern::LITERAL(_) | Pattern::RANGE { .. } => {}
+    }
+}
@@ -1,3 +1,14 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Contract, Expression, Pattern, Statement};
+use crate::environment::Env;
+
+pub type BuiltinFunction = fn(Vec<ObjectType>) -> ObjectType;
+
+pub type SharedSet = Rc<RefCell<BTreeSet<HashKey>>>;
 
 #[derive(Clone, Debug)]
 pub enum ObjectType {
@@ -5,8 +16,68 @@ pub enum ObjectType {
     String(String),
     Boolean(bool),
     Null,
+    // `:ok`, every atom with the same name shares one interned string
+    Atom(Rc<str>),
+    Array(Vec<ObjectType>),
+    // `(a, b)`, fixed once made
+    Tuple(Vec<ObjectType>),
+    Map(BTreeMap<HashKey, ObjectType>),
+    // `set{1, 2}`, holds the same values maps accept as keys. `add` and `remove`
+    // change it in place, so every binding of the set sees the change
+    Set(SharedSet),
+    // a closure keeps the environment it was created in
+    Function {
+        name: Option<String>,
+        parameters: Vec<Pattern>,
+        rest: Option<String>,
+        body: Vec<Statement>,
+        env: Env,
+        // calling it returns a generator instead of running the body
+        generator: bool,
+        contract: Rc<Contract>,
+    },
+    Builtin(String, BuiltinFunction),
+    // `(+)`, calling it with two arguments applies the operator
+    Operator(String),
+    // `add(1, _)`, calling it fills in the `_` arguments
+    Partial(Rc<Partial>),
+    // a `struct` declaration, instances share it so `impl` blocks reach all of them
+    Struct(Rc<StructType>),
+    // struct values are shared, so `p.x = 1` is seen through every reference
+    Instance(Rc<Instance>),
+    Enum(Rc<EnumType>),
+    // a value of one of the enum's variants, `Shape.Circle(2)`
+    Variant(Rc<Variant>),
+    // `Shape.Circle` before it is called, the usize indexes the enum's variants
+    Constructor(Rc<EnumType>, usize),
+    Trait(Rc<TraitType>),
+    // an imported file, only its `pub` names are reachable
+    Module(Rc<Module>),
+    // integers from `start` towards `end`, produced lazily when iterated
+    Range {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+        inclusive: bool,
+    },
+    // unevaluated syntax, what `quote(...)` returns and macro parameters are bound to
+    Quote(Box<Expression>),
+    // a generator or lazy adapter, every copy advances the same sequence
+    Iterator(Rc<LazyIterator>),
+    // what `catch e` binds for runtime errors and `error(...)`
+    Error(Box<ErrorObject>),
+    // wraps the value of a `ret` while it unwinds to the enclosing function
+    Return(Box<ObjectType>),
+    // a thrown value unwinding to the nearest `catch`
+    Throw(Box<ObjectType>),
+    // a `?.` link met null, unwinds to the end of its chain
+    ShortCircuit,
+    // unwind to the innermost loop
+    Break,
+    Continue,
 }
 
+#[allow(dead_code)]
 pub trait Object {
     fn inspect(&self) -> String;
     fn get_type(&self) -> ObjectType;
@@ -21,6 +92,139 @@ impl Object for ObjectType {
             ObjectType::String(str) => str.clone(),
             ObjectType::Boolean(bool) => bool.to_string(),
             ObjectType::Null => "Null".to_string(),
+            ObjectType::Atom(name) => format!(":{}", name),
+            ObjectType::Array(elements) => format!(
+                "[{}]",
+                elements.iter().map(|e| e.inspect()).collect::<Vec<_>>().join(", ")
+            ),
+            ObjectType::Tuple(elements) if elements.len() == 1 => format!("({},)", elements[0].inspect()),
+            ObjectType::Tuple(elements) => format!(
+                "({})",
+                elements.iter().map(|e| e.inspect()).collect::<Vec<_>>().join(", ")
+            ),
+            ObjectType::Map(pairs) => format!(
+                "{{{}}}",
+                pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.to_object().inspect(), v.inspect()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ObjectType::Set(elements) => format!(
+                "set{{{}}}",
+                elements.borrow().iter().map(|e| e.to_object().inspect()).collect::<Vec<_>>().join(", ")
+            ),
+            ObjectType::Function {
+                name,
+                parameters,
+                rest,
+                ..
+            } => {
+                let mut params: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+                if let Some(rest) = rest {
+                    params.push(format!("...{}", rest));
+                }
+                format!(
+                    "fun {}({}) {{ ... }}",
+                    name.as_deref().unwrap_or(""),
+                    params.join(", ")
+                )
+            }
+            ObjectType::Builtin(name, _) => format!("builtin {}", name),
+            ObjectType::Operator(operator) => format!("({})", operator),
+            ObjectType::Partial(partial) => {
+                let mut arguments: Vec<String> = partial
+                    .arguments
+                    .iter()
+                    .map(|arg| arg.as_ref().map(|a| a.inspect()).unwrap_or_else(|| "_".to_string()))
+                    .collect();
+                arguments.extend(
+                    partial.named.iter().map(|(name, value)| format!("{}: {}", name, value.inspect())),
+                );
+                let function = match &partial.function {
+                    ObjectType::Function { name: Some(name), .. } | ObjectType::Builtin(name, _) => {
+                        name.clone()
+                    }
+                    ObjectType::Function { .. } => "<anonymous>".to_string(),
+                    other => other.inspect(),
+                };
+                format!("{}({})", function, arguments.join(", "))
+            }
+            ObjectType::Quote(expr) => format!("quote({})", expr),
+            ObjectType::Struct(struct_type) => format!("struct {}", struct_type.name),
+            ObjectType::Enum(enum_type) => format!("enum {}", enum_type.name),
+            ObjectType::Trait(trait_type) => format!("trait {}", trait_type.name),
+            ObjectType::Module(module) => format!("module {}", module.name),
+            ObjectType::Iterator(iterator) => iterator.name.clone(),
+            // a type implementing Show prints through its own `show` method
+            value if value.trait_method("Show", "show").is_some() => {
+                let show = value.trait_method("Show", "show").unwrap();
+                crate::evaluator::apply_function(show, vec![value.clone()]).inspect()
+            }
+            ObjectType::Variant(variant) => {
+                let name = if crate::builtins::is_prelude(&variant.enum_type) {
+                    variant.name().to_string()
+                } else {
+                    format!("{}.{}", variant.enum_type.name, variant.name())
+                };
+                if variant.values.is_empty() {
+                    name
+                } else {
+                    let values: Vec<String> = variant.values.iter().map(|v| v.inspect()).collect();
+                    format!("{}({})", name, values.join(", "))
+                }
+            }
+            ObjectType::Constructor(enum_type, index) if crate::builtins::is_prelude(enum_type) => {
+                enum_type.variants[*index].0.clone()
+            }
+            ObjectType::Constructor(enum_type, index) => {
+                format!("{}.{}", enum_type.name, enum_type.variants[*index].0)
+            }
+            ObjectType::Instance(instance) => {
+                let fields = instance.fields.borrow();
+                let fields: Vec<String> = instance
+                    .struct_type
+                    .fields
+                    .iter()
+                    .zip(fields.iter())
+                    .map(|(name, value)| format!("{}: {}", name, value.inspect()))
+                    .collect();
+                format!("{} {{ {} }}", instance.struct_type.name, fields.join(", "))
+            }
+            ObjectType::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let mut out = format!(
+                    "{}{}{}",
+                    start.map(|s| s.to_string()).unwrap_or_default(),
+                    if *inclusive { "..=" } else { ".." },
+                    end.map(|e| e.to_string()).unwrap_or_default()
+                );
+                if *step != 1 {
+                    out.push_str(&format!(" step {}", step));
+                }
+                out
+            }
+            ObjectType::Error(error) if error.suppressed.is_empty() => {
+                format!("{}: {}", error.kind, error.message)
+            }
+            ObjectType::Error(error) => {
+                let suppressed: Vec<String> = error.suppressed.iter().map(|e| e.inspect()).collect();
+                format!(
+                    "{}: {} (while cleaning up: {})",
+                    error.kind,
+                    error.message,
+                    suppressed.join("; ")
+                )
+            }
+            ObjectType::Return(value) => value.inspect(),
+            ObjectType::Throw(value) => format!("Uncaught {}", value.inspect()),
+            ObjectType::ShortCircuit => "Null".to_string(),
+            ObjectType::Break => "break".to_string(),
+            ObjectType::Continue => "continue".to_string(),
         }
     }
 
@@ -31,3 +235,342 @@ impl Object for ObjectType {
 
 }
 
+impl ObjectType {
+    pub fn error_value(kind: &str, message: impl Into<String>) -> ObjectType {
+        ObjectType::Error(Box::new(ErrorObject {
+            kind: kind.to_string(),
+            message: message.into(),
+            stack: vec![],
+            suppressed: vec![],
+        }))
+    }
+
+    // `ret`, `break`, `continue`, throws and stopped `?.` chains skip the rest of whatever is being evaluated
+    pub fn is_unwinding(&self) -> bool {
+        matches!(
+            self,
+            ObjectType::Return(_)
+                | ObjectType::Break
+                | ObjectType::Continue
+                | ObjectType::Throw(_)
+                | ObjectType::ShortCircuit
+        )
+    }
+
+    // a lazy sequence, `items` only runs as far as the values that are asked for
+    pub fn iterator(name: impl Into<String>, items: impl Iterator<Item = ObjectType> + 'static) -> ObjectType {
+        ObjectType::Iterator(Rc::new(LazyIterator {
+            name: name.into(),
+            items: RefCell::new(Box::new(items)),
+        }))
+    }
+
+    pub fn set(elements: BTreeSet<HashKey>) -> ObjectType {
+        ObjectType::Set(Rc::new(RefCell::new(elements)))
+    }
+
+    // the atom `:name`, made once and shared by every later use of the name
+    pub fn atom(name: &str) -> ObjectType {
+        thread_local! {
+            static ATOMS: RefCell<HashMap<String, Rc<str>>> = RefCell::new(HashMap::new());
+        }
+        ATOMS.with(|atoms| {
+            let mut atoms = atoms.borrow_mut();
+            let atom = atoms.entry(name.to_string()).or_insert_with(|| Rc::from(name));
+            ObjectType::Atom(atom.clone())
+        })
+    }
+
+    // a runtime error of the given kind, already on its way to the nearest `catch`
+    pub fn error(kind: &str, message: impl Into<String>) -> ObjectType {
+        ObjectType::Throw(Box::new(ObjectType::error_value(kind, message)))
+    }
+
+    // the method `name` of a struct instance or enum variant whose type implements `trait_name`
+    pub fn trait_method(&self, trait_name: &str, name: &str) -> Option<ObjectType> {
+        match self {
+            ObjectType::Instance(instance) if instance.struct_type.implements(trait_name) => {
+                instance.struct_type.method(name)
+            }
+            ObjectType::Variant(variant) if variant.enum_type.implements(trait_name) => {
+                variant.enum_type.method(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for ObjectType {
+    fn eq(&self, other: &Self) -> bool {
+        // a type implementing Eq decides for itself
+        if let Some(eq) = self.trait_method("Eq", "eq") {
+            let result = crate::evaluator::apply_function(eq, vec![self.clone(), other.clone()]);
+            return !matches!(result, ObjectType::Boolean(false) | ObjectType::Null);
+        }
+
+        match (self, other) {
+            (ObjectType::Number(l), ObjectType::Number(r)) => l == r,
+            (ObjectType::String(l), ObjectType::String(r)) => l == r,
+            (ObjectType::Boolean(l), ObjectType::Boolean(r)) => l == r,
+            (ObjectType::Null, ObjectType::Null) => true,
+            (ObjectType::Atom(l), ObjectType::Atom(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Array(l), ObjectType::Array(r)) => l == r,
+            (ObjectType::Tuple(l), ObjectType::Tuple(r)) => l == r,
+            (ObjectType::Map(l), ObjectType::Map(r)) => l == r,
+            (ObjectType::Set(l), ObjectType::Set(r)) => l == r,
+            (ObjectType::Struct(l), ObjectType::Struct(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Instance(l), ObjectType::Instance(r)) => {
+                Rc::ptr_eq(&l.struct_type, &r.struct_type) && l.fields == r.fields
+            }
+            (ObjectType::Enum(l), ObjectType::Enum(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Trait(l), ObjectType::Trait(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Module(l), ObjectType::Module(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Operator(l), ObjectType::Operator(r)) => l == r,
+            (ObjectType::Iterator(l), ObjectType::Iterator(r)) => Rc::ptr_eq(l, r),
+            (ObjectType::Quote(l), ObjectType::Quote(r)) => l == r,
+            (ObjectType::Variant(l), ObjectType::Variant(r)) => {
+                Rc::ptr_eq(&l.enum_type, &r.enum_type) && l.index == r.index && l.values == r.values
+            }
+            (ObjectType::Constructor(l, li), ObjectType::Constructor(r, ri)) => {
+                Rc::ptr_eq(l, r) && li == ri
+            }
+            (
+                ObjectType::Range {
+                    start: ls,
+                    end: le,
+                    step: lstep,
+                    inclusive: linc,
+                },
+                ObjectType::Range {
+                    start: rs,
+                    end: re,
+                    step: rstep,
+                    inclusive: rinc,
+                },
+            ) => ls == rs && le == re && lstep == rstep && linc == rinc,
+            (ObjectType::Error(l), ObjectType::Error(r)) => l.kind == r.kind && l.message == r.message,
+            (ObjectType::Return(l), ObjectType::Return(r)) => l == r,
+            // functions are never equal, not even to themselves
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Partial {
+    pub function: ObjectType,
+    // None marks a `_` still to be filled
+    pub arguments: Vec<Option<ObjectType>>,
+    pub named: Vec<(String, ObjectType)>,
+}
+
+#[derive(Debug)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub methods: RefCell<HashMap<String, ObjectType>>,
+    // names of the traits implemented with `impl Trait for Name`
+    pub traits: RefCell<Vec<String>>,
+    pub invariants: Vec<Expression>,
+}
+
+impl StructType {
+    pub fn method(&self, name: &str) -> Option<ObjectType> {
+        self.methods.borrow().get(name).cloned()
+    }
+
+    pub fn implements(&self, trait_name: &str) -> bool {
+        self.traits.borrow().iter().any(|t| t == trait_name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub struct_type: Rc<StructType>,
+    // in the order the struct declares them
+    pub fields: RefCell<Vec<ObjectType>>,
+}
+
+impl Instance {
+    pub fn get(&self, field: &str) -> Option<ObjectType> {
+        let index = self.struct_type.fields.iter().position(|f| f == field)?;
+        Some(self.fields.borrow()[index].clone())
+    }
+
+    // returns false when the struct has no such field
+    pub fn set(&self, field: &str, value: ObjectType) -> bool {
+        match self.struct_type.fields.iter().position(|f| f == field) {
+            Some(index) => {
+                self.fields.borrow_mut()[index] = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EnumType {
+    pub name: String,
+    // each variant's name and payload field names
+    pub variants: Vec<(String, Vec<String>)>,
+    pub methods: RefCell<HashMap<String, ObjectType>>,
+    pub traits: RefCell<Vec<String>>,
+}
+
+impl EnumType {
+    pub fn variant_index(&self, name: &str) -> Option<usize> {
+        self.variants.iter().position(|(variant, _)| variant == name)
+    }
+
+    pub fn method(&self, name: &str) -> Option<ObjectType> {
+        self.methods.borrow().get(name).cloned()
+    }
+
+    pub fn implements(&self, trait_name: &str) -> bool {
+        self.traits.borrow().iter().any(|t| t == trait_name)
+    }
+}
+
+// `trait Show { ... }`, `provided` holds the default methods as closures
+#[derive(Debug)]
+pub struct TraitType {
+    pub name: String,
+    pub required: Vec<String>,
+    pub provided: Vec<(String, ObjectType)>,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub enum_type: Rc<EnumType>,
+    pub index: usize,
+    pub values: Vec<ObjectType>,
+}
+
+impl Variant {
+    pub fn name(&self) -> &str {
+        &self.enum_type.variants[self.index].0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ErrorObject {
+    pub kind: String,
+    pub message: String,
+    // the calls the error unwound through, innermost first
+    pub stack: Vec<String>,
+    // errors thrown by deferred code while this one was unwinding
+    pub suppressed: Vec<ObjectType>,
+}
+
+#[derive(Debug)]
+pub struct Module {
+    // the path as written in the import, used in error messages
+    pub name: String,
+    pub exports: HashMap<String, ObjectType>,
+}
+
+impl Module {
+    pub fn get(&self, name: &str) -> Option<ObjectType> {
+        self.exports.get(name).cloned()
+    }
+}
+
+pub struct LazyIterator {
+    // how it prints, e.g. `generator count` or `iterator map`
+    pub name: String,
+    // a Throw item is an error raised while producing the next value
+    items: RefCell<Box<dyn Iterator<Item = ObjectType>>>,
+}
+
+impl LazyIterator {
+    // None once the sequence is exhausted
+    pub fn next(&self) -> Option<ObjectType> {
+        match self.items.try_borrow_mut() {
+            Ok(mut items) => items.next(),
+            // a generator body asking itself for its next value
+            Err(_) => Some(ObjectType::error(
+                "ValueError",
+                format!("{} is already running", self.name),
+            )),
+        }
+    }
+}
+
+// the items are produced on demand, so there is nothing to show
+impl fmt::Debug for LazyIterator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LazyIterator({})", self.name)
+    }
+}
+
+// the values that can be used as map keys, ordered so maps iterate deterministically
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HashKey {
+    Number(i64),
+    String(String),
+    Boolean(bool),
+    Atom(Rc<str>),
+    // a tuple whose elements are all keys themselves
+    Tuple(Vec<HashKey>),
+}
+
+impl HashKey {
+    pub fn from_object(value: &ObjectType) -> Option<HashKey> {
+        match value {
+            ObjectType::Number(int) => Some(HashKey::Number(*int)),
+            ObjectType::String(str) => Some(HashKey::String(str.clone())),
+            ObjectType::Boolean(bool) => Some(HashKey::Boolean(*bool)),
+            ObjectType::Atom(name) => Some(HashKey::Atom(name.clone())),
+            ObjectType::Tuple(elements) => {
+                elements.iter().map(HashKey::from_object).collect::<Option<_>>().map(HashKey::Tuple)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_object(&self) -> ObjectType {
+        match self {
+            HashKey::Number(int) => ObjectType::Number(*int),
+            HashKey::String(str) => ObjectType::String(str.clone()),
+            HashKey::Boolean(bool) => ObjectType::Boolean(*bool),
+            HashKey::Atom(name) => ObjectType::Atom(name.clone()),
+            HashKey::Tuple(elements) => ObjectType::Tuple(elements.iter().map(HashKey::to_object).collect()),
+        }
+    }
+}
+
+// lazily walks a range, an open end never stops
+pub fn range_iter(start: i64, end: Option<i64>, step: i64, inclusive: bool) -> impl Iterator<Item = i64> {
+    let mut next = Some(start);
+    std::iter::from_fn(move || {
+        let current = next?;
+        let in_bounds = match end {
+            None => true,
+            Some(end) if step > 0 => current < end || (inclusive && current == end),
+            Some(end) => current > end || (inclusive && current == end),
+        };
+        if !in_bounds {
+            return None;
+        }
+        next = current.checked_add(step);
+        Some(current)
+    })
+}
+
+// membership without walking the range
+pub fn range_contains(start: Option<i64>, end: Option<i64>, step: i64, inclusive: bool, value: i64) -> bool {
+    let before_end = match end {
+        None => true,
+        Some(end) if step > 0 => value < end || (inclusive && value == end),
+        Some(end) => value > end || (inclusive && value == end),
+    };
+    // without a start there is nothing for the step to count from, only the end bounds it
+    let start = match start {
+        Some(start) => start,
+        None => return before_end,
+    };
+    let after_start = if step > 0 { value >= start } else { value <= start };
+    // the distance can be wider than an i64 when the bounds are far apart
+    after_start && before_end && (value as i128 - start as i128) % step as i128 == 0
+}
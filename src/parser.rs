@@ -1,29 +1,140 @@
+use std::collections::HashMap;
+
 use crate::ast::{
-    Expression, ExpressionStatement, LetStatement, Program, ReturnStatement, Statement,
+    prelude_variant, AssertStatement, Clause, Contract, EnumStatement, EnumVariant, Expression, ExpressionStatement, ImplStatement, LetStatement,
+    ImportStatement, InfixStatement, Type, MacroStatement, MatchArm, Pattern, Program, ReturnStatement, Statement, StructStatement,
+    TraitStatement, TryExpression,
 };
 use crate::lexer::Lexer;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token::TokenType;
 
+// a parameter list and the name of its trailing `...rest`, if any
+type Parameters = (Vec<Pattern>, Option<String>);
+// the positional and the named arguments of a call
+type Arguments = (Vec<Expression>, Vec<(String, Expression)>);
+
+// how tightly an operator binds, `infix 60 <+>` declares one at the level of `+`
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub struct Precedence(u8);
+
+impl Precedence {
+    const LOWEST: Precedence = Precedence(0);
+    const ASSIGN: Precedence = Precedence(10); // =
+    const PIPE: Precedence = Precedence(20); // |>
+    const COALESCE: Precedence = Precedence(30); // ??
+    const EQUALS: Precedence = Precedence(40); // == or !=
+    const LESSGREATER: Precedence = Precedence(50); // > or < or >= or <= or in
+    const UNION: Precedence = Precedence(52); // |
+    const SYMMETRIC: Precedence = Precedence(53); // ^
+    const INTERSECT: Precedence = Precedence(54); // &
+    const RANGE: Precedence = Precedence(55); // .. or ..=
+    const SUM: Precedence = Precedence(60); // + or -
+    const PRODUCT: Precedence = Precedence(70); // * or / or %
+    const PREFIX: Precedence = Precedence(80); // -x or !x
+    const CALL: Precedence = Precedence(90); // f(x)
+    const INDEX: Precedence = Precedence(100); // arr[i] or p.x
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OperatorInfo {
+    precedence: Precedence,
+    // `a <^> b <^> c` groups as `a <^> (b <^> c)`
+    right: bool,
+}
+
+// the binary operators the parser knows, keyed by their symbol
+pub type Operators = HashMap<String, OperatorInfo>;
+
+// the operators every program starts with
+pub fn default_operators() -> Operators {
+    let left = |precedence| OperatorInfo {
+        precedence,
+        right: false,
+    };
+    HashMap::from([
+        ("??".to_string(), left(Precedence::COALESCE)),
+        ("==".to_string(), left(Precedence::EQUALS)),
+        ("!=".to_string(), left(Precedence::EQUALS)),
+        ("<".to_string(), left(Precedence::LESSGREATER)),
+        (">".to_string(), left(Precedence::LESSGREATER)),
+        ("<=".to_string(), left(Precedence::LESSGREATER)),
+        (">=".to_string(), left(Precedence::LESSGREATER)),
+        ("in".to_string(), left(Precedence::LESSGREATER)),
+        ("|".to_string(), left(Precedence::UNION)),
+        ("^".to_string(), left(Precedence::SYMMETRIC)),
+        ("&".to_string(), left(Precedence::INTERSECT)),
+        ("+".to_string(), left(Precedence::SUM)),
+        ("-".to_string(), left(Precedence::SUM)),
+        ("*".to_string(), left(Precedence::PRODUCT)),
+        ("/".to_string(), left(Precedence::PRODUCT)),
+        ("%".to_string(), left(Precedence::PRODUCT)),
+    ])
+}
+
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
     peek_token: Token,
+    current_span: Span,
+    peek_span: Span,
+    // set while parsing `if`, `for` and `match` heads, where `x {` opens the block
+    no_struct_literal: bool,
+    // whether the function body being parsed used `yield`, None outside of functions
+    yields: Option<bool>,
+    operators: Operators,
+    // set when a type argument list ended on the first '>' of a `>>` run
+    split_close: bool,
+    // set while parsing a lambda's parameters, where a '|' closes the list instead of being an operator
+    lambda_parameters: bool,
+    // the variants of each enum declared so far, to tell whether a match covers all of them
+    enums: HashMap<String, Vec<String>>,
+    // problems that don't stop the program from running, left for the caller to report
+    warnings: Vec<String>,
 }
 
 impl Parser {
-    pub fn new(mut lexer: Lexer) -> Self {
+    pub fn new(lexer: Lexer) -> Self {
+        Parser::with_operators(lexer, default_operators())
+    }
+
+    // a parser that also knows the operators declared by earlier input, like the REPL's previous lines
+    pub fn with_operators(mut lexer: Lexer, operators: Operators) -> Self {
         let current_token = lexer.next_token();
+        let current_span = lexer.span;
         let peek_token = lexer.next_token();
-        return Self {
+        let peek_span = lexer.span;
+        Self {
             lexer,
             current_token,
             peek_token,
-        };
+            current_span,
+            peek_span,
+            no_struct_literal: false,
+            yields: None,
+            operators,
+            split_close: false,
+            lambda_parameters: false,
+            enums: HashMap::from([
+                ("Result".to_string(), vec!["Ok".to_string(), "Err".to_string()]),
+                ("Option".to_string(), vec!["Some".to_string(), "None".to_string()]),
+            ]),
+            warnings: vec![],
+        }
+    }
+
+    pub fn operators(&self) -> &Operators {
+        &self.operators
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
     }
 
     fn next_token(&mut self) {
         self.current_token = std::mem::replace(&mut self.peek_token, self.lexer.next_token());
+        self.current_span = std::mem::replace(&mut self.peek_span, self.lexer.span);
     }
 
     pub fn parse_program(&mut self) -> Result<Program, String> {
@@ -37,13 +148,11 @@ impl Parser {
             }
 
             match self.parse_statement() {
-                Ok(stmt) => statements.push(stmt),
-                Err(err) => {
-                    eprintln!("Error parsing statement: {}", err);
-                    while self.current_token.t != TokenType::EOF {
-                        self.next_token(); // Skip to end
-                    }
+                Ok(stmt) => {
+                    statements.push(stmt);
+                    self.next_token();
                 }
+                Err(err) => return Err(err),
             }
         }
 
@@ -54,9 +163,31 @@ impl Parser {
         let stmnt = match self.current_token.t {
             TokenType::LET => self.parse_let_statement(),
             TokenType::RETURN => self.parse_return_statement(),
+            TokenType::STRUCT => self.parse_struct_statement(),
+            TokenType::ENUM => self.parse_enum_statement(),
+            TokenType::TRAIT => self.parse_trait_statement(),
+            TokenType::IMPL => self.parse_impl_statement(),
+            TokenType::PUB => self.parse_pub_statement(),
+            TokenType::IMPORT | TokenType::FROM => self.parse_import_statement(),
+            TokenType::MACRO => self.parse_macro_statement(),
+            TokenType::INFIX => self.parse_infix_statement(),
+            TokenType::DEFER => {
+                self.next_token(); // Skip the "defer" token
+                Ok(Statement::Defer(self.parse_expression(Precedence::LOWEST)?))
+            }
+            TokenType::ASSERT => self.parse_assert_statement(),
+            TokenType::BREAK => Ok(Statement::Break),
+            TokenType::CONTINUE => Ok(Statement::Continue),
+            // a named function is a declaration, so a following `[` or `(` starts a new statement
+            TokenType::FUNCTION if self.peek_token.t == TokenType::IDENT => {
+                let expr = self.parse_function_expression()?;
+                Ok(Statement::Expression(ExpressionStatement {
+                    expression: expr,
+                }))
+            }
             _ => {
                 // Treat expressions as valid statements
-                let expr = self.parse_expression()?;
+                let expr = self.parse_expression(Precedence::LOWEST)?;
                 Ok(Statement::Expression(ExpressionStatement {
                     expression: expr,
                 }))
@@ -70,20 +201,39 @@ impl Parser {
         return stmnt;
     }
 
+    // Parses `{ ... }` starting at the '{' and leaves the parser on the '}'
+    fn parse_block_statement(&mut self) -> Result<Vec<Statement>, String> {
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '{'
+
+        let mut body = vec![];
+
+        while self.current_token.t != TokenType::RBRACE {
+            if self.current_token.t == TokenType::EOF {
+                return Err("Expected '}', found end of input".to_string());
+            }
+            // Skip semicolons that appear between statements
+            if self.current_token.t == TokenType::SEMICOLON {
+                self.next_token();
+                continue;
+            }
+            let stmnt = self.parse_statement()?;
+            body.push(stmnt);
+            self.next_token();
+        }
+
+        Ok(body)
+    }
+
     fn parse_let_statement(&mut self) -> Result<Statement, String> {
-        // Move to the identifier
+        let span = self.current_span;
+        // Move to the identifier or destructuring pattern
         self.next_token();
 
-        // Expect identifier
-        let identifier = match &self.current_token.t {
-            TokenType::IDENT => self.current_token.literal.clone(),
-            _ => {
-                return Err(format!(
-                    "Expected identifier, found {:?}",
-                    self.current_token
-                ));
-            }
-        };
+        let pattern = self.parse_single_pattern()?;
+        let pattern = self.parse_typed_pattern(pattern)?;
 
         // Expect '='
         self.next_token();
@@ -95,412 +245,2525 @@ impl Parser {
         self.next_token();
 
         // Parse the expression on the right-hand side of the assignment
-        let value = self.parse_expression()?;
+        let value = self.parse_expression(Precedence::LOWEST)?;
 
         // Construct the let statement
-        Ok(Statement::Let(LetStatement {
-            name: identifier,
+        Ok(Statement::Let(Box::new(LetStatement {
+            pattern,
             value,
-        }))
+            span,
+        })))
     }
 
-    fn parse_function_expression(&mut self) -> Result<Expression, String> {
-        // skip the function token
+    fn parse_struct_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "struct" token
+
+        if self.current_token.t != TokenType::IDENT {
+            return Err(format!(
+                "Expected struct name, found {:?}",
+                self.current_token
+            ));
+        }
+        let name = self.current_token.literal.clone();
+
         self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '{'
 
-        let name = match &self.current_token.t {
-            TokenType::IDENT => self.current_token.literal.clone(),
-            _ => {
+        let mut fields = vec![];
+
+        while self.current_token.t != TokenType::RBRACE {
+            if self.current_token.t != TokenType::IDENT {
                 return Err(format!(
-                    "Expected identifier, found {:?}",
+                    "Expected field name, found {:?}",
                     self.current_token
                 ));
             }
-        };
+            if fields.contains(&self.current_token.literal) {
+                return Err(format!(
+                    "Duplicate field '{}' in struct {}",
+                    self.current_token.literal, name
+                ));
+            }
+            fields.push(self.current_token.literal.clone());
+            self.next_token();
 
-        self.next_token();
+            if self.current_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.current_token.t != TokenType::RBRACE {
+                return Err(format!(
+                    "Expected ',' or '}}', found {:?}",
+                    self.current_token
+                ));
+            }
+        }
 
-        if self.current_token.t != TokenType::LPAREN {
-            return Err(format!("Expected '(', found {:?}", self.current_token));
+        // `invariant cond` clauses follow the closing '}'
+        let mut invariants = vec![];
+        while self.peek_token.t == TokenType::INVARIANT {
+            self.next_token(); // Move to the 'invariant'
+            self.next_token(); // Skip the 'invariant'
+            invariants.push(self.parse_expression(Precedence::LOWEST)?);
         }
 
-        self.next_token(); // Skip the '('
+        Ok(Statement::Struct(StructStatement {
+            name,
+            fields,
+            invariants,
+        }))
+    }
 
-        let mut parameters = vec![];
+    fn parse_enum_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "enum" token
+
+        if self.current_token.t != TokenType::IDENT {
+            return Err(format!(
+                "Expected enum name, found {:?}",
+                self.current_token
+            ));
+        }
+        let name = self.current_token.literal.clone();
+
+        self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '{'
+
+        let mut variants: Vec<EnumVariant> = vec![];
 
-        // parse parameters
-        while self.current_token.t != TokenType::RPAREN {
+        while self.current_token.t != TokenType::RBRACE {
             if self.current_token.t != TokenType::IDENT {
                 return Err(format!(
-                    "Expected identifier, found {:?}",
+                    "Expected variant name, found {:?}",
                     self.current_token
                 ));
             }
-            parameters.push(self.current_token.literal.clone());
+            let variant = self.current_token.literal.clone();
+            if variants.iter().any(|v| v.name == variant) {
+                return Err(format!("Duplicate variant '{}' in enum {}", variant, name));
+            }
             self.next_token();
 
+            let mut fields = vec![];
+            if self.current_token.t == TokenType::LPAREN {
+                self.next_token(); // Skip the '('
+                while self.current_token.t != TokenType::RPAREN {
+                    if self.current_token.t != TokenType::IDENT {
+                        return Err(format!(
+                            "Expected field name, found {:?}",
+                            self.current_token
+                        ));
+                    }
+                    fields.push(self.current_token.literal.clone());
+                    self.next_token();
+
+                    if self.current_token.t == TokenType::COMMA {
+                        self.next_token();
+                    } else if self.current_token.t != TokenType::RPAREN {
+                        return Err(format!(
+                            "Expected ',' or ')', found {:?}",
+                            self.current_token
+                        ));
+                    }
+                }
+                self.next_token(); // Skip the ')'
+            }
+            variants.push(EnumVariant {
+                name: variant,
+                fields,
+            });
+
             if self.current_token.t == TokenType::COMMA {
                 self.next_token();
-            } else if self.current_token.t != TokenType::RPAREN {
+            } else if self.current_token.t != TokenType::RBRACE {
                 return Err(format!(
-                    "Expected ',' or ')', found {:?}",
+                    "Expected ',' or '}}', found {:?}",
                     self.current_token
                 ));
             }
         }
 
-        if self.current_token.t != TokenType::RPAREN {
-            return Err(format!("Expected ')', found {:?}", self.current_token));
-        }
+        self.enums.insert(name.clone(), variants.iter().map(|v| v.name.clone()).collect());
+        Ok(Statement::Enum(EnumStatement { name, variants }))
+    }
 
-        self.next_token(); // Skip the ')'
+    fn parse_pub_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "pub" token
 
-        if self.current_token.t != TokenType::LBRACE {
-            return Err(format!("Expected '{{', found {:?}", self.current_token));
+        let exportable = match self.current_token.t {
+            TokenType::LET | TokenType::STRUCT | TokenType::ENUM | TokenType::TRAIT => true,
+            TokenType::FUNCTION => self.peek_token.t == TokenType::IDENT,
+            _ => false,
+        };
+        if !exportable {
+            return Err(format!(
+                "Expected a declaration after 'pub', found {:?}",
+                self.current_token
+            ));
         }
 
-        self.next_token(); // Skip the '{'
+        let inner = self.parse_statement()?;
+        Ok(Statement::Pub(Box::new(inner)))
+    }
 
-        let mut body = vec![];
+    fn parse_import_statement(&mut self) -> Result<Statement, String> {
+        let from = self.current_token.t == TokenType::FROM;
+        self.next_token(); // Skip the "import" or "from" token
 
-        while self.current_token.t != TokenType::RBRACE {
-            // Skip semicolons that appear between statements
-            if self.current_token.t == TokenType::SEMICOLON {
+        if self.current_token.t != TokenType::STRING {
+            return Err(format!("Expected module path, found {:?}", self.current_token));
+        }
+        let path = self.current_token.literal.clone();
+
+        let mut alias = None;
+        let mut names = vec![];
+
+        if from {
+            self.next_token();
+            if self.current_token.t != TokenType::IMPORT {
+                return Err(format!("Expected 'import', found {:?}", self.current_token));
+            }
+            loop {
                 self.next_token();
-                continue;
+                if self.current_token.t != TokenType::IDENT {
+                    return Err(format!("Expected name to import, found {:?}", self.current_token));
+                }
+                names.push(self.current_token.literal.clone());
+                if self.peek_token.t != TokenType::COMMA {
+                    break;
+                }
+                self.next_token(); // Move to the ','
             }
-            let stmnt = self.parse_statement()?;
-            body.push(stmnt);
+        } else if self.peek_token.t == TokenType::AS {
+            self.next_token(); // Move to the "as" token
+            self.next_token(); // Skip the "as" token
+            if self.current_token.t != TokenType::IDENT {
+                return Err(format!("Expected module alias, found {:?}", self.current_token));
+            }
+            alias = Some(self.current_token.literal.clone());
         }
 
-        // Check for the closing brace
-        if self.current_token.t != TokenType::RBRACE {
-            return Err(format!("Expected '}}', found {:?}", self.current_token));
+        Ok(Statement::Import(ImportStatement { path, alias, names }))
+    }
+
+    fn parse_macro_statement(&mut self) -> Result<Statement, String> {
+        let (name, (parameters, rest)) = self.parse_function_signature()?;
+
+        let name = match name {
+            Some(name) => name,
+            None => return Err("Expected macro name after 'macro'".to_string()),
+        };
+        if rest.is_some() {
+            return Err(format!("Macro '{}' cannot take a rest parameter", name));
         }
+        // the arguments are bound unevaluated, so there is nothing to destructure
+        let parameters = parameters
+            .into_iter()
+            .map(|parameter| match parameter {
+                Pattern::BINDING(parameter) => Ok(parameter),
+                parameter => Err(format!(
+                    "Macro parameters must be plain names, found `{}`",
+                    parameter
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        self.next_token(); // Skip the '}'
+        self.next_token(); // Skip the ')'
 
-        // Construct the function statement
+        let body = self.parse_block_statement()?;
 
-        let ex = Expression::FUNCTION {
+        Ok(Statement::Macro(MacroStatement {
             name,
             parameters,
-            body: body,
-        };
-
-        return Ok(ex);
+            body,
+        }))
     }
 
-    fn parse_return_statement(&mut self) -> Result<Statement, String> {
-        self.next_token(); // Skip the "return" token
+    // Parses `infix 60 <+> (a, b) { ... }` or `infix right 80 <^> ...` and registers the operator
+    fn parse_infix_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span;
+        self.next_token(); // Skip the "infix" token
 
-        let value = self.parse_expression()?;
+        // `left` and `right` are only keywords right after `infix`
+        let right = self.current_token.t == TokenType::IDENT && self.current_token.literal == "right";
+        if self.current_token.t == TokenType::IDENT && matches!(self.current_token.literal.as_str(), "left" | "right") {
+            self.next_token();
+        }
 
-        Ok(Statement::Return(ReturnStatement {
-            return_value: value,
+        if self.current_token.t != TokenType::INT {
+            return Err(format!("Expected operator precedence, found {:?}", self.current_token));
+        }
+        // a declared operator binds looser than prefix operators and calls
+        let precedence = self
+            .current_token
+            .literal
+            .parse::<u8>()
+            .ok()
+            .filter(|precedence| (1..Precedence::PREFIX.0).contains(precedence))
+            .ok_or_else(|| {
+                format!(
+                    "Operator precedence must be between 1 and {}, found {}",
+                    Precedence::PREFIX.0 - 1,
+                    self.current_token.literal
+                )
+            })?;
+        self.next_token();
+
+        if self.is_operator(&self.current_token) && default_operators().contains_key(&self.current_token.literal) {
+            return Err(format!(
+                "Cannot redefine built-in operator '{}'",
+                self.current_token.literal
+            ));
+        }
+        if self.current_token.t != TokenType::OPERATOR {
+            return Err(format!("Expected operator symbol, found {:?}", self.current_token));
+        }
+        let operator = self.current_token.literal.clone();
+        // registered before the body so the operator can be used recursively
+        self.operators.insert(
+            operator.clone(),
+            OperatorInfo {
+                precedence: Precedence(precedence),
+                right,
+            },
+        );
+
+        // the signature starts right after the symbol, like an anonymous function's
+        let (_, (parameters, rest)) = self.parse_function_signature()?;
+        if parameters.len() != 2 || rest.is_some() {
+            return Err(format!("Operator '{}' must take exactly two parameters", operator));
+        }
+        let returns = self.parse_return_annotation()?;
+
+        self.next_token(); // Skip the ')' or the return type
+
+        let (body, generator) = self.parse_function_body(Self::parse_block_statement)?;
+
+        Ok(Statement::Infix(InfixStatement {
+            operator: operator.clone(),
+            precedence,
+            right,
+            function: Box::new(Expression::FUNCTION {
+                name: Some(operator),
+                parameters,
+                rest,
+                body,
+                generator,
+                returns,
+                contract: Contract::default(),
+                span,
+            }),
         }))
     }
 
-    fn parse_condition(&mut self) -> Result<Expression, String> {
-        let condition = self.parse_expression()?;
-
-        let ret: Result<Expression, String> = match condition {
-            Expression::BOOLEAN(_) => Ok(condition),
-            Expression::PREFIX {
-                operator: _,
-                right: _,
-            } => Ok(condition),
-            Expression::INFEX { ref operator, .. }
-                if operator == "==" || operator == "!=" || operator == ">" || operator == "<" =>
-            {
-                Ok(condition)
-            }
-            _ => Err(format!(
-                "Invalid condition: {:?}. Expected a boolean expression or comparison.",
-                condition
-            )),
+    // `assert condition` with an optional `, message`
+    fn parse_assert_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "assert" token
+        let condition = self.parse_expression(Precedence::LOWEST)?;
+
+        let message = if self.peek_token.t == TokenType::COMMA {
+            self.next_token(); // Move to the ','
+            self.next_token(); // Skip the ','
+            Some(self.parse_expression(Precedence::LOWEST)?)
+        } else {
+            None
         };
-        self.next_token();
-        return ret;
+
+        Ok(Statement::Assert(AssertStatement { condition, message }))
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expression, String> {
-        self.next_token(); // Skip the "if" token
+    fn parse_trait_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "trait" token
 
-        // Parse the condition
-        let cond = self.parse_condition()?;
+        if self.current_token.t != TokenType::IDENT {
+            return Err(format!(
+                "Expected trait name, found {:?}",
+                self.current_token
+            ));
+        }
+        let name = self.current_token.literal.clone();
 
+        self.next_token();
         if self.current_token.t != TokenType::LBRACE {
-            return Err("Expected '{' after an IF condition".to_string());
+            return Err(format!("Expected '{{', found {:?}", self.current_token));
         }
-        self.next_token(); // skip the '{'
+        self.next_token(); // Skip the '{'
 
-        let mut code = vec![];
+        let mut required = vec![];
+        let mut provided = vec![];
 
         while self.current_token.t != TokenType::RBRACE {
-            // Skip semicolons that appear between statements
+            if self.current_token.t != TokenType::FUNCTION || self.peek_token.t != TokenType::IDENT {
+                return Err(format!(
+                    "Expected a named method in trait {}, found {:?}",
+                    name, self.current_token
+                ));
+            }
+
+            let span = self.current_span;
+            let (method, (parameters, rest)) = self.parse_function_signature()?;
+            let returns = self.parse_return_annotation()?;
+            self.next_token(); // Skip the ')' or the return type
+
+            // `fun show(self);` has to be implemented, a method with a body is a default
             if self.current_token.t == TokenType::SEMICOLON {
-                self.next_token();
-                continue;
+                required.push(method.unwrap_or_default());
+            } else {
+                let contract = self.parse_contract()?;
+                let (body, generator) = self.parse_function_body(Self::parse_block_statement)?;
+                provided.push(Expression::FUNCTION {
+                    name: method,
+                    parameters,
+                    rest,
+                    body,
+                    generator,
+                    returns,
+                    contract,
+                    span,
+                });
             }
-            let stmnt = self.parse_statement()?;
-            code.push(stmnt);
+            self.next_token();
         }
 
-        // Check for the closing brace
-        if self.current_token.t != TokenType::RBRACE {
-            return Err("Expected '}' after an IF condition".to_string());
+        Ok(Statement::Trait(TraitStatement {
+            name,
+            required,
+            provided,
+        }))
+    }
+
+    fn parse_impl_statement(&mut self) -> Result<Statement, String> {
+        self.next_token(); // Skip the "impl" token
+
+        if self.current_token.t != TokenType::IDENT {
+            return Err(format!(
+                "Expected type name, found {:?}",
+                self.current_token
+            ));
         }
-        self.next_token(); // Skip the '}'
+        let mut name = self.current_token.literal.clone();
 
-        // Check for optional else block
-        if self.current_token.t == TokenType::ELSE {
-            self.next_token(); // Skip the "else" token
-            if self.current_token.t != TokenType::LBRACE {
-                return Err("Expected '{' after 'else'".to_string());
+        // `impl Show for Point` names the trait first
+        let mut trait_name = None;
+        if self.peek_token.t == TokenType::FOR {
+            self.next_token(); // Move to the "for" token
+            self.next_token(); // Skip the "for" token
+            if self.current_token.t != TokenType::IDENT {
+                return Err(format!(
+                    "Expected type name, found {:?}",
+                    self.current_token
+                ));
             }
-            self.next_token(); // Skip the '{'
-            let mut else_code = vec![];
+            trait_name = Some(name);
+            name = self.current_token.literal.clone();
+        }
 
-            while self.current_token.t != TokenType::RBRACE {
-                let stmnt = self.parse_statement()?;
-                else_code.push(stmnt);
-                self.next_token(); // Skip the ';'
-            }
+        self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '{'
 
-            if self.current_token.t != TokenType::RBRACE {
-                return Err("Expected '}' after 'else' block".to_string());
-            }
-            self.next_token(); // Skip the '}'
+        let mut methods = vec![];
 
-            return Ok(Expression::IF {
-                condition: Box::new(cond),
-                consequence: code,
-                alternative: Some(else_code),
-            });
+        while self.current_token.t != TokenType::RBRACE {
+            // Skip semicolons that appear between methods
+            if self.current_token.t == TokenType::SEMICOLON {
+                self.next_token();
+                continue;
+            }
+            if self.current_token.t != TokenType::FUNCTION || self.peek_token.t != TokenType::IDENT {
+                return Err(format!(
+                    "Expected a named method in impl {}, found {:?}",
+                    name, self.current_token
+                ));
+            }
+            methods.push(self.parse_function_expression()?);
+            self.next_token(); // Skip the method's '}'
         }
 
-        return Ok(Expression::IF {
-            condition: Box::new(cond),
-            consequence: code,
-            alternative: None,
-        });
+        Ok(Statement::Impl(ImplStatement {
+            trait_name,
+            name,
+            methods,
+        }))
     }
 
-    fn is_operator(tok: &Token) -> bool {
-        return tok.t == TokenType::PLUS
-            || tok.t == TokenType::STAR
-            || tok.t == TokenType::MINUS
-            || tok.t == TokenType::SLASH
-            || tok.t == TokenType::GT
-            || tok.t == TokenType::LT
-            || tok.t == TokenType::EQ
-            || tok.t == TokenType::NEQ;
-    }
+    fn parse_function_expression(&mut self) -> Result<Expression, String> {
+        let span = self.current_span;
+        let (name, (parameters, rest)) = self.parse_function_signature()?;
+        let returns = self.parse_return_annotation()?;
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_primary_expression()?;
+        self.next_token(); // Skip the ')' or the return type
+        let contract = self.parse_contract()?;
 
-        while Parser::is_operator(&self.peek_token) {
-            left = self.parse_infix_expression(left)?;
-        }
+        let (body, generator) = self.parse_function_body(Self::parse_block_statement)?;
 
-        if self.peek_token.t == TokenType::RPAREN {
-            self.next_token();
-        }
+        // Construct the function statement
 
-        Ok(left)
-    }
+        let ex = Expression::FUNCTION {
+            name,
+            parameters,
+            rest,
+            body,
+            generator,
+            returns,
+            contract,
+            span,
+        };
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression, String> {
-        self.next_token();
-        // Check if the next is a )
+        return Ok(ex);
+    }
 
-        let operator = self.current_token.literal.clone();
+    // Runs `parse` over a function body and reports whether it used `yield`,
+    // a nested function's `yield` makes only that function a generator
+    fn parse_function_body(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Vec<Statement>, String>,
+    ) -> Result<(Vec<Statement>, bool), String> {
+        let outer = self.yields.replace(false);
+        let body = parse(self);
+        let generator = std::mem::replace(&mut self.yields, outer);
+        Ok((body?, generator == Some(true)))
+    }
+
+    // Parses `fun name(params)` and leaves the parser on the ')'
+    fn parse_function_signature(&mut self) -> Result<(Option<String>, Parameters), String> {
+        // skip the function token
+        self.next_token();
+
+        // the name is optional, `fun(x) { ... }` is an anonymous function
+        let name = match &self.current_token.t {
+            TokenType::IDENT => {
+                let name = self.current_token.literal.clone();
+                self.next_token();
+                Some(name)
+            }
+            _ => None,
+        };
+
+        if self.current_token.t != TokenType::LPAREN {
+            return Err(format!("Expected '(', found {:?}", self.current_token));
+        }
+
+        self.next_token(); // Skip the '('
+
+        let parameters = self.parse_function_parameters(TokenType::RPAREN)?;
+
+        Ok((name, parameters))
+    }
+
+    // Parses `|x, y| x + y` or `|x| { ... }` into an anonymous function
+    fn parse_lambda_expression(&mut self) -> Result<Expression, String> {
+        let span = self.current_span;
+        self.next_token(); // Skip the opening '|'
+
+        let saved = std::mem::replace(&mut self.lambda_parameters, true);
+        let parameters = self.parse_function_parameters(TokenType::PIPE);
+        self.lambda_parameters = saved;
+        let (parameters, rest) = parameters?;
+
+        self.next_token(); // Skip the closing '|'
+
+        let (body, generator) = self.parse_function_body(|parser| {
+            if parser.current_token.t == TokenType::LBRACE {
+                parser.parse_block_statement()
+            } else {
+                let expression = parser.parse_expression(Precedence::LOWEST)?;
+                Ok(vec![Statement::Expression(ExpressionStatement { expression })])
+            }
+        })?;
+
+        Ok(Expression::FUNCTION {
+            name: None,
+            parameters,
+            rest,
+            body,
+            generator,
+            returns: None,
+            contract: Contract::default(),
+            span,
+        })
+    }
+
+    // Parses parameter patterns separated by commas up to the `end` token and leaves the parser on it,
+    // a trailing `...name` is returned separately as the rest parameter
+    fn parse_function_parameters(
+        &mut self,
+        end: TokenType,
+    ) -> Result<Parameters, String> {
+        let mut parameters = vec![];
+        let mut rest = None;
+
+        while self.current_token.t != end {
+            if self.current_token.t == TokenType::ELLIPSIS {
+                self.next_token(); // Skip the '...'
+                if self.current_token.t != TokenType::IDENT {
+                    return Err(format!(
+                        "Expected identifier after '...', found {:?}",
+                        self.current_token
+                    ));
+                }
+                rest = Some(self.current_token.literal.clone());
+                self.next_token();
+
+                if self.current_token.t != end {
+                    return Err(format!(
+                        "The rest parameter must be last, found {:?}",
+                        self.current_token
+                    ));
+                }
+                break;
+            }
+
+            if !matches!(
+                self.current_token.t,
+                TokenType::IDENT | TokenType::LBRACKET | TokenType::LBRACE
+            ) {
+                return Err(format!(
+                    "Expected identifier or pattern, found {:?}",
+                    self.current_token
+                ));
+            }
+            let pattern = self.parse_single_pattern()?;
+            let pattern = self.parse_typed_pattern(pattern)?;
+            parameters.push(self.parse_default_pattern(pattern)?);
+            self.next_token();
+
+            if self.current_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.current_token.t != end {
+                return Err(format!(
+                    "Expected ',' or {:?}, found {:?}",
+                    end, self.current_token
+                ));
+            }
+        }
+
+        Ok((parameters, rest))
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span;
+        self.next_token(); // Skip the "return" token
+
+        let mut value = self.parse_expression(Precedence::LOWEST)?;
+
+        // `ret q, r` returns the tuple `(q, r)`
+        if self.peek_token.t == TokenType::COMMA {
+            let mut elements = vec![value];
+            while self.peek_token.t == TokenType::COMMA {
+                self.next_token(); // Move to the ','
+                self.next_token(); // Skip the ','
+                elements.push(self.parse_expression(Precedence::LOWEST)?);
+            }
+            value = Expression::TUPLE(elements);
+        }
+
+        Ok(Statement::Return(ReturnStatement {
+            return_value: value,
+            span,
+        }))
+    }
+
+    // `-> type` after a parameter list, the parser is left on the ')' or the type's last token
+    fn parse_return_annotation(&mut self) -> Result<Option<Type>, String> {
+        if self.peek_token.t != TokenType::OPERATOR || self.peek_token.literal != "->" {
+            return Ok(None);
+        }
+        self.next_token(); // Move to the '->'
+        self.next_token(); // Skip the '->'
+        Ok(Some(self.parse_type()?))
+    }
+
+    // `requires cond` and `ensures cond` clauses between a signature and its body,
+    // leaves the parser on the body's '{'
+    fn parse_contract(&mut self) -> Result<Contract, String> {
+        let mut contract = Contract::default();
+        loop {
+            let clauses = match self.current_token.t {
+                TokenType::REQUIRES => &mut contract.requires,
+                TokenType::ENSURES => &mut contract.ensures,
+                _ => return Ok(contract),
+            };
+            self.next_token(); // Skip the keyword
+            clauses.push(self.parse_head_expression()?);
+            self.next_token();
+        }
+    }
+
+    // `pattern: type` in a `let` or parameter list
+    fn parse_typed_pattern(&mut self, pattern: Pattern) -> Result<Pattern, String> {
+        if self.peek_token.t != TokenType::COLON {
+            return Ok(pattern);
+        }
+        self.next_token(); // Move to the ':'
+        self.next_token(); // Skip the ':'
+        Ok(Pattern::TYPED {
+            pattern: Box::new(pattern),
+            annotation: self.parse_type()?,
+        })
+    }
+
+    // Parses `int`, `array<string>`, `map<string, int>`, `Point` or any of them followed
+    // by `?`, leaving the parser on the type's last token
+    fn parse_type(&mut self) -> Result<Type, String> {
+        let annotation = match (&self.current_token.t, self.current_token.literal.as_str()) {
+            (TokenType::NULL, _) => Type::Null,
+            (TokenType::FUNCTION, _) => Type::Function,
+            (TokenType::IDENT, "int") => Type::Int,
+            (TokenType::IDENT, "string") => Type::String,
+            (TokenType::IDENT, "bool") => Type::Bool,
+            (TokenType::IDENT, "atom") => Type::Atom,
+            (TokenType::IDENT, "any") => Type::Any,
+            (TokenType::IDENT, "array") => {
+                let [element] = self.parse_type_arguments("array")?;
+                Type::Array(Box::new(element))
+            }
+            (TokenType::IDENT, "map") => {
+                let [key, value] = self.parse_type_arguments("map")?;
+                Type::Map(Box::new(key), Box::new(value))
+            }
+            (TokenType::IDENT, name) => Type::Named(name.to_string()),
+            _ => return Err(format!("Expected a type, found {:?}", self.current_token)),
+        };
+
+        // after a split `>>` the '?' would belong to the enclosing type
+        if self.peek_token.t == TokenType::QUESTION && !self.split_close {
+            self.next_token();
+            return Ok(Type::Optional(Box::new(annotation)));
+        }
+        Ok(annotation)
+    }
+
+    // the `<...>` after `array` or `map`, with the parser on the type's name
+    fn parse_type_arguments<const N: usize>(&mut self, name: &str) -> Result<[Type; N], String> {
+        self.next_token();
+        if self.current_token.t != TokenType::LT {
+            return Err(format!("Expected '<' after '{}', found {:?}", name, self.current_token));
+        }
+
+        let mut arguments = vec![];
+        loop {
+            self.next_token();
+            arguments.push(self.parse_type()?);
+            // a nested list that ended in `>>` left the parser on this list's '>'
+            if !std::mem::take(&mut self.split_close) {
+                self.next_token();
+            }
+            if self.current_token.t != TokenType::COMMA {
+                break;
+            }
+        }
+
+        // `array<array<int>>` ends in a run of '>' that lexes as one operator,
+        // the rest of the run closes the enclosing lists
+        match self.current_token.literal.strip_prefix('>') {
+            Some("") if self.current_token.t == TokenType::GT => {}
+            Some(rest) if self.current_token.t == TokenType::OPERATOR && rest.chars().all(|ch| ch == '>') => {
+                let t = if rest == ">" { TokenType::GT } else { TokenType::OPERATOR };
+                self.current_token = Token {
+                    t,
+                    literal: rest.to_string(),
+                };
+                self.current_span.column += 1;
+                self.split_close = true;
+            }
+            _ => {
+                return Err(format!(
+                    "Expected '>' after the type arguments of '{}', found {:?}",
+                    name, self.current_token
+                ));
+            }
+        }
+
+        let count = arguments.len();
+        arguments
+            .try_into()
+            .map_err(|_| format!("'{}' takes {} type arguments, found {}", name, N, count))
+    }
+
+    // Parses an `if`, `for` or `match` head, where `x {` starts the block rather than a struct literal
+    fn parse_head_expression(&mut self) -> Result<Expression, String> {
+        let saved = std::mem::replace(&mut self.no_struct_literal, true);
+        let expr = self.parse_expression(Precedence::LOWEST);
+        self.no_struct_literal = saved;
+        expr
+    }
+
+    // runs `parse` with struct literals allowed again, inside brackets a `{` can't open the head's block
+    fn bracketed<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let saved = std::mem::replace(&mut self.no_struct_literal, false);
+        let result = parse(self);
+        self.no_struct_literal = saved;
+        result
+    }
+
+    // any expression will do, the evaluator decides whether its value is truthy
+    fn parse_condition(&mut self) -> Result<Expression, String> {
+        let condition = self.parse_head_expression()?;
+        self.next_token();
+        return Ok(condition);
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip the "if" token
+
+        if self.current_token.t == TokenType::LET {
+            return self.parse_if_let_expression();
+        }
+
+        // Parse the condition
+        let cond = self.parse_condition()?;
+
+        if self.current_token.t != TokenType::LBRACE {
+            return Err("Expected '{' after an IF condition".to_string());
+        }
+
+        let code = self.parse_block_statement()?;
+        let else_code = self.parse_else_block()?;
+
+        return Ok(Expression::IF {
+            condition: Box::new(cond),
+            consequence: code,
+            alternative: else_code,
+        });
+    }
+
+    // `if let pattern = value { ... }` runs the block only when the pattern matches
+    fn parse_if_let_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip the "let" token
+
+        let pattern = self.parse_pattern()?;
+
+        self.next_token();
+        if self.current_token.t != TokenType::ASSIGN {
+            return Err(format!("Expected '=', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '='
+
+        let value = self.parse_head_expression()?;
+
+        self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err("Expected '{' after an IF LET value".to_string());
+        }
+
+        let code = self.parse_block_statement()?;
+        let else_code = self.parse_else_block()?;
+
+        Ok(Expression::IFLET {
+            pattern: Box::new(pattern),
+            value: Box::new(value),
+            consequence: code,
+            alternative: else_code,
+        })
+    }
+
+    // Check for optional else block
+    fn parse_else_block(&mut self) -> Result<Option<Vec<Statement>>, String> {
+        if self.peek_token.t != TokenType::ELSE {
+            return Ok(None);
+        }
+
+        self.next_token(); // Move to the "else" token
+        self.next_token(); // Skip the "else" token
+        if self.current_token.t != TokenType::LBRACE {
+            return Err("Expected '{' after 'else'".to_string());
+        }
+
+        Ok(Some(self.parse_block_statement()?))
+    }
+
+    // the entry of a binary operator token in the operator table
+    fn operator(&self, tok: &Token) -> Option<OperatorInfo> {
+        match tok.t {
+            TokenType::PLUS
+            | TokenType::MINUS
+            | TokenType::STAR
+            | TokenType::SLASH
+            | TokenType::PERCENT
+            | TokenType::GT
+            | TokenType::LT
+            | TokenType::EQ
+            | TokenType::NEQ
+            | TokenType::IN
+            | TokenType::COALESCE
+            | TokenType::OPERATOR => self.operators.get(&tok.literal).copied(),
+            TokenType::PIPE if !self.lambda_parameters => self.operators.get(&tok.literal).copied(),
+            _ => None,
+        }
+    }
+
+    fn is_operator(&self, tok: &Token) -> bool {
+        self.operator(tok).is_some()
+    }
+
+    fn precedence(&self, tok: &Token) -> Precedence {
+        if let Some(operator) = self.operator(tok) {
+            return operator.precedence;
+        }
+        match tok.t {
+            TokenType::ASSIGN => Precedence::ASSIGN,
+            TokenType::PIPEARROW => Precedence::PIPE,
+            TokenType::DOTDOT | TokenType::DOTDOTEQ => Precedence::RANGE,
+            TokenType::LPAREN => Precedence::CALL,
+            TokenType::LBRACKET
+            | TokenType::DOT
+            | TokenType::QUESTION
+            | TokenType::QUESTIONDOT => Precedence::INDEX,
+            _ => Precedence::LOWEST,
+        }
+    }
+
+    // Parses an expression starting at the current token and leaves the parser on its last token
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, String> {
+        let mut left = self.parse_primary_expression()?;
+        // set once a `?.` is seen, the chain is closed off before the next non-postfix operator
+        let mut optional_chain = false;
+
+        while self.peek_token.t != TokenType::SEMICOLON
+            && precedence < self.precedence(&self.peek_token)
+        {
+            self.next_token();
+
+            let postfix = matches!(
+                self.current_token.t,
+                TokenType::LPAREN
+                    | TokenType::LBRACKET
+                    | TokenType::DOT
+                    | TokenType::QUESTION
+                    | TokenType::QUESTIONDOT
+            );
+            if optional_chain && !postfix {
+                left = Expression::OPTCHAIN(Box::new(left));
+                optional_chain = false;
+            }
+
+            left = match self.current_token.t {
+                TokenType::LPAREN => self.parse_call_expression(left)?,
+                TokenType::LBRACKET => self.parse_index_expression(left)?,
+                TokenType::DOT => self.parse_field_expression(left)?,
+                TokenType::QUESTION => Expression::PROPAGATE(Box::new(left)),
+                // `a?.b`, `f?.()` and `arr?.[0]`
+                TokenType::QUESTIONDOT => {
+                    optional_chain = true;
+                    let object = Expression::NULLSAFE(Box::new(left));
+                    match self.peek_token.t {
+                        TokenType::LPAREN | TokenType::LBRACKET => {
+                            self.next_token(); // Skip the '?.'
+                            if self.current_token.t == TokenType::LPAREN {
+                                self.parse_call_expression(object)?
+                            } else {
+                                self.parse_index_expression(object)?
+                            }
+                        }
+                        _ => self.parse_field_expression(object)?,
+                    }
+                }
+                TokenType::ASSIGN => self.parse_assign_expression(left)?,
+                TokenType::PIPEARROW => self.parse_pipe_expression(left)?,
+                TokenType::DOTDOT | TokenType::DOTDOTEQ => {
+                    self.parse_range_expression(Some(left))?
+                }
+                _ if self.is_operator(&self.current_token) => {
+                    self.parse_infix_expression(left)?
+                }
+                _ => {
+                    return Err(format!(
+                        "Unexpected token {:?} in infix position",
+                        self.current_token
+                    ));
+                }
+            };
+        }
+
+        if optional_chain {
+            left = Expression::OPTCHAIN(Box::new(left));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression, String> {
+        let operator = self.current_token.literal.clone();
+        let span = self.current_span;
+        let mut precedence = self.precedence(&self.current_token);
+        // a right-associative operator lets the right side take another of its kind
+        if self.operator(&self.current_token).is_some_and(|info| info.right) {
+            precedence = Precedence(precedence.0 - 1);
+        }
 
         self.next_token();
 
         let right = self
-            .parse_expression()
+            .parse_expression(precedence)
             .map_err(|err| format!("Error parsing right-hand side of infix expression: {}", err))?;
 
         Ok(Expression::INFEX {
             left: Box::new(left),
             operator,
             right: Box::new(right),
+            span,
         })
     }
 
-    fn parse_primary_expression(&mut self) -> Result<Expression, String> {
-        match self.current_token.t {
-            TokenType::IF => self.parse_if_expression(),
-            TokenType::FUNCTION => self.parse_function_expression(),
-            TokenType::INT => self.parse_integer_literal(),
-            TokenType::TRUE | TokenType::FALSE => self.parse_boolean_literal(),
-            TokenType::BANG | TokenType::MINUS => self.parse_prefix_expression(),
-            TokenType::LPAREN => self.parse_grouped_expression(),
-            TokenType::IDENT => self.parse_identifier_expression(),
-            _ => Err(format!(
-                "Unexpected token {:?} in primary expression",
-                self.current_token
-            )),
-        }
+    // Parses the rest of a range with the parser on its '..' or '..=', `start` is None for `..end`
+    fn parse_range_expression(&mut self, start: Option<Expression>) -> Result<Expression, String> {
+        let inclusive = self.current_token.t == TokenType::DOTDOTEQ;
+
+        // `arr[1..]` leaves the end open
+        let open_ended = matches!(
+            self.peek_token.t,
+            TokenType::RBRACKET
+                | TokenType::RPAREN
+                | TokenType::RBRACE
+                | TokenType::LBRACE
+                | TokenType::COMMA
+                | TokenType::SEMICOLON
+                | TokenType::EOF
+        );
+
+        let end = if open_ended {
+            if inclusive {
+                return Err("An inclusive range '..=' needs an end".to_string());
+            }
+            None
+        } else {
+            self.next_token(); // Skip the '..'
+            Some(Box::new(self.parse_expression(Precedence::RANGE)?))
+        };
+
+        // `step` is only a keyword right after a range
+        let step = if self.peek_token.t == TokenType::IDENT && self.peek_token.literal == "step" {
+            self.next_token(); // Move to "step"
+            self.next_token(); // Skip "step"
+            Some(Box::new(self.parse_expression(Precedence::RANGE)?))
+        } else {
+            None
+        };
+
+        Ok(Expression::RANGE {
+            start: start.map(Box::new),
+            end,
+            inclusive,
+            step,
+        })
     }
 
-    fn parse_grouped_expression(&mut self) -> Result<Expression, String> {
-        self.next_token(); // Skip '('
+    fn parse_for_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip the "for" token
 
-        let expr = self.parse_expression()?; // Parse full expression like 5 + 5
+        let pattern = self.parse_single_pattern()?;
 
-        // Now advance once to move to the next token, which should be ')'
         self.next_token();
+        if self.current_token.t != TokenType::IN {
+            return Err(format!("Expected 'in', found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the "in" token
 
-        Ok(expr)
-    }
+        let iterable = self.parse_head_expression()?;
 
-    fn parse_integer_literal(&mut self) -> Result<Expression, String> {
-        let value = self
-            .current_token
-            .literal
-            .parse()
-            .map_err(|_| format!("Invalid integer literal: {}", self.current_token.literal))?;
+        self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err("Expected '{' after a FOR iterable".to_string());
+        }
 
-        Ok(Expression::INT(value))
+        let body = self.parse_block_statement()?;
+
+        Ok(Expression::FOR {
+            pattern: Box::new(pattern),
+            iterable: Box::new(iterable),
+            body,
+        })
     }
 
-    fn parse_boolean_literal(&mut self) -> Result<Expression, String> {
-        match self.current_token.t {
-            TokenType::TRUE => Ok(Expression::BOOLEAN(true)),
-            TokenType::FALSE => Ok(Expression::BOOLEAN(false)),
-            _ => Err(format!(
-                "Unexpected token {:?} in boolean literal",
+    fn parse_field_expression(&mut self, object: Expression) -> Result<Expression, String> {
+        self.next_token(); // Skip the '.'
+
+        // `t.0` reads a tuple element
+        if self.current_token.t != TokenType::IDENT && self.current_token.t != TokenType::INT {
+            return Err(format!(
+                "Expected field name after '.', found {:?}",
                 self.current_token
-            )),
+            ));
         }
+
+        Ok(Expression::FIELD {
+            object: Box::new(object),
+            field: self.current_token.literal.clone(),
+        })
     }
 
-    fn parse_identifier_expression(&mut self) -> Result<Expression, String> {
-        if self.peek_token.t == TokenType::SEMICOLON {
-            return Ok(Expression::IDENT(self.current_token.literal.clone()));
-        } else if Parser::is_operator(&self.peek_token) {
-            return self
-                .parse_infix_expression(Expression::IDENT(self.current_token.literal.clone()));
+    // Parses `value |> function`, chains group to the left so each step feeds the next
+    fn parse_pipe_expression(&mut self, value: Expression) -> Result<Expression, String> {
+        let span = self.current_span;
+        self.next_token(); // Skip the '|>'
+
+        let function = self.parse_expression(Precedence::PIPE)?;
+
+        Ok(Expression::PIPE {
+            value: Box::new(value),
+            function: Box::new(function),
+            span,
+        })
+    }
+
+    fn parse_assign_expression(&mut self, target: Expression) -> Result<Expression, String> {
+        if !matches!(target, Expression::IDENT(_) | Expression::FIELD { .. }) {
+            return Err(format!("Invalid assignment target: {:?}", target));
+        }
+
+        self.next_token(); // Skip the '='
+
+        // assignment is right associative, `a = b = 1` sets both
+        let value = self.parse_expression(Precedence::LOWEST)?;
+
+        Ok(Expression::ASSIGN {
+            target: Box::new(target),
+            value: Box::new(value),
+        })
+    }
+
+    // Parses `set{1, 2, 3}` with the parser on the `set`
+    fn parse_set_literal(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Move to the '{'
+        if self.peek_token.t == TokenType::RBRACE {
+            self.next_token();
+            return Ok(Expression::SET(vec![]));
+        }
+
+        self.next_token(); // Skip the '{'
+        let first = self.parse_list_element()?;
+        let elements = self.finish_expression_list(vec![first], TokenType::RBRACE)?;
+
+        Ok(Expression::SET(elements))
+    }
+
+    // Parses `Name { field: value, ... }` with the parser on the name
+    fn parse_struct_literal(&mut self) -> Result<Expression, String> {
+        let name = self.current_token.literal.clone();
+        self.next_token(); // Move to the '{'
+
+        let mut fields: Vec<(String, Expression)> = vec![];
+
+        while self.peek_token.t != TokenType::RBRACE {
+            self.next_token(); // Skip the '{' or ','
+
+            if self.current_token.t != TokenType::IDENT {
+                return Err(format!(
+                    "Expected field name, found {:?}",
+                    self.current_token
+                ));
+            }
+            let field = self.current_token.literal.clone();
+
+            let value = if self.peek_token.t == TokenType::COLON {
+                self.next_token(); // Move to the ':'
+                self.next_token(); // Skip the ':'
+                self.parse_expression(Precedence::LOWEST)?
+            } else {
+                // `Point { x, y }` is shorthand for `Point { x: x, y: y }`
+                Expression::IDENT(field.clone())
+            };
+            fields.push((field, value));
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.peek_token.t != TokenType::RBRACE {
+                return Err(format!("Expected ',' or '}}', found {:?}", self.peek_token));
+            }
+        }
+
+        self.next_token(); // Move to the '}'
+
+        Ok(Expression::STRUCT { name, fields })
+    }
+
+    fn parse_call_expression(&mut self, function: Expression) -> Result<Expression, String> {
+        let span = self.current_span;
+        let (arguments, named) = self.bracketed(Self::parse_call_arguments)?;
+
+        Ok(Expression::CALL {
+            function: Box::new(function),
+            arguments,
+            named,
+            span,
+        })
+    }
+
+    // Parses the positional and named arguments of a call and leaves the parser on the ')'
+    fn parse_call_arguments(&mut self) -> Result<Arguments, String> {
+        let mut arguments = vec![];
+        let mut named = vec![];
+
+        while self.peek_token.t != TokenType::RPAREN {
+            self.next_token(); // Skip the '(' or ','
+
+            if self.current_token.t == TokenType::IDENT && self.peek_token.t == TokenType::COLON {
+                let name = self.current_token.literal.clone();
+                self.next_token(); // Move to the ':'
+                self.next_token(); // Skip the ':'
+                named.push((name, self.parse_expression(Precedence::LOWEST)?));
+            } else if !named.is_empty() {
+                return Err(format!(
+                    "Positional argument after named arguments at {}",
+                    self.current_span
+                ));
+            } else if self.current_token.t == TokenType::IDENT
+                && self.current_token.literal == "_"
+                && matches!(self.peek_token.t, TokenType::COMMA | TokenType::RPAREN)
+            {
+                arguments.push(Expression::PLACEHOLDER);
+            } else {
+                arguments.push(self.parse_list_element()?);
+            }
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.peek_token.t != TokenType::RPAREN {
+                return Err(format!("Expected ',' or ')', found {:?}", self.peek_token));
+            }
+        }
+
+        self.next_token(); // Move to the ')'
+
+        Ok((arguments, named))
+    }
+
+    // An element of an argument list or array literal, which may be spread with `...`
+    fn parse_list_element(&mut self) -> Result<Expression, String> {
+        if self.current_token.t == TokenType::ELLIPSIS {
+            self.next_token(); // Skip the '...'
+            let expr = self.parse_expression(Precedence::LOWEST)?;
+            return Ok(Expression::SPREAD(Box::new(expr)));
+        }
+
+        self.parse_expression(Precedence::LOWEST)
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression, String> {
+        self.next_token(); // Skip the '['
+
+        let index = self.bracketed(|parser| parser.parse_expression(Precedence::LOWEST))?;
+
+        self.next_token();
+        if self.current_token.t != TokenType::RBRACKET {
+            return Err(format!("Expected ']', found {:?}", self.current_token));
+        }
+
+        Ok(Expression::INDEX {
+            left: Box::new(left),
+            index: Box::new(index),
+        })
+    }
+
+    // Parses the comma separated elements after the first one of a list up to `end` and leaves the parser on it
+    fn finish_expression_list(
+        &mut self,
+        mut list: Vec<Expression>,
+        end: TokenType,
+    ) -> Result<Vec<Expression>, String> {
+        while self.peek_token.t == TokenType::COMMA {
+            self.next_token(); // Move to the ','
+            self.next_token(); // Skip the ','
+            list.push(self.parse_list_element()?);
+        }
+
+        self.next_token();
+        if self.current_token.t != end {
+            return Err(format!(
+                "Expected {:?}, found {:?}",
+                end, self.current_token
+            ));
+        }
+
+        Ok(list)
+    }
+
+    fn parse_primary_expression(&mut self) -> Result<Expression, String> {
+        match self.current_token.t {
+            TokenType::IF => self.parse_if_expression(),
+            TokenType::FUNCTION => self.parse_function_expression(),
+            TokenType::PIPE => self.parse_lambda_expression(),
+            TokenType::MATCH => self.parse_match_expression(),
+            TokenType::FOR => self.parse_for_expression(),
+            TokenType::TRY => self.parse_try_expression(),
+            TokenType::THROW => self.parse_throw_expression(),
+            TokenType::YIELD => self.parse_yield_expression(),
+            TokenType::DOTDOT | TokenType::DOTDOTEQ => self.parse_range_expression(None),
+            TokenType::INT => self.parse_integer_literal(),
+            TokenType::STRING => Ok(Expression::STRING(self.current_token.literal.clone())),
+            TokenType::ATOM => Ok(Expression::ATOM(self.current_token.literal.clone())),
+            TokenType::TEMPLATE => self.parse_interpolation(),
+            TokenType::TRUE | TokenType::FALSE => self.parse_boolean_literal(),
+            TokenType::NULL => Ok(Expression::NULL),
+            TokenType::BANG | TokenType::MINUS => self.parse_prefix_expression(),
+            TokenType::LPAREN => self.parse_grouped_expression(),
+            TokenType::LBRACKET => self.bracketed(Self::parse_array_literal),
+            TokenType::LBRACE => self.bracketed(Self::parse_map_literal),
+            TokenType::IDENT => self.parse_identifier_expression(),
+            _ => Err(format!(
+                "Unexpected token {:?} in primary expression",
+                self.current_token
+            )),
+        }
+    }
+
+    // Parses `try { } catch e { } finally { }`, the binding after `catch` is optional
+    fn parse_try_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip the "try" token
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{' after 'try', found {:?}", self.current_token));
+        }
+        let body = self.parse_block_statement()?;
+
+        let mut catch_name = None;
+        let mut catch_body = None;
+        if self.peek_token.t == TokenType::CATCH {
+            self.next_token(); // Move to the "catch" token
+            self.next_token(); // Skip the "catch" token
+            if self.current_token.t == TokenType::IDENT {
+                catch_name = Some(self.current_token.literal.clone());
+                self.next_token();
+            }
+            if self.current_token.t != TokenType::LBRACE {
+                return Err(format!("Expected '{{' after 'catch', found {:?}", self.current_token));
+            }
+            catch_body = Some(self.parse_block_statement()?);
+        }
+
+        let mut finally = None;
+        if self.peek_token.t == TokenType::FINALLY {
+            self.next_token(); // Move to the "finally" token
+            self.next_token(); // Skip the "finally" token
+            if self.current_token.t != TokenType::LBRACE {
+                return Err(format!("Expected '{{' after 'finally', found {:?}", self.current_token));
+            }
+            finally = Some(self.parse_block_statement()?);
+        }
+
+        if catch_body.is_none() && finally.is_none() {
+            return Err("Expected 'catch' or 'finally' after 'try' block".to_string());
+        }
+
+        Ok(Expression::TRY(Box::new(TryExpression {
+            body,
+            catch_name,
+            catch_body,
+            finally,
+        })))
+    }
+
+    fn parse_throw_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip the "throw" token
+
+        let value = self.parse_expression(Precedence::LOWEST)?;
+
+        Ok(Expression::THROW(Box::new(value)))
+    }
+
+    // Parses `yield value` and marks the enclosing function as a generator
+    fn parse_yield_expression(&mut self) -> Result<Expression, String> {
+        if self.yields.is_none() {
+            return Err("'yield' can only be used inside a function".to_string());
+        }
+        self.yields = Some(true);
+        self.next_token(); // Skip the "yield" token
+
+        let value = self.parse_expression(Precedence::LOWEST)?;
+
+        Ok(Expression::YIELD(Box::new(value)))
+    }
+
+    // Parses `"a ${x} b"`, starting on the TEMPLATE token and ending on the TEMPLATEEND
+    fn parse_interpolation(&mut self) -> Result<Expression, String> {
+        let mut parts = vec![];
+
+        loop {
+            if !self.current_token.literal.is_empty() {
+                parts.push(Expression::STRING(self.current_token.literal.clone()));
+            }
+            if self.current_token.t == TokenType::TEMPLATEEND {
+                break;
+            }
+
+            self.next_token(); // Skip the text before the `${`
+            // braces inside `${}` are always blocks or maps, never a struct body
+            let saved = std::mem::replace(&mut self.no_struct_literal, false);
+            let expr = self.parse_expression(Precedence::LOWEST);
+            self.no_struct_literal = saved;
+            parts.push(expr?);
+
+            self.next_token();
+            if self.current_token.t != TokenType::TEMPLATEMID
+                && self.current_token.t != TokenType::TEMPLATEEND
+            {
+                return Err(format!(
+                    "Expected '}}' to close the interpolation, found {:?}",
+                    self.current_token
+                ));
+            }
+        }
+
+        Ok(Expression::INTERPOLATION(parts))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Result<Expression, String> {
+        self.next_token(); // Skip '('
+
+        // `(+)` is the operator itself
+        if self.is_operator(&self.current_token) && self.peek_token.t == TokenType::RPAREN {
+            let operator = self.current_token.literal.clone();
+            self.next_token(); // Move to the ')'
+            return Ok(Expression::OPERATOR(operator));
+        }
+
+        // parentheses lift the struct literal restriction of `if` heads and let a default value use '|'
+        let saved = std::mem::replace(&mut self.no_struct_literal, false);
+        let saved_lambda = std::mem::replace(&mut self.lambda_parameters, false);
+        let expr = self.parse_grouped_elements();
+        self.no_struct_literal = saved;
+        self.lambda_parameters = saved_lambda;
+        let expr = expr?;
+
+        // Now advance once to move to the next token, which should be ')'
+        self.next_token();
+        if self.current_token.t != TokenType::RPAREN {
+            return Err(format!("Expected ')', found {:?}", self.current_token));
+        }
+
+        Ok(expr)
+    }
+
+    // `(5 + 5)` is a grouped expression, a comma makes it a tuple: `(a, b)` or `(x,)`
+    fn parse_grouped_elements(&mut self) -> Result<Expression, String> {
+        let first = self.parse_expression(Precedence::LOWEST)?; // Parse full expression like 5 + 5
+        if self.peek_token.t != TokenType::COMMA {
+            return Ok(first);
+        }
+
+        let mut elements = vec![first];
+        while self.peek_token.t == TokenType::COMMA {
+            self.next_token(); // Move to the ','
+            if self.peek_token.t == TokenType::RPAREN {
+                break;
+            }
+            self.next_token(); // Skip the ','
+            elements.push(self.parse_expression(Precedence::LOWEST)?);
+        }
+        Ok(Expression::TUPLE(elements))
+    }
+
+    fn parse_array_literal(&mut self) -> Result<Expression, String> {
+        if self.peek_token.t == TokenType::RBRACKET {
+            self.next_token();
+            return Ok(Expression::ARRAY(vec![]));
+        }
+
+        self.next_token(); // Skip the '['
+        let first = self.parse_list_element()?;
+
+        // `[x * x for x in xs]`
+        if self.peek_token.t == TokenType::FOR {
+            if let Expression::SPREAD(_) = first {
+                return Err("Cannot spread the element of a comprehension".to_string());
+            }
+            let clauses = self.parse_comprehension_clauses()?;
+            self.next_token();
+            if self.current_token.t != TokenType::RBRACKET {
+                return Err(format!("Expected ']', found {:?}", self.current_token));
+            }
+            return Ok(Expression::LISTCOMP {
+                element: Box::new(first),
+                clauses,
+            });
+        }
+
+        let elements = self.finish_expression_list(vec![first], TokenType::RBRACKET)?;
+
+        Ok(Expression::ARRAY(elements))
+    }
+
+    // Parses the `for x in xs` and `if cond` clauses after a comprehension's element,
+    // leaving the parser on the last token of the last clause
+    fn parse_comprehension_clauses(&mut self) -> Result<Vec<Clause>, String> {
+        let mut clauses = vec![];
+
+        loop {
+            match self.peek_token.t {
+                TokenType::FOR => {
+                    self.next_token(); // Move to the "for" token
+                    self.next_token(); // Skip the "for" token
+
+                    let pattern = self.parse_single_pattern()?;
+
+                    self.next_token();
+                    if self.current_token.t != TokenType::IN {
+                        return Err(format!("Expected 'in', found {:?}", self.current_token));
+                    }
+                    self.next_token(); // Skip the "in" token
+
+                    let iterable = self.parse_expression(Precedence::LOWEST)?;
+                    clauses.push(Clause::FOR {
+                        pattern: Box::new(pattern),
+                        iterable,
+                    });
+                }
+                TokenType::IF => {
+                    self.next_token(); // Move to the "if" token
+                    self.next_token(); // Skip the "if" token
+                    clauses.push(Clause::IF(self.parse_expression(Precedence::LOWEST)?));
+                }
+                _ => return Ok(clauses),
+            }
+        }
+    }
+
+    fn parse_map_literal(&mut self) -> Result<Expression, String> {
+        let mut pairs = vec![];
+
+        while self.peek_token.t != TokenType::RBRACE {
+            self.next_token(); // Skip the '{' or ','
+            let key = self.parse_expression(Precedence::LOWEST)?;
+
+            self.next_token();
+            if self.current_token.t != TokenType::COLON {
+                return Err(format!("Expected ':', found {:?}", self.current_token));
+            }
+
+            self.next_token(); // Skip the ':'
+            let value = self.parse_expression(Precedence::LOWEST)?;
+
+            // `{k: v for [k, v] in pairs}`
+            if pairs.is_empty() && self.peek_token.t == TokenType::FOR {
+                let clauses = self.parse_comprehension_clauses()?;
+                self.next_token();
+                if self.current_token.t != TokenType::RBRACE {
+                    return Err(format!("Expected '}}', found {:?}", self.current_token));
+                }
+                return Ok(Expression::MAPCOMP {
+                    key: Box::new(key),
+                    value: Box::new(value),
+                    clauses,
+                });
+            }
+            pairs.push((key, value));
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.peek_token.t != TokenType::RBRACE {
+                return Err(format!("Expected ',' or '}}', found {:?}", self.peek_token));
+            }
+        }
+
+        self.next_token(); // Move to the '}'
+
+        Ok(Expression::MAP(pairs))
+    }
+
+    fn parse_match_expression(&mut self) -> Result<Expression, String> {
+        let span = self.current_span;
+        self.next_token(); // Skip the "match" token
+
+        let value = self.parse_head_expression()?;
+
+        self.next_token();
+        if self.current_token.t != TokenType::LBRACE {
+            return Err(format!("Expected '{{' after match value, found {:?}", self.current_token));
+        }
+        self.next_token(); // Skip the '{'
+
+        let mut arms = vec![];
+
+        while self.current_token.t != TokenType::RBRACE {
+            if self.current_token.t == TokenType::EOF {
+                return Err("Expected '}' after match arms, found end of input".to_string());
+            }
+
+            let pattern = self.parse_pattern()?;
+
+            let guard = if self.peek_token.t == TokenType::IF {
+                self.next_token(); // Move to the "if" token
+                self.next_token(); // Skip the "if" token
+                Some(self.parse_expression(Precedence::LOWEST)?)
+            } else {
+                None
+            };
+
+            self.next_token();
+            if self.current_token.t != TokenType::FATARROW {
+                return Err(format!("Expected '=>', found {:?}", self.current_token));
+            }
+            self.next_token(); // Skip the '=>'
+
+            let body = if self.current_token.t == TokenType::LBRACE {
+                self.parse_block_statement()?
+            } else {
+                let expression = self.parse_expression(Precedence::LOWEST)?;
+                vec![Statement::Expression(ExpressionStatement { expression })]
+            };
+
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            }
+            self.next_token();
+        }
+
+        if !self.exhaustive(&arms) {
+            self.warnings.push(format!("{}: match has no catch-all arm and may fail at runtime", span));
+        }
+
+        Ok(Expression::MATCH {
+            value: Box::new(value),
+            arms,
+        })
+    }
+
+    // whether one of the arms always matches, or the arms together match every variant of an enum
+    fn exhaustive(&self, arms: &[MatchArm]) -> bool {
+        // a tuple of catch-alls only fails on a value of another shape
+        fn catch_all(pattern: &Pattern) -> bool {
+            match pattern {
+                Pattern::WILDCARD | Pattern::BINDING(_) => true,
+                Pattern::TUPLE(elements) => elements.iter().all(catch_all),
+                _ => false,
+            }
+        }
+        let mut covered: HashMap<&str, Vec<&str>> = HashMap::new();
+        for arm in arms.iter().filter(|arm| arm.guard.is_none()) {
+            let alternatives = match &arm.pattern {
+                Pattern::OR(alternatives) => alternatives.as_slice(),
+                pattern => std::slice::from_ref(pattern),
+            };
+            for pattern in alternatives {
+                match pattern {
+                    pattern if catch_all(pattern) => return true,
+                    Pattern::VARIANT {
+                        enum_name,
+                        variant,
+                        fields,
+                    } if fields.iter().all(catch_all) => {
+                        covered.entry(enum_name).or_default().push(variant);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        covered.iter().any(|(enum_name, variants)| {
+            self.enums
+                .get(*enum_name)
+                .is_some_and(|all| all.iter().all(|variant| variants.contains(&variant.as_str())))
+        })
+    }
+
+    // Parses a pattern with optional `|` alternatives and leaves the parser on its last token
+    fn parse_pattern(&mut self) -> Result<Pattern, String> {
+        let first = self.parse_single_pattern()?;
+
+        if self.peek_token.t != TokenType::PIPE {
+            return Ok(first);
+        }
+
+        let mut alternatives = vec![first];
+        while self.peek_token.t == TokenType::PIPE {
+            self.next_token(); // Move to the '|'
+            self.next_token(); // Skip the '|'
+            alternatives.push(self.parse_single_pattern()?);
+        }
+
+        Ok(Pattern::OR(alternatives))
+    }
+
+    fn parse_single_pattern(&mut self) -> Result<Pattern, String> {
+        match self.current_token.t {
+            TokenType::IDENT if self.current_token.literal == "_" => Ok(Pattern::WILDCARD),
+            TokenType::IDENT if self.peek_token.t == TokenType::DOT => self.parse_variant_pattern(),
+            // `Some(x)`, `None`, `Ok(v)` and `Err(e)` need no enum name
+            TokenType::IDENT if prelude_variant(&self.current_token.literal).is_some() => {
+                let variant = self.current_token.literal.clone();
+                let enum_name = prelude_variant(&variant).unwrap_or_default().to_string();
+                self.parse_variant_fields(enum_name, variant)
+            }
+            TokenType::IDENT => Ok(Pattern::BINDING(self.current_token.literal.clone())),
+            TokenType::LBRACKET => self.parse_array_pattern(),
+            TokenType::LBRACE => self.parse_map_pattern(),
+            TokenType::LPAREN => self.parse_tuple_pattern(),
+            TokenType::NULL => Ok(Pattern::LITERAL(Expression::NULL)),
+            TokenType::ATOM => Ok(Pattern::LITERAL(Expression::ATOM(self.current_token.literal.clone()))),
+            TokenType::INT | TokenType::MINUS | TokenType::STRING | TokenType::TRUE | TokenType::FALSE => {
+                let start = self.parse_literal_pattern()?;
+
+                if self.peek_token.t == TokenType::DOTDOT || self.peek_token.t == TokenType::DOTDOTEQ {
+                    self.next_token();
+                    let inclusive = self.current_token.t == TokenType::DOTDOTEQ;
+                    self.next_token(); // Skip the '..'
+                    let end = self.parse_literal_pattern()?;
+                    return Ok(Pattern::RANGE {
+                        start,
+                        end,
+                        inclusive,
+                    });
+                }
+
+                Ok(Pattern::LITERAL(start))
+            }
+            _ => Err(format!(
+                "Unexpected token {:?} in pattern",
+                self.current_token
+            )),
+        }
+    }
+
+    fn parse_literal_pattern(&mut self) -> Result<Expression, String> {
+        match self.current_token.t {
+            TokenType::INT => self.parse_integer_literal(),
+            TokenType::MINUS => {
+                self.next_token(); // Skip the '-'
+                match self.parse_integer_literal()? {
+                    Expression::INT(value) => Ok(Expression::INT(-value)),
+                    other => Err(format!("Expected integer after '-', found {:?}", other)),
+                }
+            }
+            TokenType::STRING => Ok(Expression::STRING(self.current_token.literal.clone())),
+            TokenType::TRUE | TokenType::FALSE => self.parse_boolean_literal(),
+            _ => Err(format!(
+                "Expected literal in pattern, found {:?}",
+                self.current_token
+            )),
+        }
+    }
+
+    fn parse_variant_pattern(&mut self) -> Result<Pattern, String> {
+        let enum_name = self.current_token.literal.clone();
+        self.next_token(); // Move to the '.'
+        self.next_token(); // Skip the '.'
+
+        if self.current_token.t != TokenType::IDENT {
+            return Err(format!(
+                "Expected variant name, found {:?}",
+                self.current_token
+            ));
+        }
+        let variant = self.current_token.literal.clone();
+
+        self.parse_variant_fields(enum_name, variant)
+    }
+
+    // Parses the optional `(a, b)` after a variant name in a pattern
+    fn parse_variant_fields(&mut self, enum_name: String, variant: String) -> Result<Pattern, String> {
+        let mut fields = vec![];
+        if self.peek_token.t == TokenType::LPAREN {
+            self.next_token(); // Move to the '('
+            while self.peek_token.t != TokenType::RPAREN {
+                self.next_token(); // Skip the '(' or ','
+                fields.push(self.parse_pattern()?);
+
+                if self.peek_token.t == TokenType::COMMA {
+                    self.next_token();
+                } else if self.peek_token.t != TokenType::RPAREN {
+                    return Err(format!("Expected ',' or ')', found {:?}", self.peek_token));
+                }
+            }
+            self.next_token(); // Move to the ')'
+        }
+
+        Ok(Pattern::VARIANT {
+            enum_name,
+            variant,
+            fields,
+        })
+    }
+
+    // An element of an array or map pattern, which may carry a default value
+    fn parse_element_pattern(&mut self) -> Result<Pattern, String> {
+        let pattern = self.parse_pattern()?;
+        self.parse_default_pattern(pattern)
+    }
+
+    fn parse_default_pattern(&mut self, pattern: Pattern) -> Result<Pattern, String> {
+        if self.peek_token.t != TokenType::ASSIGN {
+            return Ok(pattern);
+        }
+
+        self.next_token(); // Move to the '='
+        self.next_token(); // Skip the '='
+        let default = self.parse_expression(Precedence::LOWEST)?;
+
+        Ok(Pattern::DEFAULT {
+            pattern: Box::new(pattern),
+            default,
+        })
+    }
+
+    fn parse_array_pattern(&mut self) -> Result<Pattern, String> {
+        let mut before = vec![];
+        let mut rest = None;
+        let mut after = vec![];
+
+        while self.peek_token.t != TokenType::RBRACKET {
+            self.next_token(); // Skip the '[' or ','
+
+            if self.current_token.t == TokenType::DOTDOT {
+                if rest.is_some() {
+                    return Err("Only one '..' is allowed in an array pattern".to_string());
+                }
+                if self.peek_token.t == TokenType::IDENT {
+                    self.next_token();
+                    rest = Some(self.current_token.literal.clone());
+                } else {
+                    rest = Some("_".to_string());
+                }
+            } else if rest.is_some() {
+                after.push(self.parse_element_pattern()?);
+            } else {
+                before.push(self.parse_element_pattern()?);
+            }
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.peek_token.t != TokenType::RBRACKET {
+                return Err(format!("Expected ',' or ']', found {:?}", self.peek_token));
+            }
+        }
+
+        self.next_token(); // Move to the ']'
+
+        Ok(Pattern::ARRAY {
+            before,
+            rest,
+            after,
+        })
+    }
+
+    // `(q, r)` and `(x,)`, without a comma the parentheses only group: `(1 | 2)`
+    fn parse_tuple_pattern(&mut self) -> Result<Pattern, String> {
+        self.next_token(); // Skip the '('
+        let first = self.parse_pattern()?;
+
+        let mut elements = vec![];
+        if self.peek_token.t == TokenType::COMMA {
+            elements.push(first);
+            while self.peek_token.t == TokenType::COMMA {
+                self.next_token(); // Move to the ','
+                if self.peek_token.t == TokenType::RPAREN {
+                    break;
+                }
+                self.next_token(); // Skip the ','
+                elements.push(self.parse_pattern()?);
+            }
+        } else {
+            self.next_token();
+            if self.current_token.t != TokenType::RPAREN {
+                return Err(format!("Expected ')', found {:?}", self.current_token));
+            }
+            return Ok(first);
+        }
+
+        self.next_token();
+        if self.current_token.t != TokenType::RPAREN {
+            return Err(format!("Expected ',' or ')', found {:?}", self.current_token));
+        }
+        Ok(Pattern::TUPLE(elements))
+    }
+
+    fn parse_map_pattern(&mut self) -> Result<Pattern, String> {
+        let mut entries = vec![];
+
+        while self.peek_token.t != TokenType::RBRACE {
+            self.next_token(); // Skip the '{' or ','
+
+            let key = match self.current_token.t {
+                TokenType::IDENT | TokenType::STRING => self.current_token.literal.clone(),
+                _ => {
+                    return Err(format!(
+                        "Expected key in map pattern, found {:?}",
+                        self.current_token
+                    ));
+                }
+            };
+
+            let pattern = if self.peek_token.t == TokenType::COLON {
+                self.next_token(); // Move to the ':'
+                self.next_token(); // Skip the ':'
+                self.parse_element_pattern()?
+            } else if self.current_token.t == TokenType::IDENT {
+                // `{name}` is shorthand for `{name: name}`
+                self.parse_default_pattern(Pattern::BINDING(key.clone()))?
+            } else {
+                return Err(format!("Expected ':' after {:?} in map pattern", key));
+            };
+            entries.push((key, pattern));
+
+            if self.peek_token.t == TokenType::COMMA {
+                self.next_token();
+            } else if self.peek_token.t != TokenType::RBRACE {
+                return Err(format!("Expected ',' or '}}', found {:?}", self.peek_token));
+            }
+        }
+
+        self.next_token(); // Move to the '}'
+
+        Ok(Pattern::MAP(entries))
+    }
+
+    fn parse_integer_literal(&mut self) -> Result<Expression, String> {
+        let value = self
+            .current_token
+            .literal
+            .parse()
+            .map_err(|_| format!("Invalid integer literal: {}", self.current_token.literal))?;
+
+        Ok(Expression::INT(value))
+    }
+
+    fn parse_boolean_literal(&mut self) -> Result<Expression, String> {
+        match self.current_token.t {
+            TokenType::TRUE => Ok(Expression::BOOLEAN(true)),
+            TokenType::FALSE => Ok(Expression::BOOLEAN(false)),
+            _ => Err(format!(
+                "Unexpected token {:?} in boolean literal",
+                self.current_token
+            )),
+        }
+    }
+
+    fn parse_identifier_expression(&mut self) -> Result<Expression, String> {
+        if self.current_token.literal == "set" && self.peek_token.t == TokenType::LBRACE && !self.no_struct_literal {
+            return self.parse_set_literal();
+        }
+        if self.peek_token.t == TokenType::LBRACE && !self.no_struct_literal {
+            return self.parse_struct_literal();
+        }
+        return Ok(Expression::IDENT(self.current_token.literal.clone()));
+    }
+
+    fn parse_prefix_expression(&mut self) -> Result<Expression, String> {
+        let operator = self.current_token.literal.clone();
+
+        self.next_token(); // Move to the right-hand side
+        let right = self.parse_expression(Precedence::PREFIX).map_err(|err| {
+            format!(
+                "Error parsing right-hand side of prefix expression: {}",
+                err
+            )
+        })?;
+
+        Ok(Expression::PREFIX {
+            operator,
+            right: Box::new(right),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_parser(input: String) -> Parser {
+        let lexer = Lexer::new(input);
+        Parser::new(lexer)
+    }
+
+    #[test]
+    fn test_parse_let_statement() {
+        let input = "let x = 5;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Let(let_stmt) = &program.statements[0] {
+            assert_eq!(let_stmt.pattern, Pattern::BINDING("x".to_string()));
+            if let Expression::INT(value) = let_stmt.value {
+                assert_eq!(value, 5);
+            } else {
+                panic!("Expected integer literal in let statement");
+            }
+        } else {
+            panic!("Expected let statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_return_statement() {
+        let input = "ret 10;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Return(return_stmt) = &program.statements[0] {
+            if let Expression::INT(value) = return_stmt.return_value {
+                assert_eq!(value, 10);
+            } else {
+                panic!("Expected integer literal in return statement");
+            }
+        } else {
+            panic!("Expected return statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_if_expression() {
+        let input = "if x < 10 { let y = 5; } else { ret 20; }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::IF {
+                condition,
+                consequence,
+                alternative,
+            } = &expr_stmt.expression
+            {
+                if let Expression::INFEX { ref operator, .. } = **condition {
+                    assert_eq!(operator, "<");
+                } else {
+                    panic!("Expected infix expression in if condition");
+                }
+
+                assert_eq!(consequence.len(), 1);
+                if let Statement::Let(let_stmt) = &consequence[0] {
+                    assert_eq!(let_stmt.pattern, Pattern::BINDING("y".to_string()));
+                } else {
+                    panic!("Expected let statement in if consequence");
+                }
+
+                assert!(alternative.is_some());
+                let alternative = alternative.as_ref().unwrap();
+                assert_eq!(alternative.len(), 1);
+                if let Statement::Return(return_stmt) = &alternative[0] {
+                    if let Expression::INT(value) = return_stmt.return_value {
+                        assert_eq!(value, 20);
+                    } else {
+                        panic!("Expected integer literal in return statement");
+                    }
+                } else {
+                    panic!("Expected return statement in if alternative");
+                }
+            } else {
+                panic!("Expected if expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_infix_expression() {
+        let input = "5 + 10 * 2;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::INFEX { operator, .. } = &expr_stmt.expression {
+                assert_eq!(operator, "+");
+            } else {
+                panic!("Expected infix expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_boolean_literal() {
+        let input = "true;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::BOOLEAN(value) = &expr_stmt.expression {
+                assert!(*value);
+            } else {
+                panic!("Expected boolean literal");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_grouped_expression() {
+        let input = "(5 + 5) * 2;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::INFEX { operator, .. } = &expr_stmt.expression {
+                assert_eq!(operator, "*");
+            } else {
+                panic!("Expected infix expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    // test function parser
+    #[test]
+    fn test_parse_function_expression() {
+        let input = "fun myFunc(x, y) { x + y; }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::FUNCTION {
+                name,
+                parameters,
+                body,
+                ..
+            } = &expr_stmt.expression
+            {
+                assert_eq!(name.as_deref(), Some("myFunc"));
+                assert_eq!(parameters.len(), 2);
+                assert_eq!(parameters[0], Pattern::BINDING("x".to_string()));
+                assert_eq!(parameters[1], Pattern::BINDING("y".to_string()));
+                assert_eq!(body.len(), 1);
+            } else {
+                panic!("Expected function expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_anonymous_function_expression() {
+        let input = "let double = fun(x) { x * 2 };".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Let(let_stmt) = &program.statements[0] {
+            if let Expression::FUNCTION {
+                name, parameters, ..
+            } = &let_stmt.value
+            {
+                assert_eq!(*name, None);
+                assert_eq!(*parameters, vec![Pattern::BINDING("x".to_string())]);
+            } else {
+                panic!("Expected function expression");
+            }
+        } else {
+            panic!("Expected let statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_lambda_expression() {
+        let input = "map(xs, |x, y| x + y);".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::CALL { arguments, .. } = &expr_stmt.expression {
+                assert_eq!(arguments.len(), 2);
+                if let Expression::FUNCTION {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } = &arguments[1]
+                {
+                    assert_eq!(*name, None);
+                    assert_eq!(
+                        *parameters,
+                        vec![
+                            Pattern::BINDING("x".to_string()),
+                            Pattern::BINDING("y".to_string())
+                        ]
+                    );
+                    assert_eq!(body.len(), 1);
+                } else {
+                    panic!("Expected lambda as second argument");
+                }
+            } else {
+                panic!("Expected call expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_operator_precedence() {
+        let input = "10 * 2 + 5;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::INFEX { operator, left, .. } = &expr_stmt.expression {
+                assert_eq!(operator, "+");
+                assert!(matches!(**left, Expression::INFEX { .. }));
+            } else {
+                panic!("Expected infix expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_match_expression() {
+        let input = "match x { 1 | 2 => 10, [a, ..rest] if a > 0 => a, _ => 0 }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::MATCH { arms, .. } = &expr_stmt.expression {
+                assert_eq!(arms.len(), 3);
+                assert!(matches!(&arms[0].pattern, Pattern::OR(alts) if alts.len() == 2));
+                assert_eq!(
+                    arms[1].pattern,
+                    Pattern::ARRAY {
+                        before: vec![Pattern::BINDING("a".to_string())],
+                        rest: Some("rest".to_string()),
+                        after: vec![],
+                    }
+                );
+                assert!(arms[1].guard.is_some());
+                assert_eq!(arms[2].pattern, Pattern::WILDCARD);
+            } else {
+                panic!("Expected match expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_destructuring_let_statement() {
+        let input = "let [x, {name, age = 0}, y = 1] = arr;".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Let(let_stmt) = &program.statements[0] {
+            assert_eq!(
+                let_stmt.pattern,
+                Pattern::ARRAY {
+                    before: vec![
+                        Pattern::BINDING("x".to_string()),
+                        Pattern::MAP(vec![
+                            ("name".to_string(), Pattern::BINDING("name".to_string())),
+                            (
+                                "age".to_string(),
+                                Pattern::DEFAULT {
+                                    pattern: Box::new(Pattern::BINDING("age".to_string())),
+                                    default: Expression::INT(0),
+                                }
+                            ),
+                        ]),
+                        Pattern::DEFAULT {
+                            pattern: Box::new(Pattern::BINDING("y".to_string())),
+                            default: Expression::INT(1),
+                        },
+                    ],
+                    rest: None,
+                    after: vec![],
+                }
+            );
+        } else {
+            panic!("Expected let statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_default_and_rest_parameters() {
+        let input = "fun greet(name, greeting = \"hi\", ...others) { name }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::FUNCTION {
+                parameters, rest, ..
+            } = &expr_stmt.expression
+            {
+                assert_eq!(parameters.len(), 2);
+                assert_eq!(
+                    parameters[1],
+                    Pattern::DEFAULT {
+                        pattern: Box::new(Pattern::BINDING("greeting".to_string())),
+                        default: Expression::STRING("hi".to_string()),
+                    }
+                );
+                assert_eq!(rest.as_deref(), Some("others"));
+            } else {
+                panic!("Expected function expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_spread_and_named_arguments() {
+        let input = "f(1, ...args, greeting: \"yo\")".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::CALL {
+                arguments,
+                named,
+                span,
+                ..
+            } = &expr_stmt.expression
+            {
+                assert_eq!(arguments.len(), 2);
+                assert_eq!(
+                    arguments[1],
+                    Expression::SPREAD(Box::new(Expression::IDENT("args".to_string())))
+                );
+                assert_eq!(
+                    *named,
+                    vec![("greeting".to_string(), Expression::STRING("yo".to_string()))]
+                );
+                assert_eq!(*span, Span { line: 1, column: 2 });
+            } else {
+                panic!("Expected call expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_range_expressions() {
+        let input = "0..n + 1 step 2; s[..2]; arr[1..];".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 3);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::RANGE {
+                start,
+                end,
+                inclusive,
+                step,
+            } = &expr_stmt.expression
+            {
+                assert_eq!(start.as_deref(), Some(&Expression::INT(0)));
+                assert!(matches!(end.as_deref(), Some(Expression::INFEX { .. })));
+                assert!(!inclusive);
+                assert_eq!(step.as_deref(), Some(&Expression::INT(2)));
+            } else {
+                panic!("Expected range expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+
+        if let Statement::Expression(expr_stmt) = &program.statements[2] {
+            if let Expression::INDEX { index, .. } = &expr_stmt.expression {
+                assert!(matches!(**index, Expression::RANGE { end: None, .. }));
+            } else {
+                panic!("Expected index expression");
+            }
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_for_expression() {
+        let input = "for x in 0..=10 { if x in 3..5 { break; } }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(expr_stmt) = &program.statements[0] {
+            if let Expression::FOR {
+                pattern,
+                iterable,
+                body,
+            } = &expr_stmt.expression
+            {
+                assert_eq!(**pattern, Pattern::BINDING("x".to_string()));
+                assert!(matches!(**iterable, Expression::RANGE { inclusive: true, .. }));
+                assert_eq!(body.len(), 1);
+            } else {
+                panic!("Expected for expression");
+            }
         } else {
-            return Err(format!(
-                "Unexpected token {:?} in identifier expression",
-                self.current_token
-            ));
+            panic!("Expected expression statement");
         }
     }
 
-    fn parse_prefix_expression(&mut self) -> Result<Expression, String> {
-        let operator = self.current_token.literal.clone();
-
-        self.next_token(); // Move to the right-hand side
-        let right = self.parse_primary_expression().map_err(|err| {
-            format!(
-                "Error parsing right-hand side of prefix expression: {}",
-                err
-            )
-        })?;
+    #[test]
+    fn test_parse_struct_and_impl_statements() {
+        let input = "struct Point { x, y } impl Point { fun len(self) { self.x } }".to_string();
+        let mut parser = setup_parser(input);
 
-        Ok(Expression::PREFIX {
-            operator,
-            right: Box::new(right),
-        })
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        assert_eq!(
+            program.statements[0],
+            Statement::Struct(StructStatement {
+                name: "Point".to_string(),
+                fields: vec!["x".to_string(), "y".to_string()],
+                invariants: vec![],
+            })
+        );
+        if let Statement::Impl(impl_stmt) = &program.statements[1] {
+            assert_eq!(impl_stmt.name, "Point");
+            assert_eq!(impl_stmt.methods.len(), 1);
+            assert!(matches!(&impl_stmt.methods[0], Expression::FUNCTION { name: Some(name), .. } if name == "len"));
+        } else {
+            panic!("Expected impl statement");
+        }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    fn setup_parser(input: String) -> Parser {
-        let lexer = Lexer::new(input);
-        Parser::new(lexer)
+    #[test]
+    fn test_parse_struct_literals_in_bracketed_heads() {
+        for (input, expected) in [
+            ("if f(P { x: 1 }) == 1 { 1 }", "if f(P { x: 1 }) == 1 { 1 }"),
+            ("match f(P { x: 1 }) { _ => 1 }", "match f(P { x: 1 }) { _ => { 1 } }"),
+            ("for x in [P { x: 1 }] { x }", "for x in [P { x: 1 }] { x }"),
+            ("if m[P { x: 1 }] == 1 { 1 }", "if m[P { x: 1 }] == 1 { 1 }"),
+            ("if contains(set{1}, 1) { 1 }", "if contains(set{1}, 1) { 1 }"),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
     }
 
     #[test]
-    fn test_parse_let_statement() {
-        let input = "let x = 5;".to_string();
+    fn test_parse_struct_literal_field_access_and_assignment() {
+        let input = "let p = Point { x: 1, y }; p.x = p.y; if p.x == 1 { p }".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
-        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.statements.len(), 3);
 
         if let Statement::Let(let_stmt) = &program.statements[0] {
-            assert_eq!(let_stmt.name, "x");
-            if let Expression::INT(value) = let_stmt.value {
-                assert_eq!(value, 5);
+            assert_eq!(
+                let_stmt.value,
+                Expression::STRUCT {
+                    name: "Point".to_string(),
+                    fields: vec![
+                        ("x".to_string(), Expression::INT(1)),
+                        ("y".to_string(), Expression::IDENT("y".to_string())),
+                    ],
+                }
+            );
+        } else {
+            panic!("Expected let statement");
+        }
+
+        if let Statement::Expression(expr_stmt) = &program.statements[1] {
+            if let Expression::ASSIGN { target, value } = &expr_stmt.expression {
+                assert!(matches!(**target, Expression::FIELD { ref field, .. } if field == "x"));
+                assert!(matches!(**value, Expression::FIELD { ref field, .. } if field == "y"));
             } else {
-                panic!("Expected integer literal in let statement");
+                panic!("Expected assignment expression");
             }
         } else {
-            panic!("Expected let statement");
+            panic!("Expected expression statement");
+        }
+
+        // `p {` in an if head opens the block instead of a struct literal
+        if let Statement::Expression(expr_stmt) = &program.statements[2] {
+            assert!(matches!(expr_stmt.expression, Expression::IF { .. }));
+        } else {
+            panic!("Expected expression statement");
         }
     }
 
     #[test]
-    fn test_parse_return_statement() {
-        let input = "ret 10;".to_string();
+    fn test_parse_enum_statement() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Empty }".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
         assert_eq!(program.statements.len(), 1);
 
-        if let Statement::Return(return_stmt) = &program.statements[0] {
-            if let Expression::INT(value) = return_stmt.return_value {
-                assert_eq!(value, 10);
-            } else {
-                panic!("Expected integer literal in return statement");
-            }
+        if let Statement::Enum(enum_stmt) = &program.statements[0] {
+            assert_eq!(enum_stmt.name, "Shape");
+            assert_eq!(
+                enum_stmt.variants,
+                vec![
+                    EnumVariant {
+                        name: "Circle".to_string(),
+                        fields: vec!["r".to_string()],
+                    },
+                    EnumVariant {
+                        name: "Rect".to_string(),
+                        fields: vec!["w".to_string(), "h".to_string()],
+                    },
+                    EnumVariant {
+                        name: "Empty".to_string(),
+                        fields: vec![],
+                    },
+                ]
+            );
         } else {
-            panic!("Expected return statement");
+            panic!("Expected enum statement");
         }
     }
 
     #[test]
-    fn test_parse_if_expression() {
-        let input = "if x < 10 { let y = 5; } else { ret 20; }".to_string();
+    fn test_parse_if_let_with_variant_pattern() {
+        let input = "if let Shape.Rect(w, _) = s { w } else { 0 }".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
         assert_eq!(program.statements.len(), 1);
 
         if let Statement::Expression(expr_stmt) = &program.statements[0] {
-            if let Expression::IF {
-                condition,
-                consequence,
+            if let Expression::IFLET {
+                pattern,
                 alternative,
+                ..
             } = &expr_stmt.expression
             {
-                if let Expression::INFEX { ref operator, .. } = **condition {
-                    assert_eq!(operator, "<");
-                } else {
-                    panic!("Expected infix expression in if condition");
-                }
-
-                assert_eq!(consequence.len(), 1);
-                if let Statement::Let(let_stmt) = &consequence[0] {
-                    assert_eq!(let_stmt.name, "y");
-                } else {
-                    panic!("Expected let statement in if consequence");
-                }
-
-                assert!(alternative.is_some());
-                let alternative = alternative.as_ref().unwrap();
-                assert_eq!(alternative.len(), 1);
-                if let Statement::Return(return_stmt) = &alternative[0] {
-                    if let Expression::INT(value) = return_stmt.return_value {
-                        assert_eq!(value, 20);
-                    } else {
-                        panic!("Expected integer literal in return statement");
+                assert_eq!(
+                    **pattern,
+                    Pattern::VARIANT {
+                        enum_name: "Shape".to_string(),
+                        variant: "Rect".to_string(),
+                        fields: vec![Pattern::BINDING("w".to_string()), Pattern::WILDCARD],
                     }
-                } else {
-                    panic!("Expected return statement in if alternative");
-                }
+                );
+                assert!(alternative.is_some());
             } else {
-                panic!("Expected if expression");
+                panic!("Expected if let expression");
             }
         } else {
             panic!("Expected expression statement");
@@ -508,88 +2771,327 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_infix_expression() {
-        let input = "5 + 10 * 2;".to_string();
+    fn test_parse_trait_and_trait_impl() {
+        let input = "trait Show { fun show(self); fun print(self) { self.show() } }
+            impl Show for Point { fun show(self) { \"p\" } }"
+            .to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
-        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.statements.len(), 2);
 
-        if let Statement::Expression(expr_stmt) = &program.statements[0] {
-            if let Expression::INFEX { operator, .. } = &expr_stmt.expression {
-                assert_eq!(operator, "+");
-            } else {
-                panic!("Expected infix expression");
-            }
+        if let Statement::Trait(trait_stmt) = &program.statements[0] {
+            assert_eq!(trait_stmt.name, "Show");
+            assert_eq!(trait_stmt.required, vec!["show".to_string()]);
+            assert_eq!(trait_stmt.provided.len(), 1);
         } else {
-            panic!("Expected expression statement");
+            panic!("Expected trait statement");
+        }
+
+        if let Statement::Impl(impl_stmt) = &program.statements[1] {
+            assert_eq!(impl_stmt.trait_name.as_deref(), Some("Show"));
+            assert_eq!(impl_stmt.name, "Point");
+            assert_eq!(impl_stmt.methods.len(), 1);
+        } else {
+            panic!("Expected impl statement");
         }
     }
 
     #[test]
-    fn test_parse_boolean_literal() {
-        let input = "true;".to_string();
+    fn test_parse_imports_and_pub() {
+        let input = "import \"utils.em\" as utils; from \"math.em\" import sqrt, pi; pub let x = 1; pub fun f() { 1 }"
+            .to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 4);
+
+        assert_eq!(
+            program.statements[0],
+            Statement::Import(ImportStatement {
+                path: "utils.em".to_string(),
+                alias: Some("utils".to_string()),
+                names: vec![],
+            })
+        );
+        assert_eq!(
+            program.statements[1],
+            Statement::Import(ImportStatement {
+                path: "math.em".to_string(),
+                alias: None,
+                names: vec!["sqrt".to_string(), "pi".to_string()],
+            })
+        );
+
+        if let Statement::Pub(inner) = &program.statements[2] {
+            assert!(matches!(**inner, Statement::Let(_)));
+        } else {
+            panic!("Expected pub statement");
+        }
+        assert!(matches!(program.statements[3], Statement::Pub(_)));
+
+        let mut parser = setup_parser("pub 1 + 2".to_string());
+        assert!(parser.parse_statement().is_err());
+    }
+
+    #[test]
+    fn test_parse_try_catch_finally() {
+        let input = "try { throw 1 } catch e { e } finally { 2 }".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
         assert_eq!(program.statements.len(), 1);
 
-        if let Statement::Expression(expr_stmt) = &program.statements[0] {
-            if let Expression::BOOLEAN(value) = &expr_stmt.expression {
-                assert_eq!(*value, true);
-            } else {
-                panic!("Expected boolean literal");
-            }
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::TRY(try_expr),
+        }) = &program.statements[0]
+        {
+            let TryExpression {
+                body,
+                catch_name,
+                catch_body,
+                finally,
+            } = &**try_expr;
+            assert_eq!(body.len(), 1);
+            assert!(matches!(
+                &body[0],
+                Statement::Expression(ExpressionStatement {
+                    expression: Expression::THROW(_)
+                })
+            ));
+            assert_eq!(catch_name.as_deref(), Some("e"));
+            assert_eq!(catch_body.as_ref().map(|b| b.len()), Some(1));
+            assert_eq!(finally.as_ref().map(|b| b.len()), Some(1));
         } else {
-            panic!("Expected expression statement");
+            panic!("Expected try expression");
         }
+
+        let mut parser = setup_parser("try { 1 }".to_string());
+        assert!(parser.parse_statement().is_err());
     }
 
     #[test]
-    fn test_parse_grouped_expression() {
-        let input = "(5 + 5) * 2;".to_string();
+    fn test_parse_yield_marks_generator() {
+        let input = "fun gen(xs) { let f = |x| x; for x in xs { yield f(x) } }".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
         assert_eq!(program.statements.len(), 1);
 
-        if let Statement::Expression(expr_stmt) = &program.statements[0] {
-            if let Expression::INFEX { operator, .. } = &expr_stmt.expression {
-                assert_eq!(operator, "*");
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::FUNCTION { body, generator, .. },
+        }) = &program.statements[0]
+        {
+            assert!(*generator);
+            // the lambda does not yield, so it stays an ordinary function
+            assert!(matches!(
+                &body[0],
+                Statement::Let(let_stmt)
+                    if matches!(let_stmt.value, Expression::FUNCTION { generator: false, .. })
+            ));
+        } else {
+            panic!("Expected function expression");
+        }
+
+        let mut parser = setup_parser("yield 1".to_string());
+        assert!(parser.parse_statement().is_err());
+    }
+
+    #[test]
+    fn test_parse_question_mark_and_prelude_patterns() {
+        let input = "(f(x)?).y; match o { Some(v) => v, None => 0 }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::FIELD { object, field },
+        }) = &program.statements[0]
+        {
+            assert_eq!(field, "y");
+            assert!(matches!(**object, Expression::PROPAGATE(_)));
+        } else {
+            panic!("Expected field access on a propagated call");
+        }
+
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::MATCH { arms, .. },
+        }) = &program.statements[1]
+        {
+            assert_eq!(
+                arms[0].pattern,
+                Pattern::VARIANT {
+                    enum_name: "Option".to_string(),
+                    variant: "Some".to_string(),
+                    fields: vec![Pattern::BINDING("v".to_string())],
+                }
+            );
+            assert_eq!(arms[1].pattern.to_string(), "None");
+        } else {
+            panic!("Expected match expression");
+        }
+    }
+
+    #[test]
+    fn test_parse_pipe_placeholder_and_operator() {
+        let input = "data |> parse |> filter(_, ok) == x; (+); (-1)".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 3);
+
+        // `|>` binds looser than `==` and groups to the left
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::PIPE { value, function, .. },
+        }) = &program.statements[0]
+        {
+            assert!(matches!(**value, Expression::PIPE { .. }));
+            if let Expression::INFEX { left, operator, .. } = &**function {
+                assert_eq!(operator, "==");
+                assert!(matches!(
+                    &**left,
+                    Expression::CALL { arguments, .. } if arguments[0] == Expression::PLACEHOLDER
+                ));
             } else {
-                panic!("Expected infix expression");
+                panic!("Expected comparison on the right of the last pipe");
             }
         } else {
-            panic!("Expected expression statement");
+            panic!("Expected pipe expression");
+        }
+
+        assert!(matches!(
+            &program.statements[1],
+            Statement::Expression(ExpressionStatement { expression: Expression::OPERATOR(op) }) if op == "+"
+        ));
+        assert!(matches!(
+            &program.statements[2],
+            Statement::Expression(ExpressionStatement { expression: Expression::PREFIX { .. } })
+        ));
+    }
+
+    #[test]
+    fn test_parse_comprehensions() {
+        let input = "[x * x for x in 0..10 if x % 2 == 0 for y in ys]; {k: v for [k, v] in pairs}".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::LISTCOMP { element, clauses },
+        }) = &program.statements[0]
+        {
+            assert!(matches!(**element, Expression::INFEX { .. }));
+            assert_eq!(clauses.len(), 3);
+            assert!(matches!(
+                &clauses[0],
+                Clause::FOR { iterable: Expression::RANGE { .. }, .. }
+            ));
+            assert!(matches!(&clauses[1], Clause::IF(Expression::INFEX { operator, .. }) if operator == "=="));
+            assert!(matches!(&clauses[2], Clause::FOR { .. }));
+        } else {
+            panic!("Expected list comprehension");
+        }
+
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::MAPCOMP { key, clauses, .. },
+        }) = &program.statements[1]
+        {
+            assert_eq!(**key, Expression::IDENT("k".to_string()));
+            assert!(matches!(&clauses[0], Clause::FOR { pattern, .. } if pattern.to_string() == "[k, v]"));
+        } else {
+            panic!("Expected map comprehension");
         }
+
+        let mut parser = setup_parser("[...xs for x in xs]".to_string());
+        assert!(parser.parse_statement().is_err());
     }
 
-    // test function parser
     #[test]
-    fn test_parse_function_expression() {
-        let input = "fun myFunc(x, y) { x + y; }".to_string();
+    fn test_parse_optional_chaining_and_coalesce() {
+        let input = "user?.address.city ?? \"none\" == x; f?.(1); arr?.[0]; null".to_string();
         let mut parser = setup_parser(input);
 
         let program = parser.parse_program().unwrap();
-        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.statements.len(), 4);
+
+        // `??` binds looser than `==`, and the whole chain is one OPTCHAIN
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::INFEX { left, operator, right, .. },
+        }) = &program.statements[0]
+        {
+            assert_eq!(operator, "??");
+            assert!(matches!(**right, Expression::INFEX { .. }));
+            if let Expression::OPTCHAIN(chain) = &**left {
+                if let Expression::FIELD { object, field } = &**chain {
+                    assert_eq!(field, "city");
+                    assert!(matches!(
+                        &**object,
+                        Expression::FIELD { object, .. } if matches!(**object, Expression::NULLSAFE(_))
+                    ));
+                } else {
+                    panic!("Expected field access at the end of the chain");
+                }
+            } else {
+                panic!("Expected optional chain");
+            }
+        } else {
+            panic!("Expected infix expression");
+        }
 
-        if let Statement::Expression(expr_stmt) = &program.statements[0] {
-            if let Expression::FUNCTION {
-                name,
-                parameters,
-                body,
-            } = &expr_stmt.expression
+        for (i, stmt) in program.statements[1..3].iter().enumerate() {
+            if let Statement::Expression(ExpressionStatement {
+                expression: Expression::OPTCHAIN(chain),
+            }) = stmt
             {
-                assert_eq!(name, "myFunc");
-                assert_eq!(parameters.len(), 2);
-                assert_eq!(parameters[0], "x");
-                assert_eq!(parameters[1], "y");
-                assert_eq!(body.len(), 1);
+                match (i, &**chain) {
+                    (0, Expression::CALL { function, .. }) => {
+                        assert!(matches!(**function, Expression::NULLSAFE(_)))
+                    }
+                    (1, Expression::INDEX { left, .. }) => {
+                        assert!(matches!(**left, Expression::NULLSAFE(_)))
+                    }
+                    other => panic!("Unexpected chain {:?}", other),
+                }
             } else {
-                panic!("Expected function expression");
+                panic!("Expected optional chain");
             }
+        }
+
+        assert_eq!(
+            program.statements[3],
+            Statement::Expression(ExpressionStatement {
+                expression: Expression::NULL
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_string_interpolation() {
+        let input = "\"Hello, ${name}! You have ${count + 1} ${\"new${s}\"}\"".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        if let Statement::Expression(ExpressionStatement {
+            expression: Expression::INTERPOLATION(parts),
+        }) = &program.statements[0]
+        {
+            assert_eq!(parts.len(), 6);
+            assert_eq!(parts[0], Expression::STRING("Hello, ".to_string()));
+            assert_eq!(parts[1], Expression::IDENT("name".to_string()));
+            assert!(matches!(parts[3], Expression::INFEX { .. }));
+            assert_eq!(
+                parts[5],
+                Expression::INTERPOLATION(vec![
+                    Expression::STRING("new".to_string()),
+                    Expression::IDENT("s".to_string()),
+                ])
+            );
         } else {
-            panic!("Expected expression statement");
+            panic!("Expected interpolation");
         }
     }
 
@@ -611,4 +3113,201 @@ mod tests {
             panic!("Expected expression statement");
         }
     }
+
+    #[test]
+    fn test_parse_macro_statement() {
+        let input = "macro unless(cond, body) { quote(if !unquote(cond) { unquote(body) }) }".to_string();
+        let mut parser = setup_parser(input);
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        match &program.statements[0] {
+            Statement::Macro(definition) => {
+                assert_eq!(definition.name, "unless");
+                assert_eq!(definition.parameters, vec!["cond", "body"]);
+            }
+            other => panic!("Expected macro statement, found {:?}", other),
+        }
+        assert_eq!(
+            program.statements[0].to_string(),
+            "macro unless(cond, body) { quote(if !unquote(cond) { unquote(body) }) }"
+        );
+
+        let mut parser = setup_parser("macro bad([a]) { a }".to_string());
+        assert_eq!(parser.parse_program().unwrap_err(), "Macro parameters must be plain names, found `[a]`");
+    }
+
+    #[test]
+    fn test_display_expression_parentheses() {
+        for (input, expected) in [
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("-(a + b).c", "-(a + b).c"),
+            ("a?.b ?? f(x, y: 1)", "a?.b ?? f(x, y: 1)"),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_infix_declaration() {
+        let input = "infix 65 <+> (a, b) { a }
+            infix right 70 <^> (a, b) { b }
+            1 + 2 <+> 3; 1 <+> 2 <+> 3; 1 <^> 2 <^> 3;"
+            .to_string();
+        let program = setup_parser(input).parse_program().unwrap();
+        assert_eq!(program.statements.len(), 5);
+
+        assert!(matches!(
+            &program.statements[1],
+            Statement::Infix(infix) if infix.operator == "<^>" && infix.precedence == 70 && infix.right
+        ));
+        assert_eq!(program.statements[2].to_string(), "1 + (2 <+> 3)");
+        assert_eq!(program.statements[3].to_string(), "1 <+> 2 <+> 3");
+        assert_eq!(program.statements[4].to_string(), "1 <^> (2 <^> 3)");
+
+        let mut parser = setup_parser("infix 60 + (a, b) { a }".to_string());
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err, "Cannot redefine built-in operator '+'");
+
+        let mut parser = setup_parser("infix 50 >= (a, b) { a }".to_string());
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err, "Cannot redefine built-in operator '>='");
+
+        let mut parser = setup_parser("infix 90 <+> (a, b) { a }".to_string());
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err, "Operator precedence must be between 1 and 79, found 90");
+    }
+
+    #[test]
+    fn test_parse_type_annotations() {
+        for (input, expected) in [
+            ("let x: int = 5;", "let x: int = 5"),
+            ("let grid: array<array<int>> = [];", "let grid: array<array<int>> = []"),
+            ("let m: map<string, int?> = {};", "let m: map<string, int?> = {}"),
+            (
+                "fun add(a: int, b: int = 1) -> int { a + b }",
+                "fun add(a: int, b: int = 1) -> int { a + b }",
+            ),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+
+        let program = setup_parser("let name: string? = null;".to_string()).parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::Let(let_stmt) => assert_eq!(
+                let_stmt.pattern,
+                Pattern::TYPED {
+                    pattern: Box::new(Pattern::BINDING("name".to_string())),
+                    annotation: Type::Optional(Box::new(Type::String)),
+                }
+            ),
+            other => panic!("Expected let statement, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_contracts_and_invariants() {
+        for input in [
+            "fun sqrt(x) requires x >= 0 ensures result >= 0 { x }",
+            "fun pick(xs, i) -> int requires i < len(xs) requires i >= 0 { xs[i] }",
+            "struct Range { low, high } invariant self.low <= self.high",
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_parse_assert_statement() {
+        for input in ["assert len(xs) == n + 1, \"xs should have grown\"", "assert !done"] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_parse_tuples() {
+        for (input, expected) in [
+            ("(1, 2)", "(1, 2)"),
+            ("(x,)", "(x,)"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("t.0.1", "t.0.1"),
+            ("fun divmod(a, b) { ret a / b, a % b }", "fun divmod(a, b) { ret (a / b, a % b) }"),
+            ("let (q, r) = divmod(7, 2);", "let (q, r) = divmod(7, 2)"),
+            ("let ((a,), _) = t;", "let ((a,), _) = t"),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+
+        let program = setup_parser("(1, 2)".to_string()).parse_program().unwrap();
+        assert!(matches!(
+            &program.statements[0],
+            Statement::Expression(stmt) if stmt.expression == Expression::TUPLE(vec![Expression::INT(1), Expression::INT(2)])
+        ));
+    }
+
+    #[test]
+    fn test_parse_sets() {
+        for (input, expected) in [
+            ("set{1, 2, 3}", "set{1, 2, 3}"),
+            ("set{}", "set{}"),
+            ("a | b & c ^ d", "a | b & c ^ d"),
+            ("(a | b) & (c ^ d)", "(a | b) & (c ^ d)"),
+            ("a - b | c", "a - b | c"),
+            ("(a | b) - c", "(a | b) - c"),
+            ("a <= b | c == true", "a <= b | c == true"),
+            ("map(xs, |x| x | s)", "map(xs, fun (x) { x | s })"),
+            ("|x = (a | b)| x", "fun (x = ...) { x }"),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_atoms() {
+        for (input, expected) in [
+            (":ok", ":ok"),
+            ("{:ok: 1, \"a\":b}", "{:ok: 1, \"a\": b}"),
+            ("f(:error, [:a])", "f(:error, [:a])"),
+            ("let s: atom = :ok;", "let s: atom = :ok"),
+            ("match s { :ok | :done => 1, _ => 2 }", "match s { :ok | :done => { 1 }, _ => { 2 } }"),
+        ] {
+            let program = setup_parser(input.to_string()).parse_program().unwrap();
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_match_exhaustiveness_warnings() {
+        let warnings = |input: &str| {
+            let mut parser = setup_parser(input.to_string());
+            parser.parse_program().unwrap();
+            parser.warnings().to_vec()
+        };
+        assert!(warnings("match r { Ok(v) => v, Err(e) => e }").is_empty());
+        assert!(warnings("match o { None => 0, Some(_) => 1 }").is_empty());
+        assert!(warnings("enum Shape { Circle(r), Square(s), Empty } match s { Shape.Circle(r) | Shape.Square(r) => r, Shape.Empty => 0 }").is_empty());
+        assert!(warnings("match x { 1 => 1, n => n }").is_empty());
+        assert!(warnings("match (1, 2) { (a, b) => a }").is_empty());
+        assert!(warnings("match p { (0, _) => 0, (x, (_, y)) => x + y }").is_empty());
+
+        assert_eq!(
+            warnings("match r { Ok(1) => 1, Err(e) => e }"),
+            vec!["line 1, column 1: match has no catch-all arm and may fail at runtime"]
+        );
+        assert_eq!(warnings("enum Shape { Circle(r), Empty }\nmatch s { Shape.Empty => 0 }").len(), 1);
+        assert_eq!(warnings("match x { n if n > 1 => n, 1 => 1 }").len(), 1);
+        assert_eq!(warnings("match p { (0, b) => b, (a, 1) => a }").len(), 1);
+    }
 }
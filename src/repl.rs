@@ -1,6 +1,6 @@
 use std::io::{BufRead, Write};
 
-use crate::{evaluator, lexer::Lexer, object::Object, parser::Parser};
+use crate::{environment::Environment, evaluator, lexer::Lexer, macros, object::Object, parser::{self, Parser}};
 
 
 
@@ -9,6 +9,11 @@ const PROMPT: &str = "=>";
 
 // any type that implements the read trait
 pub fn start<R: BufRead, W: Write>(mut reader: R, mut writer: W, is_file: bool) {
+    // bindings live for the whole session so later lines can use earlier ones
+    let env = Environment::new();
+    let mut macros = macros::Macros::new();
+    let mut operators = parser::default_operators();
+
     loop {
         if !is_file{
             write!(writer, "{} ", PROMPT).expect("Error with the writer");
@@ -17,19 +22,45 @@ pub fn start<R: BufRead, W: Write>(mut reader: R, mut writer: W, is_file: bool)
         let _ = writer.flush();
 
         let mut written = String::new();
-        reader.read_line(&mut written).expect("Error reading input!");
+        // a file is parsed as a whole so statements can span lines
+        let read = if is_file {
+            reader.read_to_string(&mut written)
+        } else {
+            reader.read_line(&mut written)
+        }
+        .expect("Error reading input!");
+
+        // end of input
+        if read == 0 {
+            break;
+        }
 
         if written.trim().is_empty() {
             continue; // Skip empty input
         }
 
         let lexer = Lexer::new(written);
-        let mut parser = Parser::new(lexer);
+        let mut parser = Parser::with_operators(lexer, operators);
 
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
+        operators = parser.operators().clone();
+        for warning in parser.warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+        let mut program = match program {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("Error parsing statement: {}", err);
+                continue;
+            }
+        };
+        if let Err(thrown) = macros::expand(&mut program, &mut macros) {
+            writeln!(writer, "{:?}", thrown.to_string()).expect("Error writing output");
+            continue;
+        }
 
-        for stmnt in program.statements {
-            writeln!(writer, "{:?}", evaluator::eval(stmnt).to_string()).expect("Error writing output");
+        for stmnt in program.statements.iter() {
+            writeln!(writer, "{:?}", evaluator::eval(stmnt, &env).to_string()).expect("Error writing output");
         }
 
 
@@ -1,11 +1,21 @@
+use std::fmt;
 
-#[derive(PartialEq, Debug)]
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum TokenType {
     ILLEGAL,
     EOF,
 
     IDENT,
     INT,
+    STRING,
+    // `:ok`, the literal is the name without the ':'
+    ATOM,
+    // a string with `${}` in it: the text before the first `${`, between two
+    // of them, and after the last one
+    TEMPLATE,
+    TEMPLATEMID,
+    TEMPLATEEND,
     
     // operators
     ASSIGN,
@@ -14,16 +24,33 @@ pub enum TokenType {
     BANG,
     STAR,
     SLASH,
+    PERCENT,
     GT,
     LT,
+    // a run of symbol characters that is not one of the operators above, `<+>`
+    OPERATOR,
+
+    PIPE,
+    PIPEARROW,
+    FATARROW,
+    DOT,
+    DOTDOT,
+    DOTDOTEQ,
+    ELLIPSIS,
+    QUESTION,
+    QUESTIONDOT,
+    COALESCE,
 
     COMMA,
     SEMICOLON,
+    COLON,
     
     LPAREN,
     RPAREN,
     LBRACE,
     RBRACE,
+    LBRACKET,
+    RBRACKET,
 
     // cond
     EQ,
@@ -38,11 +65,50 @@ pub enum TokenType {
     IF,
     ELSE,
     RETURN,
+    MATCH,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
+    STRUCT,
+    IMPL,
+    ENUM,
+    TRAIT,
+    PUB,
+    IMPORT,
+    FROM,
+    AS,
+    TRY,
+    CATCH,
+    FINALLY,
+    THROW,
+    NULL,
+    YIELD,
+    MACRO,
+    INFIX,
+    DEFER,
+    REQUIRES,
+    ENSURES,
+    INVARIANT,
+    ASSERT,
 
 }
 
 
-#[derive(Debug)]
+// where a token starts in the source, both counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token{
     pub t: TokenType,
     pub literal: String
@@ -60,6 +126,32 @@ impl Token {
             "if"   => TokenType::IF,
             "else"   => TokenType::ELSE,
             "ret"   => TokenType::RETURN,
+            "match"   => TokenType::MATCH,
+            "for"   => TokenType::FOR,
+            "in"   => TokenType::IN,
+            "break"   => TokenType::BREAK,
+            "continue"   => TokenType::CONTINUE,
+            "struct"   => TokenType::STRUCT,
+            "impl"   => TokenType::IMPL,
+            "enum"   => TokenType::ENUM,
+            "trait"   => TokenType::TRAIT,
+            "pub"   => TokenType::PUB,
+            "import"   => TokenType::IMPORT,
+            "from"   => TokenType::FROM,
+            "as"   => TokenType::AS,
+            "try"   => TokenType::TRY,
+            "catch"   => TokenType::CATCH,
+            "finally"   => TokenType::FINALLY,
+            "throw"   => TokenType::THROW,
+            "null"   => TokenType::NULL,
+            "yield"   => TokenType::YIELD,
+            "macro"   => TokenType::MACRO,
+            "infix"   => TokenType::INFIX,
+            "defer"   => TokenType::DEFER,
+            "requires"   => TokenType::REQUIRES,
+            "ensures"   => TokenType::ENSURES,
+            "invariant"   => TokenType::INVARIANT,
+            "assert"   => TokenType::ASSERT,
             _       => TokenType::IDENT
         }
     }